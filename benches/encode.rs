@@ -0,0 +1,176 @@
+// Encoding throughput
+//
+// `edc_ecc` compares the reference byte-at-a-time EDC and scalar P/Q parity
+// with the sliced EDC and the parity backend selected for this CPU.
+// `sector` compares the allocating encoder with the in-place one on a single
+// sector. `batch` encodes a whole image the way the pipeline used to (one
+// Vec per sector, then sorted) and the way it does now (workers encoding into
+// one preallocated buffer). `pipeline` runs the full Converter on a file,
+// streaming to a sink or encoding into a memory-mapped output file.
+//
+// Images default to 64 MiB; set ISO2RAW_BENCH_MB to measure DVD-sized ones,
+// e.g. ISO2RAW_BENCH_MB=4700 cargo bench -- pipeline
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use iso2raw::converter::{
+    convert_iso_to_raw, encode_mode1_into, SectorFormat, ISO_SECTOR_SIZE, RAW_SECTOR_SIZE,
+};
+use iso2raw::edc_ecc::{
+    calc_edc, calc_edc_bytewise, calc_p_parity, calc_p_parity_scalar, calc_q_parity,
+    calc_q_parity_scalar, parity_backend,
+};
+use iso2raw::pipeline::{Converter, Output};
+use rayon::prelude::*;
+use std::hint::black_box;
+
+fn image_sectors() -> usize {
+    let megabytes: usize = std::env::var("ISO2RAW_BENCH_MB")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(64);
+    megabytes * 1024 * 1024 / ISO_SECTOR_SIZE
+}
+
+fn test_iso(sectors: usize) -> Vec<u8> {
+    (0..sectors * ISO_SECTOR_SIZE)
+        .map(|i| (i % 251) as u8)
+        .collect()
+}
+
+fn bench_edc_ecc(c: &mut Criterion) {
+    let mut sector = convert_iso_to_raw(1000, &test_iso(1)).unwrap();
+
+    let mut group = c.benchmark_group("edc_ecc");
+    group.throughput(Throughput::Bytes(RAW_SECTOR_SIZE as u64));
+
+    group.bench_function("edc/bytewise", |b| {
+        b.iter(|| calc_edc_bytewise(black_box(&sector[..2064])))
+    });
+    group.bench_function("edc/sliced", |b| {
+        b.iter(|| calc_edc(black_box(&sector[..2064])))
+    });
+    group.bench_function("parity/scalar", |b| {
+        b.iter(|| {
+            calc_p_parity_scalar(black_box(&mut sector));
+            calc_q_parity_scalar(black_box(&mut sector));
+        })
+    });
+    group.bench_function(format!("parity/{:?}", parity_backend()), |b| {
+        b.iter(|| {
+            calc_p_parity(black_box(&mut sector));
+            calc_q_parity(black_box(&mut sector));
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_sector(c: &mut Criterion) {
+    let data = test_iso(1);
+    let mut raw = vec![0u8; RAW_SECTOR_SIZE];
+
+    let mut group = c.benchmark_group("sector");
+    group.throughput(Throughput::Bytes(ISO_SECTOR_SIZE as u64));
+
+    group.bench_function("allocating", |b| {
+        b.iter(|| convert_iso_to_raw(black_box(1000), black_box(&data)).unwrap())
+    });
+    group.bench_function("in_place", |b| {
+        b.iter(|| encode_mode1_into(black_box(1000), black_box(&data), &mut raw).unwrap())
+    });
+
+    group.finish();
+}
+
+fn bench_batch(c: &mut Criterion) {
+    let sectors = image_sectors();
+    let iso = test_iso(sectors);
+    let batch_sectors = 64 * num_cpus::get().min(8);
+    let mut buffer = vec![0u8; batch_sectors * RAW_SECTOR_SIZE];
+
+    let mut group = c.benchmark_group("batch");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(iso.len() as u64));
+
+    group.bench_function(BenchmarkId::new("allocating", sectors), |b| {
+        b.iter(|| {
+            for batch in iso.chunks(batch_sectors * ISO_SECTOR_SIZE) {
+                let first = (batch.as_ptr() as usize - iso.as_ptr() as usize) / ISO_SECTOR_SIZE;
+                let sectors: Vec<(usize, Vec<u8>)> = batch
+                    .chunks(ISO_SECTOR_SIZE)
+                    .enumerate()
+                    .map(|(offset, data)| (first + offset, data.to_vec()))
+                    .collect();
+
+                let mut results: Vec<(usize, Vec<u8>)> = sectors
+                    .into_par_iter()
+                    .map(|(lba, data)| (lba, convert_iso_to_raw(lba as u32, &data).unwrap()))
+                    .collect();
+                results.sort_by_key(|(lba, _)| *lba);
+                black_box(results);
+            }
+        })
+    });
+
+    group.bench_function(BenchmarkId::new("in_place", sectors), |b| {
+        b.iter(|| {
+            for (index, batch) in iso.chunks(batch_sectors * ISO_SECTOR_SIZE).enumerate() {
+                let first = index * batch_sectors;
+                let output = &mut buffer[..batch.len() / ISO_SECTOR_SIZE * RAW_SECTOR_SIZE];
+                output
+                    .par_chunks_mut(RAW_SECTOR_SIZE)
+                    .zip(batch.par_chunks(ISO_SECTOR_SIZE))
+                    .enumerate()
+                    .for_each(|(offset, (raw, data))| {
+                        SectorFormat::Mode1
+                            .encode_into((first + offset) as u32, data, raw)
+                            .unwrap()
+                    });
+                black_box(&output);
+            }
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_pipeline(c: &mut Criterion) {
+    let sectors = image_sectors();
+    let path = std::env::temp_dir().join(format!("iso2raw-bench-{}.iso", std::process::id()));
+    std::fs::write(&path, test_iso(sectors)).unwrap();
+
+    let mut group = c.benchmark_group("pipeline");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes((sectors * ISO_SECTOR_SIZE) as u64));
+
+    group.bench_function(BenchmarkId::new("convert", sectors), |b| {
+        b.iter(|| {
+            Converter::new(&path, Output::writer(std::io::sink()))
+                .run()
+                .unwrap()
+        })
+    });
+
+    let bin_path = path.with_extension("bin");
+    group.bench_function(BenchmarkId::new("convert_mmap", sectors), |b| {
+        b.iter(|| {
+            Converter::new(&path, &bin_path)
+                .mmap_output(true)
+                .run()
+                .unwrap()
+        })
+    });
+
+    group.finish();
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(&bin_path);
+}
+
+criterion_group!(
+    benches,
+    bench_edc_ecc,
+    bench_sector,
+    bench_batch,
+    bench_pipeline
+);
+criterion_main!(benches);
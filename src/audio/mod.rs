@@ -0,0 +1,310 @@
+use anyhow::{bail, Result};
+
+use crate::converter::RAW_SECTOR_SIZE;
+
+pub const CD_SAMPLE_RATE: u32 = 44_100;
+pub const CD_CHANNELS: u16 = 2;
+pub const CD_BITS_PER_SAMPLE: u16 = 16;
+
+pub const CD_BLOCK_ALIGN: u16 = CD_CHANNELS * CD_BITS_PER_SAMPLE / 8;
+pub const CD_BYTE_RATE: u32 = CD_SAMPLE_RATE * CD_BLOCK_ALIGN as u32;
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// KSDATAFORMAT_SUBTYPE_PCM, the SubFormat of extensible PCM files
+const SUBTYPE_PCM: [u8; 16] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+/// Sample byte order of CD-DA sectors in the output image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteOrder {
+    /// Intel order, as stored in WAV files and expected by most tools
+    #[default]
+    Little,
+    /// Motorola order, expected by some burners
+    Big,
+}
+
+/// The `fmt ` chunk of a WAVE file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavFormat {
+    pub format_tag: u16,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub byte_rate: u32,
+    pub block_align: u16,
+    pub bits_per_sample: u16,
+    /// SubFormat GUID of `WAVE_FORMAT_EXTENSIBLE` files
+    pub sub_format: Option<[u8; 16]>,
+}
+
+/// A parsed WAVE file borrowing its sample data.
+#[derive(Debug)]
+pub struct WavFile<'a> {
+    pub format: WavFormat,
+    pub data: &'a [u8],
+}
+
+/// Parses a RIFF/WAVE file and checks that it holds CD-DA audio:
+/// uncompressed PCM, 44.1 kHz, 16-bit, stereo. A data chunk that runs past
+/// the end of the file is an error rather than silently shortened.
+pub fn parse_wav(bytes: &[u8]) -> Result<WavFile<'_>> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        bail!("Not a RIFF/WAVE file");
+    }
+
+    let mut format = None;
+    let mut data = None;
+
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = read_u32(bytes, offset + 4) as usize;
+        let body = offset + 8;
+        let end = (body + size).min(bytes.len());
+
+        match id {
+            b"fmt " => {
+                if end - body < 16 {
+                    bail!("WAVE fmt chunk is too short");
+                }
+                let format_tag = read_u16(bytes, body);
+                let sub_format = if format_tag == WAVE_FORMAT_EXTENSIBLE {
+                    if end - body < 40 {
+                        bail!("WAVE_FORMAT_EXTENSIBLE fmt chunk is too short");
+                    }
+                    bytes[body + 24..body + 40].try_into().ok()
+                } else {
+                    None
+                };
+                format = Some(WavFormat {
+                    format_tag,
+                    channels: read_u16(bytes, body + 2),
+                    sample_rate: read_u32(bytes, body + 4),
+                    byte_rate: read_u32(bytes, body + 8),
+                    block_align: read_u16(bytes, body + 12),
+                    bits_per_sample: read_u16(bytes, body + 14),
+                    sub_format,
+                });
+            }
+            b"data" => {
+                if body + size > bytes.len() {
+                    bail!(
+                        "WAVE data chunk is truncated: {size} bytes declared, {} present",
+                        bytes.len() - body
+                    );
+                }
+                data = Some(&bytes[body..end]);
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even size
+        offset = body + size + (size & 1);
+    }
+
+    let Some(format) = format else {
+        bail!("WAVE file has no fmt chunk");
+    };
+    let Some(data) = data else {
+        bail!("WAVE file has no data chunk");
+    };
+
+    match (format.format_tag, format.sub_format) {
+        (WAVE_FORMAT_PCM, _) | (WAVE_FORMAT_EXTENSIBLE, Some(SUBTYPE_PCM)) => {}
+        (WAVE_FORMAT_EXTENSIBLE, _) => {
+            bail!(
+                "Unsupported WAVE_FORMAT_EXTENSIBLE sub-format: only uncompressed PCM is supported"
+            )
+        }
+        (format_tag, _) => bail!(
+            "Unsupported WAVE encoding 0x{format_tag:04X}: only uncompressed PCM is supported"
+        ),
+    }
+
+    if format.sample_rate != CD_SAMPLE_RATE
+        || format.channels != CD_CHANNELS
+        || format.bits_per_sample != CD_BITS_PER_SAMPLE
+    {
+        bail!(
+            "Unsupported WAVE format: {} Hz, {}-bit, {} channels (CD audio needs {} Hz, {}-bit, {} channels)",
+            format.sample_rate,
+            format.bits_per_sample,
+            format.channels,
+            CD_SAMPLE_RATE,
+            CD_BITS_PER_SAMPLE,
+            CD_CHANNELS
+        );
+    }
+
+    if format.block_align != CD_BLOCK_ALIGN || format.byte_rate != CD_BYTE_RATE {
+        bail!(
+            "Inconsistent WAVE format: block align {} and {} bytes/s (CD audio needs {} and {})",
+            format.block_align,
+            format.byte_rate,
+            CD_BLOCK_ALIGN,
+            CD_BYTE_RATE
+        );
+    }
+
+    Ok(WavFile { format, data })
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Number of 2352-byte audio sectors needed to hold `pcm_len` bytes.
+pub fn audio_sector_count(pcm_len: usize) -> usize {
+    pcm_len.div_ceil(RAW_SECTOR_SIZE)
+}
+
+/// Splits little-endian PCM data into 2352-byte CD-DA sectors in the given
+/// byte order, padding the final partial sector with digital silence.
+pub fn audio_sectors(
+    pcm: &[u8],
+    byte_order: ByteOrder,
+) -> impl Iterator<Item = [u8; RAW_SECTOR_SIZE]> + '_ {
+    pcm.chunks(RAW_SECTOR_SIZE).map(move |chunk| {
+        let mut sector = [0u8; RAW_SECTOR_SIZE];
+        sector[..chunk.len()].copy_from_slice(chunk);
+
+        if byte_order == ByteOrder::Big {
+            for sample in sector.chunks_exact_mut(2) {
+                sample.swap(0, 1);
+            }
+        }
+
+        sector
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav_file(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+        for (id, body) in chunks {
+            bytes.extend_from_slice(*id);
+            bytes.extend_from_slice(&(body.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(body);
+            if body.len() % 2 == 1 {
+                bytes.push(0);
+            }
+        }
+        bytes
+    }
+
+    fn fmt_chunk(channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut chunk = Vec::new();
+        chunk.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+        chunk.extend_from_slice(&channels.to_le_bytes());
+        chunk.extend_from_slice(&sample_rate.to_le_bytes());
+        chunk.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        chunk.extend_from_slice(&block_align.to_le_bytes());
+        chunk.extend_from_slice(&bits.to_le_bytes());
+        chunk
+    }
+
+    #[test]
+    fn test_parse_wav() {
+        let fmt = fmt_chunk(2, 44_100, 16);
+        let bytes = wav_file(&[(b"fmt ", &fmt), (b"LIST", b"odd"), (b"data", &[1, 2, 3, 4])]);
+
+        let wav = parse_wav(&bytes).unwrap();
+        assert_eq!(wav.format.sample_rate, 44_100);
+        assert_eq!(wav.data, &[1, 2, 3, 4]);
+
+        assert!(parse_wav(b"RIFF\0\0\0\0AVI ").is_err());
+        assert!(parse_wav(&wav_file(&[(b"fmt ", &fmt)])).is_err());
+        assert!(parse_wav(&wav_file(&[(b"data", &[0; 4])])).is_err());
+    }
+
+    #[test]
+    fn test_parse_wav_rejects_non_cd_format() {
+        for fmt in [
+            fmt_chunk(2, 48_000, 16),
+            fmt_chunk(1, 44_100, 16),
+            fmt_chunk(2, 44_100, 24),
+        ] {
+            let bytes = wav_file(&[(b"fmt ", &fmt), (b"data", &[0; 4])]);
+            assert!(parse_wav(&bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_wav_rejects_truncated_data() {
+        let fmt = fmt_chunk(2, 44_100, 16);
+        let mut bytes = wav_file(&[(b"fmt ", &fmt), (b"data", &[0; 8])]);
+        bytes.truncate(bytes.len() - 4);
+
+        let error = parse_wav(&bytes).unwrap_err().to_string();
+        assert!(error.contains("8 bytes declared, 4 present"), "{error}");
+    }
+
+    #[test]
+    fn test_parse_wav_rejects_inconsistent_format() {
+        let mut fmt = fmt_chunk(2, 44_100, 16);
+        fmt[12] = 2; // block align
+        let bytes = wav_file(&[(b"fmt ", &fmt), (b"data", &[0; 4])]);
+        assert!(parse_wav(&bytes).is_err());
+
+        let mut fmt = fmt_chunk(2, 44_100, 16);
+        fmt[8..12].copy_from_slice(&88_200u32.to_le_bytes());
+        let bytes = wav_file(&[(b"fmt ", &fmt), (b"data", &[0; 4])]);
+        assert!(parse_wav(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_parse_wav_extensible() {
+        let extensible = |sub_format: [u8; 16]| {
+            let mut fmt = fmt_chunk(2, 44_100, 16);
+            fmt[0..2].copy_from_slice(&WAVE_FORMAT_EXTENSIBLE.to_le_bytes());
+            fmt.extend_from_slice(&22u16.to_le_bytes()); // cbSize
+            fmt.extend_from_slice(&16u16.to_le_bytes()); // valid bits
+            fmt.extend_from_slice(&3u32.to_le_bytes()); // front left and right
+            fmt.extend_from_slice(&sub_format);
+            wav_file(&[(b"fmt ", &fmt), (b"data", &[0; 4])])
+        };
+
+        assert!(parse_wav(&extensible(SUBTYPE_PCM)).is_ok());
+
+        // KSDATAFORMAT_SUBTYPE_IEEE_FLOAT
+        let mut float = SUBTYPE_PCM;
+        float[0] = 0x03;
+        let error = parse_wav(&extensible(float)).unwrap_err().to_string();
+        assert!(error.contains("sub-format"), "{error}");
+    }
+
+    #[test]
+    fn test_audio_sectors_pad_final_sector() {
+        let pcm = vec![0x11u8; RAW_SECTOR_SIZE + 4];
+        let sectors: Vec<_> = audio_sectors(&pcm, ByteOrder::Little).collect();
+
+        assert_eq!(audio_sector_count(pcm.len()), 2);
+        assert_eq!(sectors.len(), 2);
+        assert_eq!(&sectors[1][..4], &[0x11; 4]);
+        assert!(sectors[1][4..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_audio_sectors_big_endian() {
+        let pcm = [0x01, 0x02, 0x03, 0x04];
+        let sector = audio_sectors(&pcm, ByteOrder::Big).next().unwrap();
+
+        assert_eq!(&sector[..4], &[0x02, 0x01, 0x04, 0x03]);
+    }
+}
@@ -0,0 +1,161 @@
+// Input layout detection
+//
+// Users regularly point the converter at an image that is already RAW. The
+// first sectors are sniffed for the sync pattern followed by consecutive MSF
+// header addresses, which identifies 2352-byte images and 2448-byte images
+// with interleaved subchannel data regardless of the file size.
+
+use super::{
+    SectorAddress, ISO_SECTOR_SIZE, MODE2_PAYLOAD_SIZE, RAW_SECTOR_SIZE, SUBHEADER_SIZE,
+    SYNC_PATTERN,
+};
+use crate::subchannel::RAW_SECTOR_WITH_SUBCHANNEL_SIZE;
+
+const SNIFF_SECTORS: usize = 4;
+const PVD_IDENTIFIER_OFFSET: usize = 16 * ISO_SECTOR_SIZE + 1;
+
+/// Sector layout of an input image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputLayout {
+    /// 2048-byte user data sectors
+    Iso,
+    /// 2336-byte Mode 2 payloads (subheader, user data and EDC)
+    Mode2Payload,
+    /// 2352-byte raw sectors
+    Raw,
+    /// 2448-byte raw sectors followed by 96 bytes of subchannel data
+    RawWithSubchannel,
+}
+
+impl InputLayout {
+    pub fn sector_size(&self) -> usize {
+        match self {
+            Self::Iso => ISO_SECTOR_SIZE,
+            Self::Mode2Payload => MODE2_PAYLOAD_SIZE,
+            Self::Raw => RAW_SECTOR_SIZE,
+            Self::RawWithSubchannel => RAW_SECTOR_WITH_SUBCHANNEL_SIZE,
+        }
+    }
+
+    /// Whether sectors already carry sync, header and EDC/ECC.
+    pub fn is_raw(&self) -> bool {
+        matches!(self, Self::Raw | Self::RawWithSubchannel)
+    }
+}
+
+/// Detects the layout of an image from its first sectors. Anything not
+/// recognized as RAW or as Mode 2 payloads is assumed to be an ISO.
+pub fn detect_layout(image: &[u8]) -> InputLayout {
+    for layout in [InputLayout::Raw, InputLayout::RawWithSubchannel] {
+        if has_raw_sectors(image, layout.sector_size()) {
+            return layout;
+        }
+    }
+
+    let has_pvd = image.get(PVD_IDENTIFIER_OFFSET..PVD_IDENTIFIER_OFFSET + 5) == Some(b"CD001");
+    if !has_pvd && has_mode2_payloads(image) {
+        return InputLayout::Mode2Payload;
+    }
+
+    InputLayout::Iso
+}
+
+/// Checks that the first sectors start with the sync pattern and carry
+/// consecutive header addresses.
+fn has_raw_sectors(image: &[u8], sector_size: usize) -> bool {
+    let sectors = image.len() / sector_size;
+
+    // A lone sector could be either raw size, so the file must fit exactly
+    if sectors == 0 || (sectors == 1 && image.len() != sector_size) {
+        return false;
+    }
+
+    let mut first_frame = None;
+    for (index, sector) in image
+        .chunks_exact(sector_size)
+        .take(SNIFF_SECTORS)
+        .enumerate()
+    {
+        if sector[0..12] != SYNC_PATTERN {
+            return false;
+        }
+
+        let Some(address) = SectorAddress::from_bcd(&sector[12..15]) else {
+            return false;
+        };
+
+        let frame = address.to_frames();
+        if *first_frame.get_or_insert(frame) + index as u32 != frame {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Checks for a file made of 2336-byte sectors whose first sectors carry a
+/// duplicated XA subheader.
+fn has_mode2_payloads(image: &[u8]) -> bool {
+    if image.is_empty()
+        || !image.len().is_multiple_of(MODE2_PAYLOAD_SIZE)
+        || image.len().is_multiple_of(ISO_SECTOR_SIZE)
+    {
+        return false;
+    }
+
+    let half = SUBHEADER_SIZE / 2;
+    image
+        .chunks_exact(MODE2_PAYLOAD_SIZE)
+        .take(SNIFF_SECTORS)
+        .all(|payload| payload[..half] == payload[half..SUBHEADER_SIZE])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::{convert_iso_to_raw, convert_to_mode2_form2, Subheader};
+    use crate::subchannel::SUBCHANNEL_SIZE;
+
+    fn raw_image(start_lba: u32, sectors: u32, subchannel: bool) -> Vec<u8> {
+        let mut image = Vec::new();
+        for lba in start_lba..start_lba + sectors {
+            image.extend(convert_iso_to_raw(lba, &[0u8; ISO_SECTOR_SIZE]).unwrap());
+            if subchannel {
+                image.extend([0u8; SUBCHANNEL_SIZE]);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_detect_raw_layouts() {
+        assert_eq!(detect_layout(&raw_image(0, 8, false)), InputLayout::Raw);
+        assert_eq!(
+            detect_layout(&raw_image(4500, 8, true)),
+            InputLayout::RawWithSubchannel
+        );
+
+        // 2048 sectors of 2352 bytes are also a multiple of 2048
+        let image = raw_image(0, 2048, false);
+        assert!(image.len().is_multiple_of(ISO_SECTOR_SIZE));
+        assert_eq!(detect_layout(&image), InputLayout::Raw);
+    }
+
+    #[test]
+    fn test_detect_cooked_layouts() {
+        assert_eq!(
+            detect_layout(&[0u8; ISO_SECTOR_SIZE * 20]),
+            InputLayout::Iso
+        );
+        assert_eq!(detect_layout(&[]), InputLayout::Iso);
+
+        // Broken address sequence: not raw
+        let mut image = raw_image(0, 4, false);
+        image[RAW_SECTOR_SIZE + 14] = 0x99;
+        assert_eq!(detect_layout(&image), InputLayout::Iso);
+
+        let sector = convert_to_mode2_form2(0, &[1u8; 2324], Subheader::default(), true).unwrap();
+        let payloads = sector[16..].repeat(3);
+        assert_eq!(detect_layout(&payloads), InputLayout::Mode2Payload);
+    }
+}
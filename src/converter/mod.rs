@@ -1,8 +1,23 @@
+#[cfg(feature = "std")]
+mod detect;
+#[cfg(feature = "std")]
+mod reader;
+
+#[cfg(feature = "std")]
+pub use detect::*;
+#[cfg(feature = "std")]
+pub use reader::*;
+
 use crate::edc_ecc;
+use alloc::{vec, vec::Vec};
 use anyhow::{bail, Result};
+use core::ops::Range;
 
 pub const ISO_SECTOR_SIZE: usize = 2048;
 pub const RAW_SECTOR_SIZE: usize = 2352;
+pub const SUBHEADER_SIZE: usize = 8;
+pub const MODE2_FORM2_DATA_SIZE: usize = 2324;
+pub const MODE2_PAYLOAD_SIZE: usize = 2336; // subheader + Form 2 data + EDC
 
 pub const SYNC_PATTERN: [u8; 12] = [
     0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00,
@@ -17,11 +32,15 @@ pub struct SectorAddress {
 
 impl SectorAddress {
     pub fn from_lba(lba: u32) -> Self {
-        let lba_offset = lba + 150; // CD-ROM addresses start at 2 seconds (150 frames)
+        Self::from_frames(lba + 150) // CD-ROM addresses start at 2 seconds (150 frames)
+    }
 
-        let frame = (lba_offset % 75) as u8;
-        let second = ((lba_offset / 75) % 60) as u8;
-        let minute = ((lba_offset / 75) / 60) as u8;
+    /// Address of a frame count without the 2-second offset, as used for
+    /// track-relative times.
+    pub fn from_frames(frames: u32) -> Self {
+        let frame = (frames % 75) as u8;
+        let second = ((frames / 75) % 60) as u8;
+        let minute = ((frames / 75) / 60) as u8;
 
         Self {
             minute,
@@ -30,6 +49,27 @@ impl SectorAddress {
         }
     }
 
+    /// Decodes a BCD `mm:ss:ff` header address, rejecting invalid digits
+    /// and out-of-range seconds or frames.
+    pub fn from_bcd(bcd: &[u8]) -> Option<Self> {
+        let [minute, second, frame] = [bcd.first()?, bcd.get(1)?, bcd.get(2)?].map(|&byte| {
+            (byte >> 4 <= 9 && byte & 0x0F <= 9).then_some((byte >> 4) * 10 + (byte & 0x0F))
+        });
+
+        let address = Self {
+            minute: minute?,
+            second: second?,
+            frame: frame?,
+        };
+
+        (address.second < 60 && address.frame < 75).then_some(address)
+    }
+
+    /// Number of frames since 00:00:00, including the 2-second offset.
+    pub fn to_frames(&self) -> u32 {
+        (self.minute as u32 * 60 + self.second as u32) * 75 + self.frame as u32
+    }
+
     pub fn to_bcd(&self) -> [u8; 3] {
         [
             Self::to_bcd_byte(self.minute),
@@ -43,97 +83,265 @@ impl SectorAddress {
     }
 }
 
-pub struct Mode1Sector {
-    pub sync: [u8; 12],
-    pub header: [u8; 4],
-    pub user_data: [u8; 2048],
-    pub edc: [u8; 4],
-    pub zero: [u8; 8],
-    pub ecc_p: [u8; 172],
-    pub ecc_q: [u8; 104],
+/// CD-ROM XA subheader, stored twice at the start of every Mode 2 sector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Subheader {
+    pub file: u8,
+    pub channel: u8,
+    pub submode: u8,
+    pub coding_info: u8,
 }
 
-impl Mode1Sector {
-    pub fn new(lba: u32, data: &[u8]) -> Result<Self> {
-        if data.len() != ISO_SECTOR_SIZE {
-            bail!(
-                "Invalid ISO sector size: expected {}, got {}",
-                ISO_SECTOR_SIZE,
-                data.len()
-            );
+impl Subheader {
+    pub const SUBMODE_EOR: u8 = 0x01;
+    pub const SUBMODE_VIDEO: u8 = 0x02;
+    pub const SUBMODE_AUDIO: u8 = 0x04;
+    pub const SUBMODE_DATA: u8 = 0x08;
+    pub const SUBMODE_TRIGGER: u8 = 0x10;
+    pub const SUBMODE_FORM2: u8 = 0x20;
+    pub const SUBMODE_REALTIME: u8 = 0x40;
+    pub const SUBMODE_EOF: u8 = 0x80;
+
+    /// Plain Form 1 data sector subheader.
+    pub fn data() -> Self {
+        Self {
+            submode: Self::SUBMODE_DATA,
+            ..Self::default()
         }
+    }
 
-        let address = SectorAddress::from_lba(lba);
-        let bcd_address = address.to_bcd();
-
-        let mut sector = Self {
-            sync: SYNC_PATTERN,
-            header: [bcd_address[0], bcd_address[1], bcd_address[2], 0x01], // Mode 1
-            user_data: [0; 2048],
-            edc: [0; 4],
-            zero: [0; 8],
-            ecc_p: [0; 172],
-            ecc_q: [0; 104],
-        };
+    pub fn to_bytes(&self) -> [u8; SUBHEADER_SIZE] {
+        let bytes = [self.file, self.channel, self.submode, self.coding_info];
+        [
+            bytes[0], bytes[1], bytes[2], bytes[3], bytes[0], bytes[1], bytes[2], bytes[3],
+        ]
+    }
+}
 
-        sector.user_data.copy_from_slice(data);
+/// Sector layout used when encoding input sectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectorFormat {
+    Mode1,
+    Mode2Form1(Subheader),
+    /// Form 2 sectors from 2324-byte user data or 2336-byte Mode 2 payloads.
+    /// With `edc` unset the EDC field is left zeroed, which the XA spec allows.
+    Mode2Form2 {
+        subheader: Subheader,
+        edc: bool,
+    },
+}
+
+impl SectorFormat {
+    pub fn encode(&self, lba: u32, input_data: &[u8]) -> Result<Vec<u8>> {
+        let mut raw_data = vec![0u8; RAW_SECTOR_SIZE];
+        self.encode_into(lba, input_data, &mut raw_data)?;
+        Ok(raw_data)
+    }
 
-        Ok(sector)
+    /// Encodes a sector into a 2352-byte slice of a larger output buffer.
+    pub fn encode_into(&self, lba: u32, input_data: &[u8], raw: &mut [u8]) -> Result<()> {
+        match self {
+            Self::Mode1 => encode_mode1_into(lba, input_data, raw),
+            Self::Mode2Form1(subheader) => {
+                encode_mode2_form1_into(lba, input_data, *subheader, raw)
+            }
+            Self::Mode2Form2 { subheader, edc } => {
+                encode_mode2_form2_into(lba, input_data, *subheader, *edc, raw)
+            }
+        }
     }
+}
 
-    pub fn calculate_edc_ecc(&mut self) {
-        // Prepare complete sector for EDC/ECC calculation
-        let mut sector = vec![0u8; RAW_SECTOR_SIZE];
-        self.to_bytes(&mut sector);
+/// Sector type identified from the sync pattern, mode byte and XA subheader
+/// of a 2352-byte raw sector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SectorKind {
+    Mode0,
+    Mode1,
+    Mode2Form1,
+    Mode2Form2,
+    /// Mode 2 without an XA subheader (2336 bytes of user data)
+    Mode2Formless,
+    /// Missing sync pattern or unknown mode byte
+    Unknown,
+}
 
-        // Use vendored EDCRE implementation (thanks @github/alex-free!)
-        edc_ecc::calc_mode1_edc(&mut sector);
-        edc_ecc::calc_p_parity(&mut sector);
-        edc_ecc::calc_q_parity(&mut sector);
+impl SectorKind {
+    pub fn detect(raw: &[u8]) -> Self {
+        if raw.len() < RAW_SECTOR_SIZE || raw[0..12] != SYNC_PATTERN {
+            return Self::Unknown;
+        }
 
-        // Extract calculated values back to struct
-        self.edc.copy_from_slice(&sector[2064..2068]);
-        self.ecc_p.copy_from_slice(&sector[2076..2248]);
-        self.ecc_q.copy_from_slice(&sector[2248..2352]);
+        match raw[15] {
+            0x00 => Self::Mode0,
+            0x01 => Self::Mode1,
+            0x02 => {
+                // XA sectors carry the subheader twice
+                if raw[16..20] != raw[20..24] {
+                    Self::Mode2Formless
+                } else if raw[18] & Subheader::SUBMODE_FORM2 != 0 {
+                    Self::Mode2Form2
+                } else {
+                    Self::Mode2Form1
+                }
+            }
+            _ => Self::Unknown,
+        }
     }
 
-    pub fn to_bytes(&self, buffer: &mut [u8]) {
-        if buffer.len() < RAW_SECTOR_SIZE {
-            return;
+    /// Byte range of the user data within a raw sector, if the sector has any.
+    pub fn user_data_range(&self) -> Option<Range<usize>> {
+        match self {
+            Self::Mode1 => Some(16..16 + ISO_SECTOR_SIZE),
+            Self::Mode2Form1 => Some(24..24 + ISO_SECTOR_SIZE),
+            Self::Mode2Form2 => Some(24..24 + MODE2_FORM2_DATA_SIZE),
+            Self::Mode2Formless => Some(16..16 + MODE2_PAYLOAD_SIZE),
+            Self::Mode0 | Self::Unknown => None,
         }
+    }
+}
+
+/// Returns the detected sector kind and its user data, or `None` for sectors
+/// that carry no user data (Mode 0, unrecognized).
+pub fn extract_user_data(raw: &[u8]) -> Option<(SectorKind, &[u8])> {
+    let kind = SectorKind::detect(raw);
+    kind.user_data_range().map(|range| (kind, &raw[range]))
+}
+
+pub fn convert_iso_to_raw(lba: u32, iso_data: &[u8]) -> Result<Vec<u8>> {
+    SectorFormat::Mode1.encode(lba, iso_data)
+}
 
-        let mut offset = 0;
+pub fn convert_iso_to_mode2_form1(
+    lba: u32,
+    iso_data: &[u8],
+    subheader: Subheader,
+) -> Result<Vec<u8>> {
+    SectorFormat::Mode2Form1(subheader).encode(lba, iso_data)
+}
+
+/// Encodes a Form 2 sector from either 2324 bytes of user data (using
+/// `subheader`) or a 2336-byte Mode 2 payload (using its own subheader).
+pub fn convert_to_mode2_form2(
+    lba: u32,
+    data: &[u8],
+    subheader: Subheader,
+    with_edc: bool,
+) -> Result<Vec<u8>> {
+    SectorFormat::Mode2Form2 {
+        subheader,
+        edc: with_edc,
+    }
+    .encode(lba, data)
+}
 
-        buffer[offset..offset + 12].copy_from_slice(&self.sync);
-        offset += 12;
+// In-place encoders
+//
+// These write a complete sector straight into `raw`, which must be exactly
+// 2352 bytes, so callers can encode into disjoint slices of one preallocated
+// buffer without any per-sector allocation. Every byte of `raw` is written.
 
-        buffer[offset..offset + 4].copy_from_slice(&self.header);
-        offset += 4;
+/// Encodes 2048 bytes of user data as a Mode 1 sector.
+pub fn encode_mode1_into(lba: u32, iso_data: &[u8], raw: &mut [u8]) -> Result<()> {
+    check_sizes("ISO sector", iso_data, ISO_SECTOR_SIZE, raw)?;
 
-        buffer[offset..offset + 2048].copy_from_slice(&self.user_data);
-        offset += 2048;
+    write_sync_and_header(lba, 0x01, raw);
+    raw[16..2064].copy_from_slice(iso_data);
+    edc_ecc::calc_mode1_edc(raw);
+    raw[2068..2076].fill(0);
+    edc_ecc::calc_p_parity(raw);
+    edc_ecc::calc_q_parity(raw);
 
-        buffer[offset..offset + 4].copy_from_slice(&self.edc);
-        offset += 4;
+    Ok(())
+}
+
+/// Encodes 2048 bytes of user data as a Mode 2 Form 1 sector.
+pub fn encode_mode2_form1_into(
+    lba: u32,
+    iso_data: &[u8],
+    subheader: Subheader,
+    raw: &mut [u8],
+) -> Result<()> {
+    check_sizes("ISO sector", iso_data, ISO_SECTOR_SIZE, raw)?;
+
+    raw[0..12].copy_from_slice(&SYNC_PATTERN);
+    raw[16..24].copy_from_slice(&subheader.to_bytes());
+    raw[24..2072].copy_from_slice(iso_data);
+    edc_ecc::calc_mode2_form1_edc(raw);
+
+    // P/Q parity is computed with the header zeroed, then the header is filled in
+    raw[12..16].fill(0);
+    edc_ecc::calc_p_parity(raw);
+    edc_ecc::calc_q_parity(raw);
+    write_sync_and_header(lba, 0x02, raw);
+
+    Ok(())
+}
 
-        buffer[offset..offset + 8].copy_from_slice(&self.zero);
-        offset += 8;
+/// Encodes a Form 2 sector from either 2324 bytes of user data (using
+/// `subheader`) or a 2336-byte Mode 2 payload (using its own subheader).
+/// Either way the Form 2 bit is set in the submode, otherwise drives would
+/// decode the sector as Form 1.
+pub fn encode_mode2_form2_into(
+    lba: u32,
+    data: &[u8],
+    subheader: Subheader,
+    with_edc: bool,
+    raw: &mut [u8],
+) -> Result<()> {
+    let user_data = if data.len() == MODE2_PAYLOAD_SIZE {
+        check_sizes("Mode 2 payload", data, MODE2_PAYLOAD_SIZE, raw)?;
+        raw[16..24].copy_from_slice(&data[..SUBHEADER_SIZE]);
+        raw[18] |= Subheader::SUBMODE_FORM2;
+        raw[22] |= Subheader::SUBMODE_FORM2;
+        &data[SUBHEADER_SIZE..SUBHEADER_SIZE + MODE2_FORM2_DATA_SIZE]
+    } else {
+        check_sizes("Form 2 sector", data, MODE2_FORM2_DATA_SIZE, raw)?;
+        let subheader = Subheader {
+            submode: subheader.submode | Subheader::SUBMODE_FORM2,
+            ..subheader
+        };
+        raw[16..24].copy_from_slice(&subheader.to_bytes());
+        data
+    };
 
-        buffer[offset..offset + 172].copy_from_slice(&self.ecc_p);
-        offset += 172;
+    write_sync_and_header(lba, 0x02, raw);
+    raw[24..2348].copy_from_slice(user_data);
 
-        buffer[offset..offset + 104].copy_from_slice(&self.ecc_q);
+    if with_edc {
+        edc_ecc::calc_mode2_form2_edc(raw);
+    } else {
+        raw[2348..2352].fill(0);
     }
+
+    Ok(())
 }
 
-pub fn convert_iso_to_raw(lba: u32, iso_data: &[u8]) -> Result<Vec<u8>> {
-    let mut sector = Mode1Sector::new(lba, iso_data)?;
-    sector.calculate_edc_ecc();
+fn write_sync_and_header(lba: u32, mode: u8, raw: &mut [u8]) {
+    raw[0..12].copy_from_slice(&SYNC_PATTERN);
+    raw[12..15].copy_from_slice(&SectorAddress::from_lba(lba).to_bcd());
+    raw[15] = mode;
+}
+
+fn check_sizes(what: &str, data: &[u8], expected: usize, raw: &[u8]) -> Result<()> {
+    if data.len() != expected {
+        bail!(
+            "Invalid {} size: expected {}, got {}",
+            what,
+            expected,
+            data.len()
+        );
+    }
 
-    let mut raw_data = vec![0u8; RAW_SECTOR_SIZE];
-    sector.to_bytes(&mut raw_data);
+    if raw.len() != RAW_SECTOR_SIZE {
+        bail!(
+            "Invalid RAW sector buffer: expected {}, got {}",
+            RAW_SECTOR_SIZE,
+            raw.len()
+        );
+    }
 
-    Ok(raw_data)
+    Ok(())
 }
 
 #[cfg(test)]
@@ -152,11 +360,131 @@ mod tests {
     }
 
     #[test]
-    fn test_mode1_sector_creation() {
+    fn test_mode1_sector_layout() {
         let data = vec![0u8; ISO_SECTOR_SIZE];
-        let sector = Mode1Sector::new(0, &data).unwrap();
+        let raw = convert_iso_to_raw(0, &data).unwrap();
+
+        assert_eq!(&raw[0..12], &SYNC_PATTERN);
+        assert_eq!(&raw[12..16], &[0x00, 0x02, 0x00, 0x01]); // Mode 1
+        assert_eq!(&raw[2068..2076], &[0; 8]);
+    }
+
+    #[test]
+    fn test_mode2_form1_sector_layout() {
+        let data = vec![0x5Au8; ISO_SECTOR_SIZE];
+        let subheader = Subheader {
+            file: 1,
+            channel: 2,
+            submode: Subheader::SUBMODE_DATA | Subheader::SUBMODE_EOF,
+            coding_info: 0,
+        };
+        let raw = convert_iso_to_mode2_form1(0, &data, subheader).unwrap();
+
+        assert_eq!(&raw[0..12], &SYNC_PATTERN);
+        assert_eq!(&raw[12..16], &[0x00, 0x02, 0x00, 0x02]); // MSF restored, Mode 2
+        assert_eq!(&raw[16..24], &[1, 2, 0x88, 0, 1, 2, 0x88, 0]);
+        assert_eq!(&raw[24..2072], &data[..]);
+
+        let edc = edc_ecc::calc_edc(&raw[16..2072]);
+        assert_eq!(&raw[2072..2076], &edc.to_le_bytes());
+
+        // Parity must match a sector whose header was zeroed
+        let mut expected = raw.clone();
+        expected[12..16].fill(0);
+        edc_ecc::calc_p_parity(&mut expected);
+        edc_ecc::calc_q_parity(&mut expected);
+        assert_eq!(&raw[2076..], &expected[2076..]);
+    }
+
+    #[test]
+    fn test_mode2_form2_sector_layout() {
+        let data = vec![0x33u8; MODE2_FORM2_DATA_SIZE];
+        let raw = convert_to_mode2_form2(16, &data, Subheader::default(), true).unwrap();
+
+        assert_eq!(raw[15], 0x02);
+        assert_eq!(raw[18], Subheader::SUBMODE_FORM2);
+        assert_eq!(&raw[24..2348], &data[..]);
+
+        let edc = edc_ecc::calc_edc(&raw[16..2348]);
+        assert_eq!(&raw[2348..2352], &edc.to_le_bytes());
+
+        // A 2336-byte payload keeps its own subheader, and EDC can be zeroed
+        let mut payload = raw[16..2352].to_vec();
+        payload[2] = 0x64;
+        let raw = convert_to_mode2_form2(16, &payload, Subheader::default(), false).unwrap();
+        assert_eq!(&raw[16..24], &payload[0..8]);
+        assert_eq!(&raw[2348..2352], &[0, 0, 0, 0]);
+
+        // ...with the Form 2 bit set if the payload lacks it
+        payload[2] = Subheader::SUBMODE_DATA;
+        payload[6] = Subheader::SUBMODE_DATA;
+        let raw = convert_to_mode2_form2(16, &payload, Subheader::default(), true).unwrap();
+        assert_eq!(raw[18], Subheader::SUBMODE_DATA | Subheader::SUBMODE_FORM2);
+        assert_eq!(raw[22], Subheader::SUBMODE_DATA | Subheader::SUBMODE_FORM2);
+        assert_eq!(SectorKind::detect(&raw), SectorKind::Mode2Form2);
+    }
+
+    #[test]
+    fn test_extract_user_data_round_trip() {
+        let data = vec![0x77u8; ISO_SECTOR_SIZE];
+
+        let raw = convert_iso_to_raw(0, &data).unwrap();
+        assert_eq!(
+            extract_user_data(&raw),
+            Some((SectorKind::Mode1, &data[..]))
+        );
+
+        let raw = convert_iso_to_mode2_form1(0, &data, Subheader::data()).unwrap();
+        assert_eq!(
+            extract_user_data(&raw),
+            Some((SectorKind::Mode2Form1, &data[..]))
+        );
+
+        let form2 = vec![0x11u8; MODE2_FORM2_DATA_SIZE];
+        let raw = convert_to_mode2_form2(0, &form2, Subheader::default(), true).unwrap();
+        assert_eq!(
+            extract_user_data(&raw),
+            Some((SectorKind::Mode2Form2, &form2[..]))
+        );
+
+        let mut raw = raw;
+        raw[20] ^= 0xFF; // subheader copies disagree
+        assert_eq!(SectorKind::detect(&raw), SectorKind::Mode2Formless);
+
+        assert_eq!(extract_user_data(&[0u8; RAW_SECTOR_SIZE]), None);
+    }
+
+    #[test]
+    fn test_encode_into_overwrites_buffer() {
+        let data: Vec<u8> = (0..MODE2_PAYLOAD_SIZE).map(|i| (i % 251) as u8).collect();
+        let iso_data = &data[..ISO_SECTOR_SIZE];
+        let subheader = Subheader {
+            file: 1,
+            channel: 3,
+            submode: Subheader::SUBMODE_DATA,
+            coding_info: 0,
+        };
+
+        // A dirty buffer must end up as a freshly allocated sector
+        let formats = [
+            (SectorFormat::Mode1, iso_data),
+            (SectorFormat::Mode2Form1(subheader), iso_data),
+            (
+                SectorFormat::Mode2Form2 {
+                    subheader,
+                    edc: false,
+                },
+                &data[..],
+            ),
+        ];
+        for (format, input) in formats {
+            let mut raw = [0xEEu8; RAW_SECTOR_SIZE];
+            format.encode_into(4500, input, &mut raw).unwrap();
+            assert_eq!(raw[..], format.encode(4500, input).unwrap()[..]);
+        }
 
-        assert_eq!(sector.sync, SYNC_PATTERN);
-        assert_eq!(sector.header[3], 0x01); // Mode 1
+        let mut raw = [0u8; RAW_SECTOR_SIZE];
+        assert!(encode_mode1_into(0, iso_data, &mut raw[..2048]).is_err());
+        assert!(encode_mode1_into(0, &data, &mut raw).is_err());
     }
 }
@@ -0,0 +1,197 @@
+// Lazy 2352-byte view of an ISO
+//
+// Iso2RawReader presents any seekable ISO source as the Mode 1 RAW image it
+// would convert to, without writing that image anywhere. Sectors are encoded
+// on demand, so reading or seeking to byte N only touches the sectors around
+// it; the most recently encoded sector is kept for sequential reads, in a
+// buffer that is reused for every sector.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use super::{encode_mode1_into, ISO_SECTOR_SIZE, RAW_SECTOR_SIZE};
+
+pub struct Iso2RawReader<R> {
+    inner: R,
+    total_sectors: u64,
+    position: u64,
+    cached: Option<u64>,
+    sector: Box<[u8; RAW_SECTOR_SIZE]>,
+}
+
+impl<R: Read + Seek> Iso2RawReader<R> {
+    /// Wraps an ISO made of whole 2048-byte sectors.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let iso_len = inner.seek(SeekFrom::End(0))?;
+        if iso_len % ISO_SECTOR_SIZE as u64 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("ISO size {iso_len} is not a multiple of {ISO_SECTOR_SIZE}"),
+            ));
+        }
+
+        Ok(Self {
+            inner,
+            total_sectors: iso_len / ISO_SECTOR_SIZE as u64,
+            position: 0,
+            cached: None,
+            sector: Box::new([0; RAW_SECTOR_SIZE]),
+        })
+    }
+
+    pub fn total_sectors(&self) -> u64 {
+        self.total_sectors
+    }
+
+    /// Length of the RAW view in bytes.
+    pub fn len(&self) -> u64 {
+        self.total_sectors * RAW_SECTOR_SIZE as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total_sectors == 0
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Returns the encoded sector at `index`, encoding it if it is not the
+    /// one already cached.
+    fn encoded_sector(&mut self, index: u64) -> io::Result<&[u8]> {
+        if self.cached != Some(index) {
+            self.cached = None;
+
+            let mut data = [0u8; ISO_SECTOR_SIZE];
+            self.inner
+                .seek(SeekFrom::Start(index * ISO_SECTOR_SIZE as u64))?;
+            self.inner.read_exact(&mut data)?;
+
+            encode_mode1_into(index as u32, &data, &mut self.sector[..])
+                .map_err(io::Error::other)?;
+            self.cached = Some(index);
+        }
+
+        Ok(&self.sector[..])
+    }
+}
+
+impl<R: Read + Seek> Read for Iso2RawReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.len() {
+            return Ok(0);
+        }
+
+        let index = self.position / RAW_SECTOR_SIZE as u64;
+        let offset = (self.position % RAW_SECTOR_SIZE as u64) as usize;
+
+        let sector = self.encoded_sector(index)?;
+        let count = buf.len().min(RAW_SECTOR_SIZE - offset);
+        buf[..count].copy_from_slice(&sector[offset..offset + count]);
+
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: Read + Seek> Seek for Iso2RawReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(position) => (position, 0),
+            SeekFrom::End(offset) => (self.len(), offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+
+        self.position = base.checked_add_signed(offset).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::convert_iso_to_raw;
+    use std::io::Cursor;
+
+    /// Counts the bytes read from the wrapped ISO.
+    struct CountingReader {
+        inner: Cursor<Vec<u8>>,
+        bytes_read: usize,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = self.inner.read(buf)?;
+            self.bytes_read += count;
+            Ok(count)
+        }
+    }
+
+    impl Seek for CountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    fn test_iso(sectors: usize) -> Vec<u8> {
+        (0..sectors * ISO_SECTOR_SIZE)
+            .map(|i| (i % 239) as u8)
+            .collect()
+    }
+
+    fn expected_raw(iso: &[u8]) -> Vec<u8> {
+        iso.chunks(ISO_SECTOR_SIZE)
+            .enumerate()
+            .flat_map(|(lba, data)| convert_iso_to_raw(lba as u32, data).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_reads_full_raw_view() {
+        let iso = test_iso(5);
+        let mut reader = Iso2RawReader::new(Cursor::new(iso.clone())).unwrap();
+        assert_eq!(reader.len(), 5 * RAW_SECTOR_SIZE as u64);
+
+        let mut raw = Vec::new();
+        reader.read_to_end(&mut raw).unwrap();
+        assert_eq!(raw, expected_raw(&iso));
+    }
+
+    #[test]
+    fn test_seek_encodes_only_touched_sectors() {
+        let iso = test_iso(100);
+        let expected = expected_raw(&iso);
+        let counting = CountingReader {
+            inner: Cursor::new(iso),
+            bytes_read: 0,
+        };
+        let mut reader = Iso2RawReader::new(counting).unwrap();
+
+        // A read spanning the boundary of sectors 41 and 42
+        let start = 42 * RAW_SECTOR_SIZE as u64 - 10;
+        assert_eq!(reader.seek(SeekFrom::Start(start)).unwrap(), start);
+
+        let mut buf = [0u8; 20];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &expected[start as usize..start as usize + 20]);
+        assert_eq!(reader.into_inner().bytes_read, 2 * ISO_SECTOR_SIZE);
+    }
+
+    #[test]
+    fn test_seek_bounds() {
+        let mut reader = Iso2RawReader::new(Cursor::new(test_iso(2))).unwrap();
+
+        assert_eq!(reader.seek(SeekFrom::End(-4)).unwrap(), reader.len() - 4);
+        let mut buf = [0u8; 16];
+        assert_eq!(reader.read(&mut buf).unwrap(), 4);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+        assert!(reader.seek(SeekFrom::Current(-1_000_000)).is_err());
+        assert!(Iso2RawReader::new(Cursor::new(vec![0u8; 100])).is_err());
+    }
+}
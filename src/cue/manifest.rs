@@ -0,0 +1,240 @@
+// Cue-like manifest describing the tracks of a multi-track image:
+//
+//   FILE "game.iso" ISO
+//     TRACK 01 MODE1/2352
+//   FILE "track02.wav" WAVE
+//     TRACK 02 AUDIO
+//   FILE "track03.pcm" BINARY
+//     TRACK 03 AUDIO
+//     PREGAP 00:03:00
+//
+// ISO files feed data tracks, WAVE files and raw little-endian PCM (BINARY)
+// feed audio tracks. Relative paths are resolved against the manifest's
+// directory. Every track after the first gets a 2-second pregap unless a
+// PREGAP line says otherwise.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{TrackMode, FRAMES_PER_SECOND};
+
+pub const DEFAULT_PREGAP: u32 = 2 * FRAMES_PER_SECOND;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    Iso,
+    Wave,
+    /// Raw 16-bit little-endian stereo PCM
+    Binary,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestTrack {
+    pub number: u8,
+    pub mode: TrackMode,
+    pub source: PathBuf,
+    pub source_kind: SourceKind,
+    /// Pregap length in sectors, written to the image as INDEX 00
+    pub pregap: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub tracks: Vec<ManifestTrack>,
+}
+
+impl Manifest {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read manifest: {}", path.display()))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+        Self::parse(&text, base_dir)
+            .with_context(|| format!("Invalid manifest: {}", path.display()))
+    }
+
+    pub fn parse(text: &str, base_dir: &Path) -> Result<Self> {
+        let mut tracks: Vec<ManifestTrack> = Vec::new();
+        let mut file: Option<(PathBuf, SourceKind)> = None;
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("REM") {
+                continue;
+            }
+
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+
+            match keyword.to_ascii_uppercase().as_str() {
+                "FILE" => {
+                    let (name, kind) = parse_file(rest)
+                        .with_context(|| format!("line {}: bad FILE entry", line_number + 1))?;
+                    file = Some((base_dir.join(name), kind));
+                }
+                "TRACK" => {
+                    let Some((source, source_kind)) = file.take() else {
+                        bail!("line {}: TRACK without a preceding FILE", line_number + 1);
+                    };
+                    let (number, mode) = parse_track(rest)
+                        .with_context(|| format!("line {}: bad TRACK entry", line_number + 1))?;
+
+                    let expected = tracks.len() + 1;
+                    if number as usize != expected {
+                        bail!("line {}: expected track {expected:02}", line_number + 1);
+                    }
+
+                    match (source_kind, mode) {
+                        (SourceKind::Iso, TrackMode::Mode1 | TrackMode::Mode2) => {}
+                        (SourceKind::Wave | SourceKind::Binary, TrackMode::Audio) => {}
+                        _ => bail!(
+                            "line {}: {:?} files cannot feed {} tracks",
+                            line_number + 1,
+                            source_kind,
+                            mode.as_str()
+                        ),
+                    }
+
+                    tracks.push(ManifestTrack {
+                        number,
+                        mode,
+                        source,
+                        source_kind,
+                        pregap: if tracks.is_empty() { 0 } else { DEFAULT_PREGAP },
+                    });
+                }
+                "PREGAP" => {
+                    let Some(track) = tracks.last_mut() else {
+                        bail!("line {}: PREGAP before any TRACK", line_number + 1);
+                    };
+                    track.pregap = parse_msf(rest)
+                        .with_context(|| format!("line {}: bad PREGAP", line_number + 1))?;
+                }
+                other => bail!("line {}: unknown keyword {other}", line_number + 1),
+            }
+        }
+
+        if tracks.is_empty() {
+            bail!("no tracks defined");
+        }
+
+        Ok(Self { tracks })
+    }
+}
+
+fn parse_file(rest: &str) -> Result<(&str, SourceKind)> {
+    let (name, kind) = split_file_entry(rest)?;
+
+    let kind = match kind.to_ascii_uppercase().as_str() {
+        "ISO" => SourceKind::Iso,
+        "WAVE" => SourceKind::Wave,
+        "BINARY" => SourceKind::Binary,
+        other => bail!("unsupported file type {other}"),
+    };
+
+    Ok((name, kind))
+}
+
+/// Splits the rest of a `FILE` line into the file name, quoted or not, and
+/// its type.
+pub(super) fn split_file_entry(rest: &str) -> Result<(&str, &str)> {
+    let (name, kind) = match rest.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"').context("unterminated file name")?,
+        None => rest
+            .rsplit_once(char::is_whitespace)
+            .context("missing file type")?,
+    };
+
+    Ok((name, kind.trim()))
+}
+
+pub(super) fn parse_track(rest: &str) -> Result<(u8, TrackMode)> {
+    let (number, mode) = rest
+        .split_once(char::is_whitespace)
+        .context("missing track mode")?;
+    let number: u8 = number.parse().context("invalid track number")?;
+
+    let mode = match mode.trim().to_ascii_uppercase().as_str() {
+        "AUDIO" => TrackMode::Audio,
+        "MODE1" | "MODE1/2352" => TrackMode::Mode1,
+        "MODE2" | "MODE2/2352" => TrackMode::Mode2,
+        other => bail!("unsupported track mode {other}"),
+    };
+
+    Ok((number, mode))
+}
+
+/// Parses a `mm:ss:ff` timestamp into a sector count.
+pub fn parse_msf(value: &str) -> Result<u32> {
+    let parts: Vec<&str> = value.trim().split(':').collect();
+    let [minutes, seconds, frames] = parts.as_slice() else {
+        bail!("expected mm:ss:ff, got {value}");
+    };
+
+    let minutes: u32 = minutes.parse().context("invalid minutes")?;
+    let seconds: u32 = seconds.parse().context("invalid seconds")?;
+    let frames: u32 = frames.parse().context("invalid frames")?;
+
+    let sectors = minutes
+        .checked_mul(60)
+        .and_then(|total| total.checked_add(seconds))
+        .and_then(|total| total.checked_mul(FRAMES_PER_SECOND))
+        .and_then(|total| total.checked_add(frames));
+    match sectors {
+        Some(sectors) if seconds < 60 && frames < FRAMES_PER_SECOND => Ok(sectors),
+        _ => bail!("timestamp out of range: {value}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_msf() {
+        assert_eq!(parse_msf("00:02:00").unwrap(), 150);
+        assert_eq!(parse_msf(" 79:59:74").unwrap(), 359_999);
+        assert!(parse_msf("00:60:00").is_err());
+        assert!(parse_msf("00:00:75").is_err());
+        assert!(parse_msf("99999999:00:00").is_err());
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let text = r#"
+            # Mixed-mode disc
+            FILE "game data.iso" ISO
+              TRACK 01 MODE1/2352
+            FILE "track02.wav" WAVE
+              TRACK 02 AUDIO
+            FILE track03.pcm BINARY
+              TRACK 03 AUDIO
+              PREGAP 00:03:10
+        "#;
+
+        let manifest = Manifest::parse(text, Path::new("/discs")).unwrap();
+        assert_eq!(manifest.tracks.len(), 3);
+
+        let track = &manifest.tracks[0];
+        assert_eq!(track.source, Path::new("/discs/game data.iso"));
+        assert_eq!(track.source_kind, SourceKind::Iso);
+        assert_eq!(track.mode, TrackMode::Mode1);
+        assert_eq!(track.pregap, 0);
+
+        assert_eq!(manifest.tracks[1].pregap, DEFAULT_PREGAP);
+        assert_eq!(manifest.tracks[2].source, Path::new("/discs/track03.pcm"));
+        assert_eq!(manifest.tracks[2].pregap, 3 * 75 + 10);
+    }
+
+    #[test]
+    fn test_reject_invalid_manifest() {
+        let base = Path::new("");
+
+        assert!(Manifest::parse("TRACK 01 AUDIO", base).is_err());
+        assert!(Manifest::parse("FILE \"a.wav\" WAVE\nTRACK 01 MODE1", base).is_err());
+        assert!(Manifest::parse("FILE \"a.iso\" ISO\nTRACK 02 MODE1", base).is_err());
+        assert!(Manifest::parse("", base).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+mod manifest;
+
+pub use manifest::*;
+
+use manifest::{parse_track, split_file_entry};
+
+use anyhow::{bail, Context, Result};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::audio::ByteOrder;
+use crate::converter::{SectorFormat, Subheader};
+
+pub const FRAMES_PER_SECOND: u32 = 75;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackMode {
+    Audio,
+    Mode1,
+    Mode2,
+}
+
+impl TrackMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Audio => "AUDIO",
+            Self::Mode1 => "MODE1/2352",
+            Self::Mode2 => "MODE2/2352",
+        }
+    }
+
+    /// Sector format used to encode data tracks of this mode.
+    pub fn sector_format(&self) -> Option<SectorFormat> {
+        match self {
+            Self::Audio => None,
+            Self::Mode1 => Some(SectorFormat::Mode1),
+            Self::Mode2 => Some(SectorFormat::Mode2Form1(Subheader::data())),
+        }
+    }
+}
+
+impl From<SectorFormat> for TrackMode {
+    fn from(format: SectorFormat) -> Self {
+        match format {
+            SectorFormat::Mode1 => Self::Mode1,
+            SectorFormat::Mode2Form1(_) | SectorFormat::Mode2Form2 { .. } => Self::Mode2,
+        }
+    }
+}
+
+/// Type of the BIN file named by the `FILE` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileType {
+    /// Audio samples in little-endian (Intel) order
+    #[default]
+    Binary,
+    /// Audio samples in big-endian (Motorola) order
+    Motorola,
+}
+
+impl FileType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Binary => "BINARY",
+            Self::Motorola => "MOTOROLA",
+        }
+    }
+}
+
+impl From<ByteOrder> for FileType {
+    fn from(byte_order: ByteOrder) -> Self {
+        match byte_order {
+            ByteOrder::Little => Self::Binary,
+            ByteOrder::Big => Self::Motorola,
+        }
+    }
+}
+
+/// An `INDEX` entry, positioned in sectors from the start of the BIN file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CueIndex {
+    pub number: u8,
+    pub position: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CueTrack {
+    pub number: u8,
+    pub mode: TrackMode,
+    /// Length in sectors of a `PREGAP`, which is not stored in the BIN.
+    /// Generated cue sheets store pregaps as INDEX 00 instead and leave
+    /// this at 0.
+    pub pregap: u32,
+    pub indexes: Vec<CueIndex>,
+}
+
+/// Cue sheet describing the tracks of a single BIN file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CueSheet {
+    pub file_name: String,
+    pub file_type: FileType,
+    pub tracks: Vec<CueTrack>,
+}
+
+impl CueSheet {
+    /// Cue sheet for a BIN holding one track that starts at its first sector.
+    pub fn single_track(file_name: impl Into<String>, mode: TrackMode) -> Self {
+        Self {
+            file_name: file_name.into(),
+            file_type: FileType::Binary,
+            tracks: vec![CueTrack {
+                number: 1,
+                mode,
+                pregap: 0,
+                indexes: vec![CueIndex {
+                    number: 1,
+                    position: 0,
+                }],
+            }],
+        }
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(&path, self.to_string())
+            .with_context(|| format!("Failed to write cue sheet: {}", path.as_ref().display()))
+    }
+}
+
+/// Lines end in CRLF, as in the cue sheets of Redump dumps, so that a
+/// generated cue sheet hashes the same as the DAT entry for it.
+impl fmt::Display for CueSheet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "FILE \"{}\" {}\r\n",
+            self.file_name,
+            self.file_type.as_str()
+        )?;
+
+        for track in &self.tracks {
+            write!(f, "  TRACK {:02} {}\r\n", track.number, track.mode.as_str())?;
+            if track.pregap > 0 {
+                write!(f, "    PREGAP {}\r\n", format_msf(track.pregap))?;
+            }
+
+            for index in &track.indexes {
+                write!(
+                    f,
+                    "    INDEX {:02} {}\r\n",
+                    index.number,
+                    format_msf(index.position)
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the cue sheet at `path`; see [`parse_cue_sheet`].
+pub fn load_cue_sheet<P: AsRef<Path>>(path: P) -> Result<Vec<CueSheet>> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read cue sheet: {}", path.display()))?;
+
+    parse_cue_sheet(&text).with_context(|| format!("Invalid cue sheet: {}", path.display()))
+}
+
+/// Parses a cue sheet into one [`CueSheet`] per `FILE` entry, in order.
+/// Commands that do not affect where the tracks lie, such as TITLE, FLAGS
+/// or REM, are skipped.
+pub fn parse_cue_sheet(text: &str) -> Result<Vec<CueSheet>> {
+    let mut files: Vec<CueSheet> = Vec::new();
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let keyword = keyword.to_ascii_uppercase();
+        let bad_entry = || format!("line {}: bad {keyword} entry", line_number + 1);
+
+        let track = files.last_mut().and_then(|file| file.tracks.last_mut());
+        match (keyword.as_str(), track) {
+            ("FILE", _) => {
+                let (name, file_type) = split_file_entry(rest).with_context(bad_entry)?;
+                let file_type = match file_type.to_ascii_uppercase().as_str() {
+                    "BINARY" => FileType::Binary,
+                    "MOTOROLA" => FileType::Motorola,
+                    other => bail!("line {}: unsupported file type {other}", line_number + 1),
+                };
+                files.push(CueSheet {
+                    file_name: name.to_string(),
+                    file_type,
+                    tracks: Vec::new(),
+                });
+            }
+            ("TRACK", _) => {
+                let Some(file) = files.last_mut() else {
+                    bail!("line {}: TRACK without a preceding FILE", line_number + 1);
+                };
+                let (number, mode) = parse_track(rest).with_context(bad_entry)?;
+                file.tracks.push(CueTrack {
+                    number,
+                    mode,
+                    pregap: 0,
+                    indexes: Vec::new(),
+                });
+            }
+            ("INDEX", Some(track)) => {
+                let (number, position) = rest
+                    .split_once(char::is_whitespace)
+                    .context("missing index position")
+                    .with_context(bad_entry)?;
+                track.indexes.push(CueIndex {
+                    number: number.parse().with_context(bad_entry)?,
+                    position: parse_msf(position).with_context(bad_entry)?,
+                });
+            }
+            ("PREGAP", Some(track)) => {
+                track.pregap = parse_msf(rest).with_context(bad_entry)?;
+            }
+            ("INDEX" | "PREGAP", None) => {
+                bail!("line {}: {keyword} outside a TRACK", line_number + 1);
+            }
+            _ => {}
+        }
+    }
+
+    if files.iter().all(|file| file.tracks.is_empty()) {
+        bail!("no tracks defined");
+    }
+
+    Ok(files)
+}
+
+/// Formats a sector count as a cue sheet `mm:ss:ff` timestamp.
+pub fn format_msf(frames: u32) -> String {
+    let minutes = frames / (60 * FRAMES_PER_SECOND);
+    let seconds = (frames / FRAMES_PER_SECOND) % 60;
+    let frame = frames % FRAMES_PER_SECOND;
+
+    format!("{minutes:02}:{seconds:02}:{frame:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_track_cue_sheet() {
+        let cue = CueSheet::single_track("game.bin", TrackMode::Mode1);
+        assert_eq!(
+            cue.to_string(),
+            "FILE \"game.bin\" BINARY\r\n  TRACK 01 MODE1/2352\r\n    INDEX 01 00:00:00\r\n"
+        );
+
+        let mode = TrackMode::from(SectorFormat::Mode2Form1(Subheader::data()));
+        assert_eq!(mode.as_str(), "MODE2/2352");
+    }
+
+    #[test]
+    fn test_parse_cue_sheet() {
+        let text = "REM Mixed-mode disc\r\n\
+                    FILE \"Game (Track 1).bin\" BINARY\r\n\
+                    \x20 TRACK 01 MODE1/2352\r\n\
+                    \x20   INDEX 01 00:00:00\r\n\
+                    FILE \"Game (Track 2).bin\" BINARY\r\n\
+                    \x20 TRACK 02 AUDIO\r\n\
+                    \x20   FLAGS DCP\r\n\
+                    \x20   PREGAP 00:01:00\r\n\
+                    \x20   INDEX 00 00:00:00\r\n\
+                    \x20   INDEX 01 00:02:00\r\n";
+
+        let files = parse_cue_sheet(text).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].file_name, "Game (Track 1).bin");
+        assert_eq!(files[0].tracks[0].mode, TrackMode::Mode1);
+
+        let track = &files[1].tracks[0];
+        assert_eq!(track.number, 2);
+        assert_eq!(track.mode, TrackMode::Audio);
+        assert_eq!(track.pregap, 75);
+        assert_eq!(
+            track.indexes,
+            vec![
+                CueIndex {
+                    number: 0,
+                    position: 0
+                },
+                CueIndex {
+                    number: 1,
+                    position: 150
+                }
+            ]
+        );
+
+        // Generated cue sheets read back unchanged
+        let cue = CueSheet::single_track("game.bin", TrackMode::Mode2);
+        assert_eq!(parse_cue_sheet(&cue.to_string()).unwrap(), vec![cue]);
+
+        assert!(parse_cue_sheet("INDEX 01 00:00:00").is_err());
+        assert!(parse_cue_sheet("FILE \"a.bin\" BINARY\nTRACK 01 MODE1/2048").is_err());
+        assert!(parse_cue_sheet("FILE \"a.wav\" WAVE\nTRACK 01 AUDIO").is_err());
+    }
+
+    #[test]
+    fn test_format_msf() {
+        assert_eq!(format_msf(0), "00:00:00");
+        assert_eq!(format_msf(150), "00:02:00");
+        assert_eq!(format_msf(60 * 75 * 12 + 75 * 34 + 56), "12:34:56");
+    }
+}
@@ -0,0 +1,259 @@
+// Logiqx DAT matching
+//
+// Redump publishes its catalogue as Logiqx XML datafiles: one <game> per
+// title with a <rom> per file giving its name, size and hashes. Older DATs
+// use <machine> instead of <game>. Matching an image needs only those
+// attributes, so the rest of the document is ignored.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use crate::hash::ImageHashes;
+
+/// One file of a DAT entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rom {
+    pub name: String,
+    pub size: u64,
+    pub crc32: Option<u32>,
+    pub md5: Option<[u8; 16]>,
+    pub sha1: Option<[u8; 20]>,
+}
+
+impl Rom {
+    /// True when the size and every hash the DAT lists agree with `hashes`.
+    /// A rom without any hash never matches.
+    pub fn matches(&self, hashes: &ImageHashes) -> bool {
+        if self.crc32.is_none() && self.md5.is_none() && self.sha1.is_none() {
+            return false;
+        }
+
+        self.size == hashes.size
+            && self.crc32.is_none_or(|crc32| crc32 == hashes.crc32)
+            && self.md5.is_none_or(|md5| md5 == hashes.md5)
+            && self.sha1.is_none_or(|sha1| sha1 == hashes.sha1)
+    }
+}
+
+/// A title and the files of its dump.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game {
+    pub name: String,
+    pub roms: Vec<Rom>,
+}
+
+impl Game {
+    pub fn find_rom(&self, hashes: &ImageHashes) -> Option<&Rom> {
+        self.roms.iter().find(|rom| rom.matches(hashes))
+    }
+}
+
+/// A rom of a DAT together with the title it belongs to.
+#[derive(Debug, Clone, Copy)]
+pub struct DatMatch<'a> {
+    pub game: &'a Game,
+    pub rom: &'a Rom,
+}
+
+/// A parsed Logiqx datafile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dat {
+    /// Name from the header, e.g. "Sony - PlayStation"
+    pub name: Option<String>,
+    pub games: Vec<Game>,
+}
+
+impl Dat {
+    pub fn load(path: &Path) -> Result<Self> {
+        let xml = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read DAT file: {}", path.display()))?;
+        Self::parse(&xml).with_context(|| format!("Invalid DAT file: {}", path.display()))
+    }
+
+    pub fn parse(xml: &str) -> Result<Self> {
+        // Redump DATs carry a DOCTYPE pointing at the Logiqx DTD
+        let options = roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        };
+        let document = roxmltree::Document::parse_with_options(xml, options)?;
+
+        let root = document.root_element();
+        if !root.has_tag_name("datafile") {
+            anyhow::bail!(
+                "Expected a <datafile> root element, found <{}>",
+                root.tag_name().name()
+            );
+        }
+
+        let name = root
+            .children()
+            .find(|node| node.has_tag_name("header"))
+            .and_then(|header| header.children().find(|node| node.has_tag_name("name")))
+            .and_then(|name| name.text())
+            .map(|name| name.trim().to_string());
+
+        let games = root
+            .children()
+            .filter(|node| node.has_tag_name("game") || node.has_tag_name("machine"))
+            .map(parse_game)
+            .collect::<Result<_>>()?;
+
+        Ok(Self { name, games })
+    }
+
+    /// First rom of any title that matches `hashes`.
+    pub fn find(&self, hashes: &ImageHashes) -> Option<DatMatch<'_>> {
+        self.games
+            .iter()
+            .find_map(|game| game.find_rom(hashes).map(|rom| DatMatch { game, rom }))
+    }
+}
+
+fn parse_game(node: roxmltree::Node) -> Result<Game> {
+    let name = required_attribute(node, "name")?.to_string();
+    let roms = node
+        .children()
+        .filter(|child| child.has_tag_name("rom"))
+        .map(parse_rom)
+        .collect::<Result<_>>()
+        .with_context(|| format!("Invalid entry: {name}"))?;
+
+    Ok(Game { name, roms })
+}
+
+fn parse_rom(node: roxmltree::Node) -> Result<Rom> {
+    let name = required_attribute(node, "name")?.to_string();
+    let size = required_attribute(node, "size")?;
+    let size = size
+        .parse()
+        .with_context(|| format!("Invalid size for {name}: {size}"))?;
+
+    let crc32 = node
+        .attribute("crc")
+        .map(|crc| {
+            u32::from_str_radix(crc, 16).with_context(|| format!("Invalid CRC32 for {name}: {crc}"))
+        })
+        .transpose()?;
+    let md5 = node
+        .attribute("md5")
+        .map(|md5| parse_hex(md5).with_context(|| format!("Invalid MD5 for {name}: {md5}")))
+        .transpose()?;
+    let sha1 = node
+        .attribute("sha1")
+        .map(|sha1| parse_hex(sha1).with_context(|| format!("Invalid SHA-1 for {name}: {sha1}")))
+        .transpose()?;
+
+    Ok(Rom {
+        name,
+        size,
+        crc32,
+        md5,
+        sha1,
+    })
+}
+
+fn required_attribute<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Result<&'a str> {
+    node.attribute(name).with_context(|| {
+        format!(
+            "<{}> without a {name} attribute on line {}",
+            node.tag_name().name(),
+            node.document().text_pos_at(node.range().start).row
+        )
+    })
+}
+
+fn parse_hex<const N: usize>(text: &str) -> Result<[u8; N]> {
+    if text.len() != N * 2 || !text.is_ascii() {
+        anyhow::bail!("expected {} hex digits", N * 2);
+    }
+
+    let mut bytes = [0u8; N];
+    for (byte, digits) in bytes.iter_mut().zip(text.as_bytes().chunks_exact(2)) {
+        // Both digits are ASCII, so the slice is valid UTF-8
+        let digits = std::str::from_utf8(digits)?;
+        *byte = u8::from_str_radix(digits, 16)?;
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ImageHasher;
+
+    const DAT: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE datafile PUBLIC "-//Logiqx//DTD ROM Management Datafile//EN" "http://www.logiqx.com/Dats/datafile.dtd">
+<datafile>
+	<header>
+		<name>Test System</name>
+		<description>Test System - Datfile (2)</description>
+	</header>
+	<game name="Alpha (USA)">
+		<category>Games</category>
+		<description>Alpha (USA)</description>
+		<rom name="Alpha (USA).cue" size="3" crc="352441c2" md5="900150983cd24fb0d6963f7d28e17f72" sha1="a9993e364706816aba3e25717850c26c9cd0d89d"/>
+		<rom name="Alpha (USA).bin" size="43" crc="414fa339" md5="9e107d9d372bb6826bd81d3542a419d6" sha1="2fd4e1c67a2d28fced849ee1bb76e7391b93eb12"/>
+	</game>
+	<machine name="Beta (Europe)">
+		<rom name="Beta (Europe).bin" size="43" crc="414FA339"/>
+	</machine>
+</datafile>
+"#;
+
+    fn hashes_of(data: &[u8]) -> ImageHashes {
+        let mut hasher = ImageHasher::new();
+        hasher.update(data);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_parse() {
+        let dat = Dat::parse(DAT).unwrap();
+
+        assert_eq!(dat.name.as_deref(), Some("Test System"));
+        assert_eq!(dat.games.len(), 2);
+        assert_eq!(dat.games[0].roms.len(), 2);
+        assert_eq!(dat.games[1].name, "Beta (Europe)");
+
+        let rom = &dat.games[0].roms[1];
+        assert_eq!(rom.name, "Alpha (USA).bin");
+        assert_eq!(rom.size, 43);
+        assert_eq!(rom.crc32, Some(0x414fa339));
+        assert_eq!(rom.md5.unwrap()[..2], [0x9e, 0x10]);
+        assert_eq!(rom.sha1.unwrap()[19], 0x12);
+    }
+
+    #[test]
+    fn test_find() {
+        let dat = Dat::parse(DAT).unwrap();
+
+        let hashes = hashes_of(b"The quick brown fox jumps over the lazy dog");
+        let found = dat.find(&hashes).unwrap();
+        assert_eq!(found.game.name, "Alpha (USA)");
+        assert_eq!(found.rom.name, "Alpha (USA).bin");
+
+        let cue = found.game.find_rom(&hashes_of(b"abc")).unwrap();
+        assert_eq!(cue.name, "Alpha (USA).cue");
+
+        // Same CRC32 as the second entry's only hash, but a different size
+        let mut truncated = hashes;
+        truncated.size -= 1;
+        assert!(dat.find(&truncated).is_none());
+
+        // Only the CRC32 of Beta is known, and the SHA-1 of Alpha rules it out
+        truncated.size += 1;
+        truncated.sha1 = [0; 20];
+        assert_eq!(dat.find(&truncated).unwrap().game.name, "Beta (Europe)");
+    }
+
+    #[test]
+    fn test_invalid_hash() {
+        let xml = r#"<datafile><game name="Gamma"><rom name="Gamma.bin" size="1" md5="xyz"/></game></datafile>"#;
+        let error = format!("{:#}", Dat::parse(xml).unwrap_err());
+        assert!(error.contains("Invalid MD5 for Gamma.bin"), "{error}");
+
+        assert!(Dat::parse("<softwarelist/>").is_err());
+    }
+}
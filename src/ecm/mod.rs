@@ -0,0 +1,612 @@
+// ECM (Error Code Modeler) container
+//
+// ECM stores a BIN without the fields a CD-ROM sector can regenerate: sync,
+// mode byte, EDC, the Mode 1 zero bytes and P/Q parity. The format is the one
+// written by Neill Corlett's ecm/unecm tools:
+//
+//   "ECM\0"
+//   records, each a type/count header followed by `count` items
+//     type 0  literal bytes, stored unchanged
+//     type 1  Mode 1 sector (2352 bytes) as 3 address bytes + 2048 data
+//     type 2  Mode 2 Form 1 sector minus sync/header (2336 bytes) as
+//             4 subheader bytes + 2048 data
+//     type 3  Mode 2 Form 2 sector minus sync/header (2336 bytes) as
+//             4 subheader bytes + 2324 data
+//   end marker (a type 0 header with count 2^32)
+//   EDC of the whole decoded BIN, little endian
+//
+// A record header holds n = count - 1: the first byte is
+// `continue << 7 | (n & 31) << 2 | type`, every further byte `continue << 7`
+// plus the next 7 bits of n.
+//
+// Sectors are classified by regenerating them from what ECM would store and
+// comparing the result with the input, so a sector is only stripped when
+// decoding gives back exactly the same bytes. As in the reference encoder,
+// the sync and header of Mode 2 sectors are kept as 16 literal bytes.
+
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::ops::Range;
+
+use crate::converter::{Subheader, RAW_SECTOR_SIZE, SYNC_PATTERN};
+use crate::edc_ecc;
+use crate::parallel::ParallelProcessor;
+
+pub const ECM_MAGIC: [u8; 4] = *b"ECM\0";
+
+/// Bytes before the part of a Mode 2 sector that ECM stores as type 2 or 3.
+const MODE2_PREFIX_SIZE: usize = 16;
+
+/// Largest literal record written at once.
+const LITERAL_CHUNK_SIZE: usize = 1 << 20;
+
+/// How ECM stores a piece of a BIN.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EcmType {
+    Literal,
+    Mode1,
+    Mode2Form1,
+    Mode2Form2,
+}
+
+impl EcmType {
+    fn code(self) -> u8 {
+        match self {
+            Self::Literal => 0,
+            Self::Mode1 => 1,
+            Self::Mode2Form1 => 2,
+            Self::Mode2Form2 => 3,
+        }
+    }
+
+    fn from_code(code: u8) -> Self {
+        match code & 3 {
+            0 => Self::Literal,
+            1 => Self::Mode1,
+            2 => Self::Mode2Form1,
+            _ => Self::Mode2Form2,
+        }
+    }
+
+    /// Where the stored address or subheader bytes and the user data sit in
+    /// a 2352-byte sector.
+    fn stored_ranges(self) -> (Range<usize>, Range<usize>) {
+        match self {
+            Self::Literal => (0..0, 0..1),
+            Self::Mode1 => (12..15, 16..2064),
+            Self::Mode2Form1 => (20..24, 24..2072),
+            Self::Mode2Form2 => (20..24, 24..2348),
+        }
+    }
+
+    /// Bytes stored per item.
+    fn stored_size(self) -> usize {
+        let (prefix, data) = self.stored_ranges();
+        prefix.len() + data.len()
+    }
+
+    /// Part of the regenerated 2352-byte sector that an item decodes to.
+    fn decoded_range(self) -> Range<usize> {
+        match self {
+            Self::Literal => 0..1,
+            Self::Mode1 => 0..RAW_SECTOR_SIZE,
+            Self::Mode2Form1 | Self::Mode2Form2 => MODE2_PREFIX_SIZE..RAW_SECTOR_SIZE,
+        }
+    }
+}
+
+/// Sizes and sector counts of an ECM encode or decode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EcmSummary {
+    pub bin_bytes: u64,
+    pub ecm_bytes: u64,
+    pub mode1_sectors: u64,
+    pub mode2_form1_sectors: u64,
+    pub mode2_form2_sectors: u64,
+    /// Bytes stored unchanged, including the sync and header of Mode 2 sectors
+    pub literal_bytes: u64,
+}
+
+impl EcmSummary {
+    fn add(&mut self, ecm_type: EcmType, count: u64) {
+        match ecm_type {
+            EcmType::Literal => self.literal_bytes += count,
+            EcmType::Mode1 => self.mode1_sectors += count,
+            EcmType::Mode2Form1 => self.mode2_form1_sectors += count,
+            EcmType::Mode2Form2 => self.mode2_form2_sectors += count,
+        }
+    }
+
+    /// ECM size as a fraction of the BIN size.
+    pub fn ratio(&self) -> f64 {
+        if self.bin_bytes > 0 {
+            self.ecm_bytes as f64 / self.bin_bytes as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Picks the ECM type of a 2352-byte sector. For the Mode 2 types the first
+/// 16 bytes of the sector are stored as literals.
+pub fn classify_sector(raw: &[u8]) -> EcmType {
+    if raw.len() != RAW_SECTOR_SIZE {
+        return EcmType::Literal;
+    }
+
+    let mode1 = (raw[15] == 0x01).then_some(EcmType::Mode1);
+
+    // XA sectors carry the subheader twice; try the form it claims first
+    let mode2 = if raw[16..20] != raw[20..24] {
+        [None, None]
+    } else if raw[18] & Subheader::SUBMODE_FORM2 != 0 {
+        [Some(EcmType::Mode2Form2), Some(EcmType::Mode2Form1)]
+    } else {
+        [Some(EcmType::Mode2Form1), Some(EcmType::Mode2Form2)]
+    };
+
+    let mut sector = [0u8; RAW_SECTOR_SIZE];
+    [mode1]
+        .into_iter()
+        .chain(mode2)
+        .flatten()
+        .find(|&ecm_type| {
+            let (prefix, data) = ecm_type.stored_ranges();
+            regenerate(ecm_type, &raw[prefix], &raw[data], &mut sector);
+
+            let decoded = ecm_type.decoded_range();
+            sector[decoded.clone()] == raw[decoded]
+        })
+        .unwrap_or(EcmType::Literal)
+}
+
+/// Rebuilds a sector from its stored address or subheader bytes and user
+/// data. The decoded bytes are `sector[ecm_type.decoded_range()]`.
+fn regenerate(ecm_type: EcmType, prefix: &[u8], data: &[u8], sector: &mut [u8; RAW_SECTOR_SIZE]) {
+    let (_, data_range) = ecm_type.stored_ranges();
+    sector[data_range].copy_from_slice(data);
+
+    match ecm_type {
+        EcmType::Literal => {}
+        EcmType::Mode1 => {
+            sector[0..12].copy_from_slice(&SYNC_PATTERN);
+            sector[12..15].copy_from_slice(prefix);
+            sector[15] = 0x01;
+            edc_ecc::calc_mode1_edc(sector);
+            sector[2068..2076].fill(0);
+            edc_ecc::calc_p_parity(sector);
+            edc_ecc::calc_q_parity(sector);
+        }
+        EcmType::Mode2Form1 => {
+            // Parity is computed with the header zeroed
+            sector[12..16].fill(0);
+            sector[16..20].copy_from_slice(prefix);
+            sector[20..24].copy_from_slice(prefix);
+            edc_ecc::calc_mode2_form1_edc(sector);
+            edc_ecc::calc_p_parity(sector);
+            edc_ecc::calc_q_parity(sector);
+        }
+        EcmType::Mode2Form2 => {
+            sector[16..20].copy_from_slice(prefix);
+            sector[20..24].copy_from_slice(prefix);
+            edc_ecc::calc_mode2_form2_edc(sector);
+        }
+    }
+}
+
+/// Writes ECM records, collecting consecutive items of one type into a
+/// single record.
+struct RecordWriter<W> {
+    writer: W,
+    run_type: EcmType,
+    run_count: u32,
+    run_data: Vec<u8>,
+    bytes_written: u64,
+}
+
+impl<W: Write> RecordWriter<W> {
+    fn new(writer: W) -> Result<Self> {
+        let mut records = Self {
+            writer,
+            run_type: EcmType::Literal,
+            run_count: 0,
+            run_data: Vec::new(),
+            bytes_written: 0,
+        };
+        records.write(&ECM_MAGIC)?;
+        Ok(records)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.writer
+            .write_all(bytes)
+            .context("Failed to write ECM output")?;
+        self.bytes_written += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn push_literal(&mut self, bytes: &[u8]) -> Result<()> {
+        for chunk in bytes.chunks(LITERAL_CHUNK_SIZE) {
+            self.push(EcmType::Literal, chunk.len() as u32, &[chunk])?;
+        }
+        Ok(())
+    }
+
+    fn push_sector(&mut self, ecm_type: EcmType, raw: &[u8]) -> Result<()> {
+        let (prefix, data) = ecm_type.stored_ranges();
+        self.push(ecm_type, 1, &[&raw[prefix], &raw[data]])
+    }
+
+    fn push(&mut self, ecm_type: EcmType, count: u32, parts: &[&[u8]]) -> Result<()> {
+        if ecm_type != self.run_type || self.run_count.checked_add(count).is_none() {
+            self.flush()?;
+            self.run_type = ecm_type;
+        }
+
+        self.run_count += count;
+        for part in parts {
+            self.run_data.extend_from_slice(part);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.run_count == 0 {
+            return Ok(());
+        }
+
+        let header = type_count_header(self.run_type, self.run_count - 1);
+        self.write(&header)?;
+        let data = std::mem::take(&mut self.run_data);
+        self.write(&data)?;
+
+        self.run_data = data;
+        self.run_data.clear();
+        self.run_count = 0;
+        Ok(())
+    }
+
+    /// Writes the end marker and the EDC of the decoded data, returning the
+    /// size of the ECM file.
+    fn finish(mut self, edc: u32) -> Result<u64> {
+        self.flush()?;
+        self.write(&type_count_header(EcmType::Literal, u32::MAX))?;
+        self.write(&edc.to_le_bytes())?;
+        self.writer.flush().context("Failed to write ECM output")?;
+        Ok(self.bytes_written)
+    }
+}
+
+/// Encodes a record header for `n` = count - 1.
+fn type_count_header(ecm_type: EcmType, n: u32) -> Vec<u8> {
+    let mut header = vec![(((n & 31) as u8) << 2) | ecm_type.code()];
+    let mut rest = n >> 5;
+
+    while rest != 0 {
+        *header.last_mut().unwrap() |= 0x80;
+        header.push((rest & 127) as u8);
+        rest >>= 7;
+    }
+
+    header
+}
+
+/// Reads a record header, returning `None` for the end marker.
+fn read_type_count(reader: &mut impl Read) -> Result<Option<(EcmType, u64)>> {
+    let mut byte = [0u8; 1];
+    reader
+        .read_exact(&mut byte)
+        .context("ECM file ends before its end marker")?;
+
+    let ecm_type = EcmType::from_code(byte[0]);
+    let mut n = ((byte[0] >> 2) & 31) as u64;
+    let mut bits = 5;
+
+    while byte[0] & 0x80 != 0 {
+        if bits > 31 {
+            anyhow::bail!("Corrupt ECM record header");
+        }
+        reader
+            .read_exact(&mut byte)
+            .context("ECM file ends inside a record header")?;
+        n |= ((byte[0] & 0x7F) as u64) << bits;
+        bits += 7;
+    }
+
+    if n > u32::MAX as u64 {
+        anyhow::bail!("Corrupt ECM record header");
+    }
+
+    Ok((n != u32::MAX as u64).then_some((ecm_type, n + 1)))
+}
+
+/// Fills `buffer` from `reader`, returning fewer bytes only at the end of the
+/// input.
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err).context("Failed to read input"),
+        }
+    }
+
+    Ok(filled)
+}
+
+/// Encodes a BIN into ECM. Sectors are classified in parallel a batch at a
+/// time; `on_batch` is called after every batch with the totals so far.
+pub fn encode<R: Read, W: Write>(
+    mut reader: R,
+    writer: W,
+    processor: &ParallelProcessor,
+    on_batch: &mut dyn FnMut(&EcmSummary),
+) -> Result<EcmSummary> {
+    let mut records = RecordWriter::new(writer)?;
+    let mut summary = EcmSummary::default();
+    let mut edc = 0;
+    let mut buffer = vec![0u8; processor.sectors_per_batch() * RAW_SECTOR_SIZE];
+
+    loop {
+        let filled = read_full(&mut reader, &mut buffer)?;
+        let batch = &buffer[..filled];
+        edc = edc_ecc::update_edc(edc, batch);
+
+        let sectors = batch.chunks_exact(RAW_SECTOR_SIZE);
+        let tail = sectors.remainder();
+        let types: Vec<EcmType> = processor.install(|| {
+            batch
+                .par_chunks_exact(RAW_SECTOR_SIZE)
+                .map(classify_sector)
+                .collect()
+        });
+
+        for (raw, ecm_type) in sectors.zip(types) {
+            match ecm_type {
+                EcmType::Literal => records.push_literal(raw)?,
+                EcmType::Mode1 => records.push_sector(ecm_type, raw)?,
+                EcmType::Mode2Form1 | EcmType::Mode2Form2 => {
+                    records.push_literal(&raw[..MODE2_PREFIX_SIZE])?;
+                    summary.add(EcmType::Literal, MODE2_PREFIX_SIZE as u64);
+                    records.push_sector(ecm_type, raw)?;
+                }
+            }
+
+            let count = if ecm_type == EcmType::Literal {
+                RAW_SECTOR_SIZE as u64
+            } else {
+                1
+            };
+            summary.add(ecm_type, count);
+        }
+
+        records.push_literal(tail)?;
+        summary.add(EcmType::Literal, tail.len() as u64);
+        records.flush()?;
+
+        summary.bin_bytes += filled as u64;
+        summary.ecm_bytes = records.bytes_written;
+        on_batch(&summary);
+
+        if filled < buffer.len() {
+            break;
+        }
+    }
+
+    summary.ecm_bytes = records.finish(edc)?;
+
+    Ok(summary)
+}
+
+/// Decodes an ECM file back into the original BIN, checking the EDC stored
+/// at its end. Sectors are regenerated in parallel; `on_batch` is called
+/// after every written batch with the totals so far.
+pub fn decode<R: Read, W: Write>(
+    reader: R,
+    mut writer: W,
+    processor: &ParallelProcessor,
+    on_batch: &mut dyn FnMut(&EcmSummary),
+) -> Result<EcmSummary> {
+    let mut reader = CountingReader::new(BufReader::new(reader));
+    let mut summary = EcmSummary::default();
+
+    let mut magic = [0u8; 4];
+    reader
+        .read_exact(&mut magic)
+        .context("Failed to read ECM header")?;
+    if magic != ECM_MAGIC {
+        anyhow::bail!("Not an ECM file: missing \"ECM\\0\" header");
+    }
+
+    let sectors_per_batch = processor.sectors_per_batch();
+    let mut stored = Vec::new();
+    let mut decoded = Vec::new();
+    let mut edc = 0;
+
+    while let Some((ecm_type, count)) = read_type_count(&mut reader)? {
+        let mut remaining = count;
+
+        while remaining > 0 {
+            let items = if ecm_type == EcmType::Literal {
+                remaining.min(LITERAL_CHUNK_SIZE as u64)
+            } else {
+                remaining.min(sectors_per_batch as u64)
+            } as usize;
+
+            stored.resize(items * ecm_type.stored_size(), 0);
+            reader
+                .read_exact(&mut stored)
+                .context("ECM file ends inside a record")?;
+
+            let output = if ecm_type == EcmType::Literal {
+                &stored
+            } else {
+                let decoded_size = ecm_type.decoded_range().len();
+                decoded.resize(items * decoded_size, 0);
+
+                processor.install(|| {
+                    decoded
+                        .par_chunks_mut(decoded_size)
+                        .zip(stored.par_chunks(ecm_type.stored_size()))
+                        .for_each(|(output, item)| {
+                            let (prefix, _) = ecm_type.stored_ranges();
+                            let (prefix, data) = item.split_at(prefix.len());
+                            let mut sector = [0u8; RAW_SECTOR_SIZE];
+                            regenerate(ecm_type, prefix, data, &mut sector);
+                            output.copy_from_slice(&sector[ecm_type.decoded_range()]);
+                        })
+                });
+                &decoded
+            };
+
+            edc = edc_ecc::update_edc(edc, output);
+            writer
+                .write_all(output)
+                .context("Failed to write RAW output")?;
+
+            remaining -= items as u64;
+            summary.add(ecm_type, items as u64);
+            summary.bin_bytes += output.len() as u64;
+            summary.ecm_bytes = reader.bytes_read;
+            on_batch(&summary);
+        }
+    }
+
+    let mut stored_edc = [0u8; 4];
+    reader
+        .read_exact(&mut stored_edc)
+        .context("ECM file ends before its EDC")?;
+    summary.ecm_bytes = reader.bytes_read;
+    writer.flush().context("Failed to write RAW output")?;
+
+    if u32::from_le_bytes(stored_edc) != edc {
+        anyhow::bail!(
+            "EDC mismatch: the ECM file stores {:08x} but the decoded data has {edc:08x}",
+            u32::from_le_bytes(stored_edc)
+        );
+    }
+
+    Ok(summary)
+}
+
+/// Counts the bytes read through it.
+struct CountingReader<R> {
+    reader: R,
+    bytes_read: u64,
+}
+
+impl<R> CountingReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            bytes_read: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.bytes_read += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::{
+        convert_iso_to_mode2_form1, convert_iso_to_raw, convert_to_mode2_form2,
+    };
+
+    fn noise(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    /// Mode 1, both Mode 2 forms, a damaged sector, audio and a short tail.
+    fn mixed_bin() -> Vec<u8> {
+        let mut bin = Vec::new();
+        for lba in 0..40u32 {
+            let data = noise(2324, lba + 1);
+            let sector = match lba % 5 {
+                0 | 1 => convert_iso_to_raw(lba, &data[..2048]).unwrap(),
+                2 => convert_iso_to_mode2_form1(lba, &data[..2048], Subheader::data()).unwrap(),
+                3 => convert_to_mode2_form2(lba, &data, Subheader::default(), true).unwrap(),
+                _ => noise(RAW_SECTOR_SIZE, lba + 100),
+            };
+            bin.extend(sector);
+        }
+        bin[6 * RAW_SECTOR_SIZE + 500] ^= 0x40;
+        bin.extend(noise(1000, 7));
+        bin
+    }
+
+    fn encode_bytes(bin: &[u8]) -> (Vec<u8>, EcmSummary) {
+        let mut ecm = Vec::new();
+        let summary = encode(bin, &mut ecm, &ParallelProcessor::new(Some(2)), &mut |_| {}).unwrap();
+        (ecm, summary)
+    }
+
+    fn decode_bytes(ecm: &[u8]) -> Result<Vec<u8>> {
+        let mut bin = Vec::new();
+        decode(ecm, &mut bin, &ParallelProcessor::new(Some(2)), &mut |_| {})?;
+        Ok(bin)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bin = mixed_bin();
+        let (ecm, summary) = encode_bytes(&bin);
+
+        assert_eq!(&ecm[..4], b"ECM\0");
+        assert_eq!(summary.ecm_bytes, ecm.len() as u64);
+        assert_eq!(summary.bin_bytes, bin.len() as u64);
+        assert_eq!(summary.mode1_sectors, 15); // one of 16 is damaged
+        assert_eq!(summary.mode2_form1_sectors, 8);
+        assert_eq!(summary.mode2_form2_sectors, 8);
+        assert!(ecm.len() < bin.len());
+
+        assert!(decode_bytes(&ecm).unwrap() == bin);
+    }
+
+    #[test]
+    fn test_type_count_header() {
+        assert_eq!(type_count_header(EcmType::Mode1, 0), [0x01]);
+        assert_eq!(type_count_header(EcmType::Mode2Form1, 31), [0x7E]);
+        assert_eq!(type_count_header(EcmType::Literal, 32), [0x80, 0x01]);
+        assert_eq!(
+            type_count_header(EcmType::Literal, u32::MAX),
+            [0xFC, 0xFF, 0xFF, 0xFF, 0x3F]
+        );
+
+        for n in [0, 31, 32, 4095, 4096, 1 << 20, u32::MAX - 1] {
+            let header = type_count_header(EcmType::Mode2Form2, n);
+            let parsed = read_type_count(&mut header.as_slice()).unwrap();
+            assert_eq!(parsed, Some((EcmType::Mode2Form2, n as u64 + 1)));
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_damage() {
+        let (mut ecm, _) = encode_bytes(&mixed_bin());
+        ecm[200] ^= 0x01;
+        let err = decode_bytes(&ecm).unwrap_err();
+        assert!(err.to_string().contains("EDC mismatch"), "{err}");
+
+        assert!(decode_bytes(b"BIN\0").is_err());
+        let (ecm, _) = encode_bytes(&mixed_bin());
+        assert!(decode_bytes(&ecm[..ecm.len() - 2]).is_err());
+    }
+}
@@ -8,85 +8,117 @@
 // EDCRE source: https://github.com/alex-free/edcre
 // Original cdrdao source: https://github.com/cdrdao/cdrdao
 
-use std::sync::OnceLock;
+// All tables are evaluated at compile time, so encoding needs no
+// initialization, locking or allocation.
 
 const GF8_PRIM_POLY: u16 = 0x11d; // x^8 + x^4 + x^3 + x^2 + 1
 const EDC_POLY: u32 = 0x8001801b; // (x^16 + x^15 + x^2 + 1) * (x^16 + x^2 + x + 1)
 
-static GF8_LOG: OnceLock<[u8; 256]> = OnceLock::new();
-static GF8_ILOG: OnceLock<[u8; 256]> = OnceLock::new();
-static CRC_TABLE: OnceLock<[u32; 256]> = OnceLock::new();
-static GF8_Q_COEFFS_TABLE: OnceLock<[[u16; 256]; 43]> = OnceLock::new();
+static CRC_TABLE: [u32; 256] = init_crc_table();
+static CRC_SLICES: [[u32; 256]; 8] = init_crc_slices(&CRC_TABLE);
 
-fn mirror_bits(d: u32, bits: usize) -> u32 {
+/// GF(2^8) log and antilog tables, shared with the RS-PC decoder.
+pub(super) static GF8_LOG: [u8; 256] = init_gf8_tables().0;
+pub(super) static GF8_ILOG: [u8; 256] = init_gf8_tables().1;
+
+/// Products of every byte with both Q coefficients of each of the 43 rows:
+/// the low byte for parity byte 0, the high byte for parity byte 1. Shared
+/// with the SIMD parity backend.
+pub(super) static GF8_Q_COEFFS_TABLE: [[u16; 256]; 43] = init_gf8_q_coeffs_table();
+
+const fn mirror_bits(d: u32, bits: usize) -> u32 {
     let mut r = 0u32;
     let mut d = d;
+    let mut i = 0;
 
-    for _ in 0..bits {
+    while i < bits {
         r <<= 1;
         if (d & 0x1) != 0 {
             r |= 0x1;
         }
         d >>= 1;
+        i += 1;
     }
 
     r
 }
 
-fn init_crc_table() -> [u32; 256] {
+const fn init_crc_table() -> [u32; 256] {
     let mut table = [0u32; 256];
+    let mut i = 0;
 
-    for (i, entry) in table.iter_mut().enumerate() {
+    while i < 256 {
         let mut r = mirror_bits(i as u32, 8);
         r <<= 24;
 
-        for _ in 0..8 {
+        let mut bit = 0;
+        while bit < 8 {
             if (r & 0x80000000) != 0 {
                 r <<= 1;
                 r ^= EDC_POLY;
             } else {
                 r <<= 1;
             }
+            bit += 1;
         }
 
-        r = mirror_bits(r, 32);
-        *entry = r;
+        table[i] = mirror_bits(r, 32);
+        i += 1;
     }
 
     table
 }
 
-fn init_gf8_tables() -> ([u8; 256], [u8; 256]) {
+/// Tables for slicing-by-8: entry `k` advances the CRC of a byte by `k`
+/// further zero bytes, so eight input bytes are folded in with one lookup each.
+const fn init_crc_slices(table: &[u32; 256]) -> [[u32; 256]; 8] {
+    let mut slices = [[0u32; 256]; 8];
+    slices[0] = *table;
+
+    let mut k = 1;
+    while k < 8 {
+        let mut i = 0;
+        while i < 256 {
+            let previous = slices[k - 1][i];
+            slices[k][i] = (previous >> 8) ^ table[(previous & 0xff) as usize];
+            i += 1;
+        }
+        k += 1;
+    }
+
+    slices
+}
+
+const fn init_gf8_tables() -> ([u8; 256], [u8; 256]) {
     let mut log_table = [0u8; 256];
     let mut ilog_table = [0u8; 256];
 
     let mut b = 1u16;
+    let mut log = 0;
 
-    for log in 0..255u8 {
-        log_table[b as usize] = log;
-        ilog_table[log as usize] = b as u8;
+    while log < 255 {
+        log_table[b as usize] = log as u8;
+        ilog_table[log] = b as u8;
 
         b <<= 1;
 
         if (b & 0x100) != 0 {
             b ^= GF8_PRIM_POLY;
         }
+        log += 1;
     }
 
     (log_table, ilog_table)
 }
 
-fn gf8_add(a: u8, b: u8) -> u8 {
+const fn gf8_add(a: u8, b: u8) -> u8 {
     a ^ b
 }
 
-fn gf8_div(a: u8, b: u8) -> u8 {
-    let log_table = GF8_LOG.get().unwrap();
-    let ilog_table = GF8_ILOG.get().unwrap();
-
-    if b == 0 {
-        panic!("Division by zero in GF(8)");
-    }
+/// Only used while building the tables, so a zero divisor fails the build
+/// rather than a running conversion.
+const fn gf8_div(a: u8, b: u8, log_table: &[u8; 256], ilog_table: &[u8; 256]) -> u8 {
+    assert!(b != 0, "Division by zero in GF(8)");
 
     if a == 0 {
         return 0;
@@ -101,9 +133,9 @@ fn gf8_div(a: u8, b: u8) -> u8 {
     ilog_table[sum as usize]
 }
 
-fn init_gf8_q_coeffs_table() -> [[u16; 256]; 43] {
-    let log_table = GF8_LOG.get().unwrap();
-    let ilog_table = GF8_ILOG.get().unwrap();
+const fn init_gf8_q_coeffs_table() -> [[u16; 256]; 43] {
+    let (log_table, ilog_table) = init_gf8_tables();
+    let (log_table, ilog_table) = (&log_table, &ilog_table);
 
     let mut gf8_coeffs_help = [[0u8; 45]; 2];
     let mut gf8_q_coeffs = [[0u8; 45]; 2];
@@ -111,43 +143,65 @@ fn init_gf8_q_coeffs_table() -> [[u16; 256]; 43] {
     // Build matrix H:
     // 1    1   ...  1   1
     // a^44 a^43 ... a^1 a^0
-    for j in 0..45 {
+    let mut j = 0;
+    while j < 45 {
         gf8_coeffs_help[0][j] = 1; // e0
         gf8_coeffs_help[1][j] = ilog_table[44 - j]; // e1
+        j += 1;
     }
 
     // Resolve equation system for parity byte 0 and 1
 
     // e1' = e1 + e0
-    for j in 0..45 {
+    j = 0;
+    while j < 45 {
         gf8_q_coeffs[1][j] = gf8_add(gf8_coeffs_help[1][j], gf8_coeffs_help[0][j]);
+        j += 1;
     }
 
     // e1'' = e1' / (a^1 + 1)
-    for j in 0..45 {
-        gf8_q_coeffs[1][j] = gf8_div(gf8_q_coeffs[1][j], gf8_q_coeffs[1][43]);
+    j = 0;
+    while j < 45 {
+        gf8_q_coeffs[1][j] = gf8_div(
+            gf8_q_coeffs[1][j],
+            gf8_q_coeffs[1][43],
+            log_table,
+            ilog_table,
+        );
+        j += 1;
     }
 
     // e0' = e0 + e1 / a^1
-    for j in 0..45 {
+    j = 0;
+    while j < 45 {
         gf8_q_coeffs[0][j] = gf8_add(
             gf8_coeffs_help[0][j],
-            gf8_div(gf8_coeffs_help[1][j], ilog_table[1]),
+            gf8_div(gf8_coeffs_help[1][j], ilog_table[1], log_table, ilog_table),
         );
+        j += 1;
     }
 
     // e0'' = e0' / (1 + 1 / a^1)
-    for j in 0..45 {
-        gf8_q_coeffs[0][j] = gf8_div(gf8_q_coeffs[0][j], gf8_q_coeffs[0][44]);
+    j = 0;
+    while j < 45 {
+        gf8_q_coeffs[0][j] = gf8_div(
+            gf8_q_coeffs[0][j],
+            gf8_q_coeffs[0][44],
+            log_table,
+            ilog_table,
+        );
+        j += 1;
     }
 
     // Compute the products of 0..255 with all of the Q coefficients
     let mut table = [[0u16; 256]; 43];
 
-    for j in 0..43 {
+    j = 0;
+    while j < 43 {
         table[j][0] = 0;
 
-        for i in 1..256 {
+        let mut i = 1;
+        while i < 256 {
             let mut c = log_table[i] as u16 + log_table[gf8_q_coeffs[0][j] as usize] as u16;
             if c >= 255 {
                 c -= 255;
@@ -159,31 +213,50 @@ fn init_gf8_q_coeffs_table() -> [[u16; 256]; 43] {
                 c -= 255;
             }
             table[j][i] |= (ilog_table[c as usize] as u16) << 8;
+            i += 1;
         }
+        j += 1;
     }
 
     table
 }
 
-fn ensure_tables_initialized() {
-    static INIT: OnceLock<()> = OnceLock::new();
-    INIT.get_or_init(|| {
-        let (log_table, ilog_table) = init_gf8_tables();
-        let _ = GF8_LOG.set(log_table);
-        let _ = GF8_ILOG.set(ilog_table);
-        let _ = CRC_TABLE.set(init_crc_table());
-        let _ = GF8_Q_COEFFS_TABLE.set(init_gf8_q_coeffs_table());
-    });
+/// EDC (CRC-32 with the CD-ROM polynomial), eight bytes at a time.
+pub fn calc_edc(data: &[u8]) -> u32 {
+    update_edc(0, data)
 }
 
-pub fn calc_edc(data: &[u8]) -> u32 {
-    ensure_tables_initialized();
-    let table = CRC_TABLE.get().unwrap();
+/// Continues an EDC over further data, so that `update_edc(calc_edc(a), b)`
+/// equals the EDC of `a` followed by `b`.
+pub fn update_edc(mut crc: u32, data: &[u8]) -> u32 {
+    let slices = &CRC_SLICES;
+    let mut words = data.chunks_exact(8);
+
+    for word in &mut words {
+        let lo = crc ^ u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+        let hi = u32::from_le_bytes([word[4], word[5], word[6], word[7]]);
+
+        crc = slices[7][(lo & 0xff) as usize]
+            ^ slices[6][((lo >> 8) & 0xff) as usize]
+            ^ slices[5][((lo >> 16) & 0xff) as usize]
+            ^ slices[4][(lo >> 24) as usize]
+            ^ slices[3][(hi & 0xff) as usize]
+            ^ slices[2][((hi >> 8) & 0xff) as usize]
+            ^ slices[1][((hi >> 16) & 0xff) as usize]
+            ^ slices[0][(hi >> 24) as usize];
+    }
 
-    let mut crc = 0u32;
+    update_edc_bytewise(crc, words.remainder())
+}
 
+/// EDC one byte at a time, the reference the sliced version must match.
+pub fn calc_edc_bytewise(data: &[u8]) -> u32 {
+    update_edc_bytewise(0, data)
+}
+
+fn update_edc_bytewise(mut crc: u32, data: &[u8]) -> u32 {
     for &byte in data {
-        crc = table[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+        crc = CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
     }
 
     crc
@@ -198,9 +271,50 @@ pub fn calc_mode1_edc(sector: &mut [u8]) {
     sector[2067] = ((crc >> 24) & 0xff) as u8;
 }
 
+pub fn calc_mode2_form1_edc(sector: &mut [u8]) {
+    let crc = calc_edc(&sector[16..2072]); // subheader + data
+
+    sector[2072] = (crc & 0xff) as u8;
+    sector[2073] = ((crc >> 8) & 0xff) as u8;
+    sector[2074] = ((crc >> 16) & 0xff) as u8;
+    sector[2075] = ((crc >> 24) & 0xff) as u8;
+}
+
+pub fn calc_mode2_form2_edc(sector: &mut [u8]) {
+    let crc = calc_edc(&sector[16..2348]); // subheader + data
+
+    sector[2348] = (crc & 0xff) as u8;
+    sector[2349] = ((crc >> 8) & 0xff) as u8;
+    sector[2350] = ((crc >> 16) & 0xff) as u8;
+    sector[2351] = ((crc >> 24) & 0xff) as u8;
+}
+
+/// Computes the P parity of a 2352-byte sector with the fastest backend the
+/// CPU supports.
 pub fn calc_p_parity(sector: &mut [u8]) {
-    ensure_tables_initialized();
-    let table = GF8_Q_COEFFS_TABLE.get().unwrap();
+    #[cfg(target_arch = "x86_64")]
+    if super::simd::ssse3_available() {
+        // SAFETY: SSSE3 support was just detected
+        return unsafe { super::simd::calc_p_parity_ssse3(sector) };
+    }
+
+    calc_p_parity_scalar(sector);
+}
+
+/// Computes the Q parity of a 2352-byte sector with the fastest backend the
+/// CPU supports. P parity must already be in place.
+pub fn calc_q_parity(sector: &mut [u8]) {
+    #[cfg(target_arch = "x86_64")]
+    if super::simd::ssse3_available() {
+        // SAFETY: SSSE3 support was just detected
+        return unsafe { super::simd::calc_q_parity_ssse3(sector) };
+    }
+
+    calc_q_parity_scalar(sector);
+}
+
+pub fn calc_p_parity_scalar(sector: &mut [u8]) {
+    let table = &GF8_Q_COEFFS_TABLE;
 
     let p_lsb_start = 12; // LEC_HEADER_OFFSET
     let p_parity_offset = 2076; // LEC_MODE1_P_PARITY_OFFSET
@@ -231,9 +345,8 @@ pub fn calc_p_parity(sector: &mut [u8]) {
     }
 }
 
-pub fn calc_q_parity(sector: &mut [u8]) {
-    ensure_tables_initialized();
-    let table = GF8_Q_COEFFS_TABLE.get().unwrap();
+pub fn calc_q_parity_scalar(sector: &mut [u8]) {
+    let table = &GF8_Q_COEFFS_TABLE;
 
     let q_lsb_start = 12; // LEC_HEADER_OFFSET
     let q_parity_offset = 2248; // LEC_MODE1_Q_PARITY_OFFSET
@@ -267,3 +380,27 @@ pub fn calc_q_parity(sector: &mut [u8]) {
         sector[q_parity_offset + i * 2 + 1] = (q01_msb >> 8) as u8;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sliced_edc_matches_bytewise() {
+        let data: Vec<u8> = (0..2400u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+
+        // Every remainder length, and the spans used by each sector mode
+        for len in (0..64).chain([2048, 2056, 2064, 2332, 2400]) {
+            assert_eq!(
+                calc_edc(&data[..len]),
+                calc_edc_bytewise(&data[..len]),
+                "length {len}"
+            );
+        }
+
+        let (head, tail) = data.split_at(1001);
+        assert_eq!(update_edc(calc_edc(head), tail), calc_edc(&data));
+    }
+}
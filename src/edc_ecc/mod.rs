@@ -1,3 +1,7 @@
 mod edcre;
+mod rspc;
+mod simd;
 
 pub use edcre::*;
+pub use rspc::*;
+pub use simd::{parity_backend, ParityBackend};
@@ -0,0 +1,188 @@
+// RS-PC (Reed-Solomon Product Code) error correction for CD-ROM sectors
+//
+// The 2340 bytes after the sync pattern are split into an LSB and an MSB plane
+// of 1170 bytes each. Every plane holds 43 P codewords (columns, RS(26,24)) and
+// 26 Q codewords (diagonals, RS(45,43)), both checked by the matrix
+//   1       1       ...  1
+//   a^(n-1) a^(n-2) ...  a^0
+// which lets each codeword locate and fix a single byte error. Running P and Q
+// passes alternately, as a drive's C3 layer does, resolves errors that neither
+// pass could fix alone.
+
+use super::edcre::{GF8_ILOG, GF8_LOG};
+
+const HEADER_OFFSET: usize = 12; // LEC_HEADER_OFFSET
+const P_COLUMNS: usize = 43;
+const P_LENGTH: usize = 26;
+const Q_DIAGONALS: usize = 26;
+const Q_LENGTH: usize = 45;
+const Q_DATA_WORDS: usize = 1118; // words covered by Q besides Q parity itself
+
+const MAX_PASSES: usize = 8;
+
+/// Result of running the P/Q decoder over a sector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Correction {
+    /// Number of bytes that were changed
+    pub bytes_corrected: usize,
+    /// Whether all P and Q syndromes are zero after correction
+    pub consistent: bool,
+}
+
+#[derive(Default)]
+struct PassResult {
+    errors: usize,
+    corrected: usize,
+}
+
+/// Corrects byte errors in place using the P and Q parity of a 2352-byte
+/// sector. For Mode 2 Form 1 sectors the header must be zeroed by the caller
+/// beforehand, mirroring how the parity was generated.
+pub fn correct_pq_parity(sector: &mut [u8]) -> Correction {
+    let mut bytes_corrected = 0;
+
+    for _ in 0..MAX_PASSES {
+        let p = run_pass(sector, P_COLUMNS, P_LENGTH, p_word, true);
+        let q = run_pass(sector, Q_DIAGONALS, Q_LENGTH, q_word, true);
+        bytes_corrected += p.corrected + q.corrected;
+
+        if p.errors == 0 && q.errors == 0 {
+            return Correction {
+                bytes_corrected,
+                consistent: true,
+            };
+        }
+
+        if p.corrected == 0 && q.corrected == 0 {
+            break;
+        }
+    }
+
+    // A final check catches errors introduced by a miscorrection
+    let p = run_pass(sector, P_COLUMNS, P_LENGTH, p_word, false);
+    let q = run_pass(sector, Q_DIAGONALS, Q_LENGTH, q_word, false);
+
+    Correction {
+        bytes_corrected,
+        consistent: p.errors == 0 && q.errors == 0,
+    }
+}
+
+/// Word index of element `k` of P codeword (column) `i`.
+fn p_word(i: usize, k: usize) -> usize {
+    i + P_COLUMNS * k
+}
+
+/// Word index of element `j` of Q codeword (diagonal) `i`.
+fn q_word(i: usize, j: usize) -> usize {
+    match j {
+        43 => Q_DATA_WORDS + i,
+        44 => Q_DATA_WORDS + Q_DIAGONALS + i,
+        _ => (P_COLUMNS * i + 44 * j) % Q_DATA_WORDS,
+    }
+}
+
+/// Computes the syndromes of every codeword in both planes and, if `correct`
+/// is set, fixes single byte errors.
+fn run_pass(
+    sector: &mut [u8],
+    codewords: usize,
+    length: usize,
+    word_at: fn(usize, usize) -> usize,
+    correct: bool,
+) -> PassResult {
+    let (log_table, ilog_table) = (&GF8_LOG, &GF8_ILOG);
+    let mut result = PassResult::default();
+
+    for plane in 0..2 {
+        for i in 0..codewords {
+            let mut s0 = 0u8;
+            let mut s1 = 0u8;
+
+            for k in 0..length {
+                let byte = sector[byte_offset(word_at(i, k), plane)];
+                s0 ^= byte;
+                if byte != 0 {
+                    let exponent = (log_table[byte as usize] as usize + length - 1 - k) % 255;
+                    s1 ^= ilog_table[exponent];
+                }
+            }
+
+            if s0 == 0 && s1 == 0 {
+                continue;
+            }
+
+            result.errors += 1;
+
+            // A single error e at position k gives s0 = e and s1 = e * a^(n-1-k)
+            if !correct || s0 == 0 || s1 == 0 {
+                continue;
+            }
+
+            let distance =
+                (log_table[s1 as usize] as usize + 255 - log_table[s0 as usize] as usize) % 255;
+            if distance >= length {
+                continue;
+            }
+
+            let k = length - 1 - distance;
+            sector[byte_offset(word_at(i, k), plane)] ^= s0;
+            result.corrected += 1;
+        }
+    }
+
+    result
+}
+
+fn byte_offset(word: usize, plane: usize) -> usize {
+    HEADER_OFFSET + 2 * word + plane
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::convert_iso_to_raw;
+
+    fn test_sector() -> Vec<u8> {
+        let data: Vec<u8> = (0..2048).map(|i| (i * 7 % 256) as u8).collect();
+        convert_iso_to_raw(1234, &data).unwrap()
+    }
+
+    #[test]
+    fn test_clean_sector_has_zero_syndromes() {
+        let mut sector = test_sector();
+        let correction = correct_pq_parity(&mut sector);
+
+        assert!(correction.consistent);
+        assert_eq!(correction.bytes_corrected, 0);
+    }
+
+    #[test]
+    fn test_corrects_scattered_errors() {
+        let original = test_sector();
+        let mut sector = original.clone();
+
+        for offset in [12, 15, 100, 101, 1500, 2064, 2100, 2300, 2351] {
+            sector[offset] ^= 0xA5;
+        }
+
+        let correction = correct_pq_parity(&mut sector);
+        assert!(correction.consistent);
+        assert_eq!(correction.bytes_corrected, 9);
+        assert_eq!(sector, original);
+    }
+
+    #[test]
+    fn test_corrects_burst_with_iteration() {
+        let original = test_sector();
+        let mut sector = original.clone();
+
+        // A burst over several rows leaves P columns with multiple errors
+        // that only become correctable after the Q pass
+        sector[200..300].iter_mut().for_each(|byte| *byte ^= 0xFF);
+
+        let correction = correct_pq_parity(&mut sector);
+        assert!(correction.consistent);
+        assert_eq!(sector, original);
+    }
+}
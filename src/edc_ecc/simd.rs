@@ -0,0 +1,239 @@
+// SIMD backend for P/Q parity
+//
+// Both parities are sums of GF(2^8) products of sector bytes with one
+// coefficient pair per row. A product splits by nibble,
+//   c * x = c * (x & 0x0F) ^ c * (x & 0xF0),
+// so two 16-entry tables per coefficient let PSHUFB multiply 16 bytes at
+// once. P rows are contiguous in the sector; Q diagonals are gathered into a
+// contiguous row first. The nibble tables are cut from the scalar product
+// table, so both backends produce identical parity.
+
+/// Implementation used for P/Q parity on this CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParityBackend {
+    Scalar,
+    Ssse3,
+}
+
+/// Backend that `calc_p_parity` and `calc_q_parity` select at runtime.
+pub fn parity_backend() -> ParityBackend {
+    #[cfg(target_arch = "x86_64")]
+    if ssse3_available() {
+        return ParityBackend::Ssse3;
+    }
+
+    ParityBackend::Scalar
+}
+
+#[cfg(target_arch = "x86_64")]
+pub(super) use x86::*;
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use core::arch::x86_64::*;
+
+    use crate::edc_ecc::edcre::GF8_Q_COEFFS_TABLE;
+
+    const HEADER_OFFSET: usize = 12;
+    const P_PARITY_OFFSET: usize = 2076;
+    const Q_PARITY_OFFSET: usize = 2248;
+    const P_ROW_BYTES: usize = 86; // 43 columns in two planes
+    const Q_ROW_BYTES: usize = 52; // 26 diagonals in two planes
+    const P_VECTORS: usize = P_ROW_BYTES.div_ceil(16);
+    const Q_VECTORS: usize = Q_ROW_BYTES.div_ceil(16);
+
+    /// Byte offset of every word of every Q row: row `j` holds element `j`
+    /// of each of the 26 diagonals.
+    const Q_GATHER: [[u16; 26]; 43] = q_gather_offsets();
+
+    const fn q_gather_offsets() -> [[u16; 26]; 43] {
+        let mut offsets = [[0u16; 26]; 43];
+        let mut j = 0;
+        while j < 43 {
+            let mut i = 0;
+            while i < 26 {
+                let word = (43 * i + 44 * j) % 1118;
+                offsets[j][i] = (HEADER_OFFSET + 2 * word) as u16;
+                i += 1;
+            }
+            j += 1;
+        }
+        offsets
+    }
+
+    /// Products of one coefficient pair with every low and high nibble.
+    struct NibbleTables {
+        low: [[u8; 16]; 2],
+        high: [[u8; 16]; 2],
+    }
+
+    static NIBBLE_TABLES: [NibbleTables; 43] = nibble_tables();
+
+    const fn nibble_tables() -> [NibbleTables; 43] {
+        let mut tables = [const {
+            NibbleTables {
+                low: [[0; 16]; 2],
+                high: [[0; 16]; 2],
+            }
+        }; 43];
+
+        let mut j = 0;
+        while j < 43 {
+            let mut parity = 0;
+            while parity < 2 {
+                let mut n = 0;
+                while n < 16 {
+                    let low = GF8_Q_COEFFS_TABLE[j][n];
+                    let high = GF8_Q_COEFFS_TABLE[j][n << 4];
+                    tables[j].low[parity][n] = (low >> (8 * parity)) as u8;
+                    tables[j].high[parity][n] = (high >> (8 * parity)) as u8;
+                    n += 1;
+                }
+                parity += 1;
+            }
+            j += 1;
+        }
+
+        tables
+    }
+
+    /// Without `std` there is no runtime detection, so SSSE3 is only used
+    /// when the build targets it (`-C target-feature=+ssse3`).
+    pub fn ssse3_available() -> bool {
+        #[cfg(feature = "std")]
+        return std::is_x86_feature_detected!("ssse3");
+
+        #[cfg(not(feature = "std"))]
+        return cfg!(target_feature = "ssse3");
+    }
+
+    /// Adds the products of `row` with both coefficients of `tables` to the
+    /// accumulators. `row` must hold at least `N` vectors.
+    #[target_feature(enable = "ssse3")]
+    unsafe fn multiply_accumulate<const N: usize>(
+        accumulators: &mut [[__m128i; N]; 2],
+        row: &[u8],
+        tables: &NibbleTables,
+    ) {
+        assert!(row.len() >= N * 16);
+        let mask = _mm_set1_epi8(0x0F);
+
+        for (parity, accumulator) in accumulators.iter_mut().enumerate() {
+            let low = _mm_loadu_si128(tables.low[parity].as_ptr().cast());
+            let high = _mm_loadu_si128(tables.high[parity].as_ptr().cast());
+
+            for (v, sum) in accumulator.iter_mut().enumerate() {
+                let bytes = _mm_loadu_si128(row.as_ptr().add(v * 16).cast());
+                let low_nibbles = _mm_and_si128(bytes, mask);
+                let high_nibbles = _mm_and_si128(_mm_srli_epi16(bytes, 4), mask);
+                let product = _mm_xor_si128(
+                    _mm_shuffle_epi8(low, low_nibbles),
+                    _mm_shuffle_epi8(high, high_nibbles),
+                );
+                *sum = _mm_xor_si128(*sum, product);
+            }
+        }
+    }
+
+    /// Stores the first `len` bytes of both accumulators: parity byte 1 at
+    /// `offset` and parity byte 0 right after it.
+    #[target_feature(enable = "ssse3")]
+    unsafe fn store_parity<const N: usize>(
+        sector: &mut [u8],
+        accumulators: &[[__m128i; N]; 2],
+        offset: usize,
+        len: usize,
+    ) {
+        for (parity, destination) in [(1, offset), (0, offset + len)] {
+            let mut bytes = [[0u8; 16]; N];
+            for (v, sum) in accumulators[parity].iter().enumerate() {
+                _mm_storeu_si128(bytes[v].as_mut_ptr().cast(), *sum);
+            }
+            sector[destination..destination + len].copy_from_slice(&bytes.as_flattened()[..len]);
+        }
+    }
+
+    /// # Safety
+    ///
+    /// The CPU must support SSSE3.
+    #[target_feature(enable = "ssse3")]
+    pub unsafe fn calc_p_parity_ssse3(sector: &mut [u8]) {
+        let tables = &NIBBLE_TABLES;
+        let mut accumulators = [[_mm_setzero_si128(); P_VECTORS]; 2];
+
+        // Row k of the 24 data rows uses coefficient 19 + k. The vectors of
+        // a row run a few bytes into the next one; those lanes are dropped.
+        for (k, tables) in tables[19..].iter().enumerate() {
+            let start = HEADER_OFFSET + k * P_ROW_BYTES;
+            multiply_accumulate(
+                &mut accumulators,
+                &sector[start..start + P_VECTORS * 16],
+                tables,
+            );
+        }
+
+        store_parity(sector, &accumulators, P_PARITY_OFFSET, P_ROW_BYTES);
+    }
+
+    /// # Safety
+    ///
+    /// The CPU must support SSSE3.
+    #[target_feature(enable = "ssse3")]
+    pub unsafe fn calc_q_parity_ssse3(sector: &mut [u8]) {
+        let tables = &NIBBLE_TABLES;
+        let mut accumulators = [[_mm_setzero_si128(); Q_VECTORS]; 2];
+        let mut row = [0u8; Q_VECTORS * 16];
+
+        for (offsets, tables) in Q_GATHER.iter().zip(tables) {
+            for (word, &offset) in row.chunks_exact_mut(2).zip(offsets) {
+                let offset = offset as usize;
+                word.copy_from_slice(&sector[offset..offset + 2]);
+            }
+            multiply_accumulate(&mut accumulators, &row, tables);
+        }
+
+        store_parity(sector, &accumulators, Q_PARITY_OFFSET, Q_ROW_BYTES);
+    }
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+    use super::*;
+    use crate::edc_ecc::{calc_p_parity_scalar, calc_q_parity_scalar};
+
+    /// Pseudo-random sector contents from a fixed seed.
+    fn noise_sector(seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..2352)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ssse3_parity_matches_scalar() {
+        if !ssse3_available() {
+            return;
+        }
+
+        for seed in 1..=64 {
+            let mut scalar = noise_sector(seed);
+            let mut simd = scalar.clone();
+
+            calc_p_parity_scalar(&mut scalar);
+            calc_q_parity_scalar(&mut scalar);
+            unsafe {
+                calc_p_parity_ssse3(&mut simd);
+                calc_q_parity_ssse3(&mut simd);
+            }
+
+            assert!(scalar == simd, "seed {seed}");
+        }
+
+        assert_eq!(parity_backend(), ParityBackend::Ssse3);
+    }
+}
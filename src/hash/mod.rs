@@ -0,0 +1,197 @@
+// Output image hashes
+//
+// Redump DATs identify a dump by the size, CRC32, MD5 and SHA-1 of each BIN.
+// ImageHasher computes all three as the image is written, so matching a
+// conversion against a DAT needs no second pass over the file. The sidecar
+// formats are the ones read by common checksum tools.
+
+use anyhow::{Context, Result};
+use md5::{Digest, Md5};
+use sha1::Sha1;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::io::companion_path;
+
+/// Buffers at least this large are hashed by the three algorithms in parallel.
+const PARALLEL_THRESHOLD: usize = 64 * 1024;
+
+/// Size and hashes of a written image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHashes {
+    pub size: u64,
+    pub crc32: u32,
+    pub md5: [u8; 16],
+    pub sha1: [u8; 20],
+}
+
+impl ImageHashes {
+    pub fn crc32_hex(&self) -> String {
+        format!("{:08x}", self.crc32)
+    }
+
+    pub fn md5_hex(&self) -> String {
+        hex(&self.md5)
+    }
+
+    pub fn sha1_hex(&self) -> String {
+        hex(&self.sha1)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, byte| {
+        let _ = write!(out, "{byte:02x}");
+        out
+    })
+}
+
+/// Computes CRC32, MD5 and SHA-1 of a stream of bytes.
+#[derive(Clone, Default)]
+pub struct ImageHasher {
+    size: u64,
+    crc32: crc32fast::Hasher,
+    md5: Md5,
+    sha1: Sha1,
+}
+
+impl ImageHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.size += data.len() as u64;
+
+        if data.len() < PARALLEL_THRESHOLD {
+            self.crc32.update(data);
+            self.md5.update(data);
+            self.sha1.update(data);
+            return;
+        }
+
+        let Self {
+            crc32, md5, sha1, ..
+        } = self;
+        rayon::join(
+            || rayon::join(|| crc32.update(data), || md5.update(data)),
+            || sha1.update(data),
+        );
+    }
+
+    pub fn finish(self) -> ImageHashes {
+        ImageHashes {
+            size: self.size,
+            crc32: self.crc32.finalize(),
+            md5: self.md5.finalize().into(),
+            sha1: self.sha1.finalize().into(),
+        }
+    }
+}
+
+/// Checksum file written next to an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SidecarFormat {
+    /// `name CRC32`, as read by SFV checkers
+    Sfv,
+    /// `md5  name`, as read by `md5sum -c`
+    Md5,
+    /// `sha1  name`, as read by `sha1sum -c`
+    Sha1,
+}
+
+impl SidecarFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Sfv => "sfv",
+            Self::Md5 => "md5",
+            Self::Sha1 => "sha1",
+        }
+    }
+
+    /// Contents of the sidecar for an image called `file_name`.
+    pub fn contents(self, file_name: &str, hashes: &ImageHashes) -> String {
+        match self {
+            Self::Sfv => format!(
+                "; Generated by iso2raw\n{file_name} {}\n",
+                hashes.crc32_hex().to_uppercase()
+            ),
+            Self::Md5 => format!("{}  {file_name}\n", hashes.md5_hex()),
+            Self::Sha1 => format!("{}  {file_name}\n", hashes.sha1_hex()),
+        }
+    }
+
+    /// Writes the sidecar next to `image_path`, with the image's extension
+    /// replaced, and returns its path.
+    pub fn write(self, image_path: &Path, hashes: &ImageHashes) -> Result<PathBuf> {
+        let file_name = image_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .with_context(|| format!("Invalid image file name: {}", image_path.display()))?;
+
+        let sidecar_path = companion_path(image_path, self.extension())?;
+        std::fs::write(&sidecar_path, self.contents(&file_name, hashes)).with_context(|| {
+            format!("Failed to write checksum file: {}", sidecar_path.display())
+        })?;
+
+        Ok(sidecar_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_hashes() {
+        let mut hasher = ImageHasher::new();
+        hasher.update(b"The quick brown fox ");
+        hasher.update(b"jumps over the lazy dog");
+        let hashes = hasher.finish();
+
+        assert_eq!(hashes.size, 43);
+        assert_eq!(hashes.crc32_hex(), "414fa339");
+        assert_eq!(hashes.md5_hex(), "9e107d9d372bb6826bd81d3542a419d6");
+        assert_eq!(
+            hashes.sha1_hex(),
+            "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12"
+        );
+    }
+
+    #[test]
+    fn test_parallel_update_matches_sequential() {
+        let data: Vec<u8> = (0..PARALLEL_THRESHOLD * 3)
+            .map(|i| (i % 251) as u8)
+            .collect();
+
+        let mut parallel = ImageHasher::new();
+        parallel.update(&data);
+
+        let mut sequential = ImageHasher::new();
+        for chunk in data.chunks(PARALLEL_THRESHOLD / 2) {
+            sequential.update(chunk);
+        }
+
+        assert_eq!(parallel.finish(), sequential.finish());
+    }
+
+    #[test]
+    fn test_sidecar_contents() {
+        let mut hasher = ImageHasher::new();
+        hasher.update(b"abc");
+        let hashes = hasher.finish();
+
+        assert_eq!(
+            SidecarFormat::Sfv.contents("game.bin", &hashes),
+            "; Generated by iso2raw\ngame.bin 352441C2\n"
+        );
+        assert_eq!(
+            SidecarFormat::Md5.contents("game.bin", &hashes),
+            "900150983cd24fb0d6963f7d28e17f72  game.bin\n"
+        );
+        assert_eq!(
+            SidecarFormat::Sha1.contents("game.bin", &hashes),
+            "a9993e364706816aba3e25717850c26c9cd0d89d  game.bin\n"
+        );
+    }
+}
@@ -1,62 +1,286 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use indicatif::{ProgressBar, ProgressStyle};
-use memmap2::{Mmap, MmapOptions};
+use memmap2::{Mmap, MmapMut, MmapOptions};
 use std::fs::{File, OpenOptions};
-use std::io::{BufWriter, Write};
-use std::path::Path;
+use std::io::{self as stdio, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
-use crate::converter::{ISO_SECTOR_SIZE, RAW_SECTOR_SIZE};
+use crate::audio::{audio_sector_count, parse_wav};
+use crate::converter::{
+    detect_layout, extract_user_data, InputLayout, ISO_SECTOR_SIZE, RAW_SECTOR_SIZE,
+};
+use crate::hash::{ImageHasher, ImageHashes};
+use crate::subchannel::{SubchannelGenerator, SUBCHANNEL_SIZE};
 
 pub struct IsoReader {
     mmap: Mmap,
+    layout: InputLayout,
+    sector_size: usize,
     total_sectors: usize,
 }
 
 impl IsoReader {
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let file = File::open(&path)
-            .with_context(|| format!("Failed to open ISO file: {}", path.as_ref().display()))?;
+        Self::with_sector_size(path, ISO_SECTOR_SIZE)
+    }
+
+    /// Opens an input made of fixed-size sectors other than 2048 bytes,
+    /// such as 2324- or 2336-byte Mode 2 payload streams.
+    ///
+    /// Inputs that turn out to be RAW images are opened with their own
+    /// sector size; see [`IsoReader::layout`].
+    pub fn with_sector_size<P: AsRef<Path>>(path: P, sector_size: usize) -> Result<Self> {
+        let path = path.as_ref();
+        let mmap = map_file(path, "ISO")?;
 
-        let metadata = file.metadata()?;
-        let file_size = metadata.len() as usize;
+        let layout = detect_layout(&mmap);
+        let sector_size = if layout.is_raw() {
+            layout.sector_size()
+        } else {
+            sector_size
+        };
 
-        if !file_size.is_multiple_of(ISO_SECTOR_SIZE) {
+        if !mmap.len().is_multiple_of(sector_size) {
+            if layout == InputLayout::Mode2Payload {
+                anyhow::bail!(
+                    "Invalid ISO file size: {} looks like {}-byte Mode 2 payloads",
+                    path.display(),
+                    layout.sector_size()
+                );
+            }
             anyhow::bail!(
                 "Invalid ISO file size: {} is not a multiple of {}",
-                file_size,
-                ISO_SECTOR_SIZE
+                mmap.len(),
+                sector_size
             );
         }
 
-        let mmap = unsafe {
-            MmapOptions::new()
-                .map(&file)
-                .with_context(|| "Failed to memory-map ISO file")?
-        };
+        let total_sectors = mmap.len() / sector_size;
 
         Ok(Self {
             mmap,
-            total_sectors: file_size / ISO_SECTOR_SIZE,
+            layout,
+            sector_size,
+            total_sectors,
         })
     }
 
+    /// Layout detected from the first sectors of the input.
+    pub fn layout(&self) -> InputLayout {
+        self.layout
+    }
+
+    pub fn sector_size(&self) -> usize {
+        self.sector_size
+    }
+
     pub fn total_sectors(&self) -> usize {
         self.total_sectors
     }
 
+    /// Returns the user data of a sector. For RAW inputs this is the payload
+    /// of the raw sector, or `None` if it carries no user data.
     pub fn read_sector(&self, sector_index: usize) -> Option<&[u8]> {
+        let sector = self.read_stored_sector(sector_index)?;
+
+        if self.layout.is_raw() {
+            extract_user_data(&sector[..RAW_SECTOR_SIZE]).map(|(_, data)| data)
+        } else {
+            Some(sector)
+        }
+    }
+
+    /// Returns the 2352 bytes of a sector of a RAW input, without any
+    /// subchannel data.
+    pub fn read_raw_sector(&self, sector_index: usize) -> Option<&[u8]> {
+        if !self.layout.is_raw() {
+            return None;
+        }
+
+        self.read_stored_sector(sector_index)
+            .map(|sector| &sector[..RAW_SECTOR_SIZE])
+    }
+
+    fn read_stored_sector(&self, sector_index: usize) -> Option<&[u8]> {
         if sector_index >= self.total_sectors {
             return None;
         }
 
-        let offset = sector_index * ISO_SECTOR_SIZE;
-        Some(&self.mmap[offset..offset + ISO_SECTOR_SIZE])
+        let offset = sector_index * self.sector_size;
+        Some(&self.mmap[offset..offset + self.sector_size])
     }
 }
 
+/// Reads fixed-size sectors from a source that cannot be memory-mapped,
+/// such as stdin or a pipe.
+pub struct SectorStream {
+    reader: Box<dyn Read + Send>,
+    sector_size: usize,
+}
+
+impl SectorStream {
+    pub fn new<R: Read + Send + 'static>(reader: R, sector_size: usize) -> Self {
+        Self {
+            reader: Box::new(reader),
+            sector_size,
+        }
+    }
+
+    /// Reads up to `max_sectors` whole sectors. An empty result marks the end
+    /// of the input.
+    pub fn read_batch(&mut self, max_sectors: usize) -> Result<Vec<u8>> {
+        let buffer = self.read_partial_batch(max_sectors)?;
+        self.check_whole_sectors(&buffer)?;
+        Ok(buffer)
+    }
+
+    /// Reads up to `max_sectors` sectors' worth of bytes, which may end with
+    /// a partial sector if the input does. Lets the caller look at a short
+    /// input before [`Self::check_whole_sectors`] rejects it.
+    pub fn read_partial_batch(&mut self, max_sectors: usize) -> Result<Vec<u8>> {
+        let mut buffer = vec![0u8; max_sectors * self.sector_size];
+        let mut filled = 0;
+
+        while filled < buffer.len() {
+            match self.reader.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == stdio::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err).context("Failed to read input"),
+            }
+        }
+
+        buffer.truncate(filled);
+        Ok(buffer)
+    }
+
+    pub fn check_whole_sectors(&self, batch: &[u8]) -> Result<()> {
+        if !batch.len().is_multiple_of(self.sector_size) {
+            anyhow::bail!(
+                "Input ends with a partial sector: {} bytes left over from {}-byte sectors",
+                batch.len() % self.sector_size,
+                self.sector_size
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Reader for 2352-byte RAW images (BIN files).
+pub struct RawReader {
+    mmap: Mmap,
+    total_sectors: usize,
+}
+
+impl RawReader {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mmap = map_sector_file(path.as_ref(), RAW_SECTOR_SIZE, "RAW")?;
+        let total_sectors = mmap.len() / RAW_SECTOR_SIZE;
+
+        Ok(Self {
+            mmap,
+            total_sectors,
+        })
+    }
+
+    pub fn total_sectors(&self) -> usize {
+        self.total_sectors
+    }
+
+    pub fn read_sector(&self, sector_index: usize) -> Option<&[u8]> {
+        if sector_index >= self.total_sectors {
+            return None;
+        }
+
+        let offset = sector_index * RAW_SECTOR_SIZE;
+        Some(&self.mmap[offset..offset + RAW_SECTOR_SIZE])
+    }
+}
+
+/// Memory-mapped audio track source: raw little-endian PCM, or the data
+/// chunk of a WAVE file.
+pub struct PcmReader {
+    mmap: Mmap,
+    data: Range<usize>,
+}
+
+impl PcmReader {
+    /// Maps a file of raw 16-bit little-endian stereo PCM.
+    pub fn raw<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mmap = map_file(path.as_ref(), "PCM")?;
+        let data = 0..mmap.len();
+
+        Ok(Self { mmap, data })
+    }
+
+    /// Maps a WAVE file, checking that it holds CD audio as [`parse_wav`]
+    /// does.
+    pub fn wav<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mmap = map_file(path, "WAVE")?;
+
+        let wav =
+            parse_wav(&mmap).with_context(|| format!("Invalid WAVE file: {}", path.display()))?;
+        // The data chunk borrows from the map, so its offset is the
+        // distance between the two
+        let start = wav.data.as_ptr() as usize - mmap.as_ptr() as usize;
+        let data = start..start + wav.data.len();
+
+        Ok(Self { mmap, data })
+    }
+
+    pub fn pcm(&self) -> &[u8] {
+        &self.mmap[self.data.clone()]
+    }
+
+    pub fn total_sectors(&self) -> usize {
+        audio_sector_count(self.data.len())
+    }
+}
+
+fn map_sector_file(path: &Path, sector_size: usize, kind: &str) -> Result<Mmap> {
+    let mmap = map_file(path, kind)?;
+
+    if !mmap.len().is_multiple_of(sector_size) {
+        anyhow::bail!(
+            "Invalid {} file size: {} is not a multiple of {}",
+            kind,
+            mmap.len(),
+            sector_size
+        );
+    }
+
+    Ok(mmap)
+}
+
+fn map_file(path: &Path, kind: &str) -> Result<Mmap> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open {kind} file: {}", path.display()))?;
+
+    let mmap = unsafe {
+        MmapOptions::new()
+            .map(&file)
+            .with_context(|| format!("Failed to memory-map {kind} file"))?
+    };
+
+    Ok(mmap)
+}
+
 pub struct RawWriter {
-    writer: BufWriter<File>,
+    writer: BufWriter<Box<dyn Write + Send>>,
     sectors_written: usize,
+    bytes_written: u64,
+    subchannel: Option<(SubchannelGenerator, SubchannelSink)>,
+    hasher: Option<ImageHasher>,
+}
+
+/// Where the subchannel of each written sector goes.
+enum SubchannelSink {
+    /// Separate `.sub` file with 96 bytes per sector
+    File(BufWriter<File>),
+    /// Appended to every sector, giving 2448-byte sectors
+    Interleaved,
 }
 
 impl RawWriter {
@@ -70,13 +294,339 @@ impl RawWriter {
                 format!("Failed to create output file: {}", path.as_ref().display())
             })?;
 
+        Ok(Self::from_writer(file))
+    }
+
+    /// Writes RAW sectors to any destination, such as stdout.
+    pub fn from_writer<W: Write + Send + 'static>(writer: W) -> Self {
+        Self {
+            writer: BufWriter::with_capacity(1024 * 1024, Box::new(writer)), // 1MB buffer
+            sectors_written: 0,
+            bytes_written: 0,
+            subchannel: None,
+            hasher: None,
+        }
+    }
+
+    /// Writes the subchannel of every sector to a separate `.sub` file.
+    pub fn with_subchannel_file<P: AsRef<Path>>(
+        mut self,
+        generator: SubchannelGenerator,
+        path: P,
+    ) -> Result<Self> {
+        let file = File::create(&path).with_context(|| {
+            format!(
+                "Failed to create subchannel file: {}",
+                path.as_ref().display()
+            )
+        })?;
+
+        let sink = SubchannelSink::File(BufWriter::with_capacity(64 * 1024, file));
+        self.subchannel = Some((generator, sink));
+        Ok(self)
+    }
+
+    /// Appends the interleaved subchannel to every sector.
+    pub fn with_interleaved_subchannel(mut self, generator: SubchannelGenerator) -> Self {
+        self.subchannel = Some((generator, SubchannelSink::Interleaved));
+        self
+    }
+
+    /// Hashes everything written to the output; see [`Self::finish`].
+    pub fn with_hashing(mut self) -> Self {
+        self.hasher = Some(ImageHasher::new());
+        self
+    }
+
+    fn write_output(&mut self, data: &[u8]) -> Result<()> {
+        self.writer.write_all(data)?;
+        self.bytes_written += data.len() as u64;
+
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(data);
+        }
+
+        Ok(())
+    }
+
+    pub fn write_sector(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() != RAW_SECTOR_SIZE {
+            anyhow::bail!(
+                "Invalid RAW sector size: expected {}, got {}",
+                RAW_SECTOR_SIZE,
+                data.len()
+            );
+        }
+
+        self.write_output(data)?;
+
+        if let Some((generator, sink)) = &mut self.subchannel {
+            let subchannel = generator.generate(self.sectors_written as u32);
+            match sink {
+                SubchannelSink::File(sub_writer) => sub_writer.write_all(&subchannel.to_bytes())?,
+                SubchannelSink::Interleaved => self.write_output(&subchannel.to_interleaved())?,
+            }
+        }
+
+        self.sectors_written += 1;
+        Ok(())
+    }
+
+    /// Writes consecutive 2352-byte sectors from one buffer. Without
+    /// interleaved subchannel data the whole buffer goes out in one write.
+    pub fn write_sectors(&mut self, data: &[u8]) -> Result<()> {
+        if !data.len().is_multiple_of(RAW_SECTOR_SIZE) {
+            anyhow::bail!(
+                "Invalid RAW buffer size: {} is not a multiple of {}",
+                data.len(),
+                RAW_SECTOR_SIZE
+            );
+        }
+
+        if self.subchannel.is_some() {
+            return data
+                .chunks_exact(RAW_SECTOR_SIZE)
+                .try_for_each(|sector| self.write_sector(sector));
+        }
+
+        self.write_output(data)?;
+        self.sectors_written += data.len() / RAW_SECTOR_SIZE;
+        Ok(())
+    }
+
+    /// Bytes written to the output, not counting a separate `.sub` file.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Flushes buffered sectors, reporting errors that dropping would hide.
+    /// Returns the hashes of the output if hashing was enabled.
+    pub fn finish(mut self) -> Result<Option<ImageHashes>> {
+        self.writer.flush().context("Failed to write output")?;
+
+        if let Some((_, SubchannelSink::File(sub_writer))) = &mut self.subchannel {
+            sub_writer
+                .flush()
+                .context("Failed to write subchannel file")?;
+        }
+
+        Ok(self.hasher.map(ImageHasher::finish))
+    }
+}
+
+/// RAW output preallocated to its final size and memory-mapped, so workers
+/// can encode straight into disjoint sector ranges of the file. Sectors are
+/// `sector_stride()` bytes apart: 2352, or 2448 with interleaved subchannel.
+/// Dropping the writer before every sector is committed truncates the file
+/// to the committed prefix, like a partially written stream; [`Self::abort`]
+/// does the same and reports a failure to truncate.
+pub struct MmapRawWriter {
+    file: File,
+    /// Unmapped (`None`) before the file is resized or truncated, which
+    /// Windows refuses while it is mapped
+    mmap: Option<MmapMut>,
+    total_sectors: usize,
+    sector_stride: usize,
+    sectors_written: usize,
+    subchannel: Option<(SubchannelGenerator, SubchannelSink)>,
+    hasher: Option<ImageHasher>,
+}
+
+impl MmapRawWriter {
+    pub fn create<P: AsRef<Path>>(path: P, total_sectors: usize) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| {
+                format!("Failed to create output file: {}", path.as_ref().display())
+            })?;
+
+        let mmap = map_output(&file, total_sectors * RAW_SECTOR_SIZE)?;
+
         Ok(Self {
-            writer: BufWriter::with_capacity(1024 * 1024, file), // 1MB buffer
+            file,
+            mmap: Some(mmap),
+            total_sectors,
+            sector_stride: RAW_SECTOR_SIZE,
             sectors_written: 0,
+            subchannel: None,
+            hasher: None,
         })
     }
 
-    pub fn write_sector(&mut self, data: &[u8]) -> Result<()> {
+    /// Writes the subchannel of every sector to a separate `.sub` file.
+    pub fn with_subchannel_file<P: AsRef<Path>>(
+        mut self,
+        generator: SubchannelGenerator,
+        path: P,
+    ) -> Result<Self> {
+        let file = File::create(&path).with_context(|| {
+            format!(
+                "Failed to create subchannel file: {}",
+                path.as_ref().display()
+            )
+        })?;
+
+        let sink = SubchannelSink::File(BufWriter::with_capacity(64 * 1024, file));
+        self.subchannel = Some((generator, sink));
+        Ok(self)
+    }
+
+    /// Appends the interleaved subchannel to every sector, growing the file
+    /// to 2448-byte sectors.
+    pub fn with_interleaved_subchannel(mut self, generator: SubchannelGenerator) -> Result<Self> {
+        self.sector_stride = RAW_SECTOR_SIZE + SUBCHANNEL_SIZE;
+        self.mmap = None;
+        self.mmap = Some(map_output(
+            &self.file,
+            self.total_sectors * self.sector_stride,
+        )?);
+        self.subchannel = Some((generator, SubchannelSink::Interleaved));
+        Ok(self)
+    }
+
+    /// Hashes the sectors as they are committed; see [`Self::finish`].
+    pub fn with_hashing(mut self) -> Self {
+        self.hasher = Some(ImageHasher::new());
+        self
+    }
+
+    pub fn total_sectors(&self) -> usize {
+        self.total_sectors
+    }
+
+    pub fn sector_stride(&self) -> usize {
+        self.sector_stride
+    }
+
+    /// Bytes of `range`, `sector_stride()` per sector, of which the first
+    /// 2352 of each are the sector itself.
+    pub fn sectors_mut(&mut self, range: Range<usize>) -> &mut [u8] {
+        let bytes = range.start * self.sector_stride..range.end * self.sector_stride;
+        &mut mapped(&mut self.mmap)[bytes]
+    }
+
+    /// Marks the sectors of `range` as written, adding their subchannel data.
+    /// Ranges must be committed in order.
+    pub fn commit(&mut self, range: Range<usize>) -> Result<()> {
+        if let Some((generator, sink)) = &mut self.subchannel {
+            for index in range.clone() {
+                let subchannel = generator.generate(index as u32);
+                match sink {
+                    SubchannelSink::File(sub_writer) => {
+                        sub_writer.write_all(&subchannel.to_bytes())?
+                    }
+                    SubchannelSink::Interleaved => {
+                        let offset = index * self.sector_stride + RAW_SECTOR_SIZE;
+                        mapped(&mut self.mmap)[offset..offset + SUBCHANNEL_SIZE]
+                            .copy_from_slice(&subchannel.to_interleaved());
+                    }
+                }
+            }
+        }
+
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(
+                &mapped(&mut self.mmap)
+                    [range.start * self.sector_stride..range.end * self.sector_stride],
+            );
+        }
+
+        self.sectors_written = range.end;
+        Ok(())
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        (self.sectors_written * self.sector_stride) as u64
+    }
+
+    /// Flushes the mapped sectors and any subchannel file to disk. Returns
+    /// the hashes of the output if hashing was enabled.
+    pub fn finish(mut self) -> Result<Option<ImageHashes>> {
+        mapped(&mut self.mmap)
+            .flush()
+            .context("Failed to write output")?;
+
+        if let Some((_, SubchannelSink::File(sub_writer))) = &mut self.subchannel {
+            sub_writer
+                .flush()
+                .context("Failed to write subchannel file")?;
+        }
+
+        Ok(self.hasher.take().map(ImageHasher::finish))
+    }
+
+    /// Gives up on the remaining sectors, truncating the file to those
+    /// committed so far so that it cannot pass for a finished image.
+    pub fn abort(mut self) -> Result<()> {
+        self.truncate_uncommitted()
+    }
+
+    fn truncate_uncommitted(&mut self) -> Result<()> {
+        if self.sectors_written == self.total_sectors {
+            return Ok(());
+        }
+
+        self.mmap = None;
+        let len = self.bytes_written();
+        self.file
+            .set_len(len)
+            .with_context(|| format!("Failed to truncate the unfinished output to {len} bytes"))?;
+        self.total_sectors = self.sectors_written;
+        Ok(())
+    }
+}
+
+impl Drop for MmapRawWriter {
+    fn drop(&mut self) {
+        // Nothing to report to while dropping; callers that need to know
+        // use abort()
+        let _ = self.truncate_uncommitted();
+    }
+}
+
+fn mapped(mmap: &mut Option<MmapMut>) -> &mut MmapMut {
+    mmap.as_mut()
+        .expect("output stays mapped until the writer is dropped or aborted")
+}
+
+fn map_output(file: &File, len: usize) -> Result<MmapMut> {
+    file.set_len(len as u64)
+        .context("Failed to preallocate output file")?;
+
+    let mmap = unsafe {
+        MmapOptions::new()
+            .map_mut(file)
+            .context("Failed to memory-map output file")?
+    };
+
+    Ok(mmap)
+}
+
+/// Rewrites individual sectors of an existing RAW image in place.
+pub struct RawPatcher {
+    file: File,
+}
+
+impl RawPatcher {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .with_context(|| {
+                format!(
+                    "Failed to open RAW file for writing: {}",
+                    path.as_ref().display()
+                )
+            })?;
+
+        Ok(Self { file })
+    }
+
+    pub fn write_sector(&mut self, sector_index: usize, data: &[u8]) -> Result<()> {
         if data.len() != RAW_SECTOR_SIZE {
             anyhow::bail!(
                 "Invalid RAW sector size: expected {}, got {}",
@@ -85,10 +635,62 @@ impl RawWriter {
             );
         }
 
+        self.file
+            .seek(SeekFrom::Start((sector_index * RAW_SECTOR_SIZE) as u64))?;
+        self.file.write_all(data)?;
+        Ok(())
+    }
+}
+
+/// Writer for cooked user data extracted from RAW sectors.
+pub struct IsoWriter {
+    writer: BufWriter<File>,
+    bytes_written: u64,
+}
+
+impl IsoWriter {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .with_context(|| {
+                format!("Failed to create output file: {}", path.as_ref().display())
+            })?;
+
+        Ok(Self {
+            writer: BufWriter::with_capacity(1024 * 1024, file), // 1MB buffer
+            bytes_written: 0,
+        })
+    }
+
+    pub fn write_sector(&mut self, data: &[u8]) -> Result<()> {
         self.writer.write_all(data)?;
-        self.sectors_written += 1;
+        self.bytes_written += data.len() as u64;
         Ok(())
     }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+}
+
+/// Path of a file written next to `output` with the extension replaced, such
+/// as the `.cue` or `.sub` of a BIN. An output that already has `extension`
+/// is refused, as the companion file would overwrite it.
+pub fn companion_path(output: &Path, extension: &str) -> Result<PathBuf> {
+    let same = output
+        .extension()
+        .is_some_and(|existing| existing.eq_ignore_ascii_case(extension));
+    if same {
+        bail!(
+            "The .{extension} file would overwrite the output {}; choose another output extension",
+            output.display()
+        );
+    }
+
+    Ok(output.with_extension(extension))
 }
 
 pub fn create_progress_bar(total_sectors: usize) -> ProgressBar {
@@ -101,3 +703,26 @@ pub fn create_progress_bar(total_sectors: usize) -> ProgressBar {
     );
     pb
 }
+
+/// Progress bar across the files of a batch, counted in input bytes.
+pub fn create_batch_progress_bar(total_bytes: u64) -> ProgressBar {
+    let pb = ProgressBar::new(total_bytes);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} {msg} ({eta})")
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+    pb
+}
+
+/// Progress indicator for input of unknown length, such as stdin.
+pub fn create_spinner() -> ProgressBar {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.green} [{elapsed_precise}] {pos} sectors ({per_sec})")
+            .unwrap(),
+    );
+    pb
+}
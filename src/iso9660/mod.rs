@@ -0,0 +1,220 @@
+// ISO 9660 volume descriptor parsing
+//
+// The volume descriptor set starts at logical sector 16 and is a sequence of
+// 2048-byte descriptors tagged "CD001", ended by a set terminator (type 255).
+// Only the Primary Volume Descriptor (type 1) is interpreted.
+
+use anyhow::{bail, Result};
+use std::fmt;
+
+/// First sector of the volume descriptor set.
+pub const VOLUME_DESCRIPTOR_START: usize = 16;
+pub const STANDARD_IDENTIFIER: &[u8; 5] = b"CD001";
+
+const TYPE_PRIMARY: u8 = 1;
+const TYPE_TERMINATOR: u8 = 255;
+const DESCRIPTOR_SIZE: usize = 2048;
+const MAX_DESCRIPTORS: usize = 64; // guard against a missing terminator
+
+/// Date and time of a volume descriptor field, in local time of the author.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VolumeDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub hundredths: u8,
+    /// Offset from GMT in 15-minute intervals
+    pub gmt_offset: i8,
+}
+
+impl VolumeDate {
+    /// Parses the 17-byte `YYYYMMDDHHMMSScc` + offset format. All-zero dates
+    /// mean "not specified" and yield `None`.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let digits = bytes.get(..16)?;
+        if !digits.iter().all(u8::is_ascii_digit) || digits.iter().all(|&b| b == b'0') {
+            return None;
+        }
+
+        let number = |range: std::ops::Range<usize>| {
+            digits[range]
+                .iter()
+                .fold(0u16, |value, &digit| value * 10 + (digit - b'0') as u16)
+        };
+
+        Some(Self {
+            year: number(0..4),
+            month: number(4..6) as u8,
+            day: number(6..8) as u8,
+            hour: number(8..10) as u8,
+            minute: number(10..12) as u8,
+            second: number(12..14) as u8,
+            hundredths: number(14..16) as u8,
+            gmt_offset: bytes[16] as i8,
+        })
+    }
+}
+
+impl fmt::Display for VolumeDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let offset_minutes = self.gmt_offset as i32 * 15;
+        let sign = if offset_minutes < 0 { '-' } else { '+' };
+
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} {}{:02}:{:02}",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            sign,
+            offset_minutes.abs() / 60,
+            offset_minutes.abs() % 60
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrimaryVolumeDescriptor {
+    pub system_id: String,
+    pub volume_id: String,
+    /// Number of logical blocks in the volume
+    pub volume_space_size: u32,
+    pub logical_block_size: u16,
+    pub creation_date: Option<VolumeDate>,
+    pub modification_date: Option<VolumeDate>,
+}
+
+impl PrimaryVolumeDescriptor {
+    /// Parses a 2048-byte Primary Volume Descriptor.
+    pub fn parse(sector: &[u8]) -> Result<Self> {
+        if sector.len() < DESCRIPTOR_SIZE {
+            bail!("Volume descriptor too short: {} bytes", sector.len());
+        }
+
+        if &sector[1..6] != STANDARD_IDENTIFIER {
+            bail!("Missing CD001 standard identifier");
+        }
+
+        if sector[0] != TYPE_PRIMARY {
+            bail!("Not a primary volume descriptor (type {})", sector[0]);
+        }
+
+        // Both-endian fields: the little-endian half comes first
+        let volume_space_size = u32::from_le_bytes(sector[80..84].try_into().unwrap());
+        let logical_block_size = u16::from_le_bytes(sector[128..130].try_into().unwrap());
+
+        Ok(Self {
+            system_id: identifier(&sector[8..40]),
+            volume_id: identifier(&sector[40..72]),
+            volume_space_size,
+            logical_block_size,
+            creation_date: VolumeDate::parse(&sector[813..830]),
+            modification_date: VolumeDate::parse(&sector[830..847]),
+        })
+    }
+
+    /// Size of the volume in 2048-byte sectors.
+    pub fn volume_sectors(&self) -> u64 {
+        self.volume_space_size as u64 * self.logical_block_size as u64 / DESCRIPTOR_SIZE as u64
+    }
+}
+
+/// Walks the volume descriptor set of an image, reading sectors through
+/// `read_sector`, and returns its Primary Volume Descriptor.
+pub fn find_primary_volume_descriptor<'a, F>(read_sector: F) -> Result<PrimaryVolumeDescriptor>
+where
+    F: Fn(usize) -> Option<&'a [u8]>,
+{
+    for index in VOLUME_DESCRIPTOR_START..VOLUME_DESCRIPTOR_START + MAX_DESCRIPTORS {
+        let Some(sector) = read_sector(index) else {
+            break;
+        };
+
+        if &sector[1..6] != STANDARD_IDENTIFIER {
+            if index == VOLUME_DESCRIPTOR_START {
+                bail!("No CD001 volume descriptor at sector {VOLUME_DESCRIPTOR_START}");
+            }
+            break;
+        }
+
+        match sector[0] {
+            TYPE_PRIMARY => return PrimaryVolumeDescriptor::parse(sector),
+            TYPE_TERMINATOR => break,
+            _ => {}
+        }
+    }
+
+    bail!("No primary volume descriptor found")
+}
+
+/// Trims the space padding of a fixed-length identifier field.
+fn identifier(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches([' ', '\0'])
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pvd_sector() -> Vec<u8> {
+        let mut sector = vec![0u8; DESCRIPTOR_SIZE];
+        sector[0] = TYPE_PRIMARY;
+        sector[1..6].copy_from_slice(STANDARD_IDENTIFIER);
+        sector[6] = 1;
+        sector[8..40].copy_from_slice(&[b' '; 32]);
+        sector[8..19].copy_from_slice(b"PLAYSTATION");
+        sector[40..72].copy_from_slice(&[b' '; 32]);
+        sector[40..47].copy_from_slice(b"MY_GAME");
+        sector[80..84].copy_from_slice(&1234u32.to_le_bytes());
+        sector[84..88].copy_from_slice(&1234u32.to_be_bytes());
+        sector[128..130].copy_from_slice(&2048u16.to_le_bytes());
+        sector[130..132].copy_from_slice(&2048u16.to_be_bytes());
+        sector[813..829].copy_from_slice(b"1999120123595900");
+        sector[829] = 36; // GMT+9
+        sector[830..846].copy_from_slice(b"0000000000000000");
+        sector
+    }
+
+    #[test]
+    fn test_parse_pvd() {
+        let pvd = PrimaryVolumeDescriptor::parse(&pvd_sector()).unwrap();
+
+        assert_eq!(pvd.system_id, "PLAYSTATION");
+        assert_eq!(pvd.volume_id, "MY_GAME");
+        assert_eq!(pvd.volume_space_size, 1234);
+        assert_eq!(pvd.logical_block_size, 2048);
+        assert_eq!(pvd.volume_sectors(), 1234);
+        assert_eq!(
+            pvd.creation_date.unwrap().to_string(),
+            "1999-12-01 23:59:59 +09:00"
+        );
+        assert_eq!(pvd.modification_date, None);
+    }
+
+    #[test]
+    fn test_find_pvd() {
+        let mut image = vec![vec![0u8; DESCRIPTOR_SIZE]; 16];
+        let mut supplementary = pvd_sector();
+        supplementary[0] = 2;
+        let mut terminator = vec![0u8; DESCRIPTOR_SIZE];
+        terminator[0] = TYPE_TERMINATOR;
+        terminator[1..6].copy_from_slice(STANDARD_IDENTIFIER);
+        image.extend([supplementary, pvd_sector(), terminator]);
+
+        let pvd = find_primary_volume_descriptor(|i| image.get(i).map(Vec::as_slice)).unwrap();
+        assert_eq!(pvd.volume_id, "MY_GAME");
+
+        // A blob without descriptors, or one too short to hold them
+        let blob = vec![vec![0xAAu8; DESCRIPTOR_SIZE]; 20];
+        assert!(find_primary_volume_descriptor(|i| blob.get(i).map(Vec::as_slice)).is_err());
+        assert!(find_primary_volume_descriptor(|_| None).is_err());
+    }
+}
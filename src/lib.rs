@@ -1,2 +1,37 @@
+//! Without the default `std` feature only the sector encoder is built:
+//! `edc_ecc` and the sector types and encoders of `converter`. It needs
+//! `alloc` for error messages and the allocating `encode` helpers, but the
+//! `encode_*_into` functions write into caller-provided buffers.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod audio;
 pub mod converter;
+#[cfg(feature = "std")]
+pub mod cue;
+#[cfg(feature = "std")]
+pub mod dat;
+#[cfg(feature = "std")]
+pub mod ecm;
 pub mod edc_ecc;
+#[cfg(feature = "std")]
+pub mod hash;
+#[cfg(feature = "std")]
+pub mod io;
+#[cfg(feature = "std")]
+pub mod iso9660;
+#[cfg(feature = "std")]
+pub mod parallel;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "std")]
+pub mod progress;
+#[cfg(feature = "std")]
+pub mod report;
+#[cfg(feature = "std")]
+pub mod subchannel;
+#[cfg(feature = "std")]
+pub mod verify;
@@ -1,32 +1,152 @@
-pub mod converter;
-pub mod edc_ecc;
-mod io;
-mod parallel;
-
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use indicatif::ProgressBar;
 use rayon::prelude::*;
-use std::path::PathBuf;
-use std::time::Instant;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use iso2raw::audio::{audio_sector_count, audio_sectors, parse_wav, ByteOrder};
+use iso2raw::converter::{
+    extract_user_data, SectorFormat, SectorKind, Subheader, ISO_SECTOR_SIZE, MODE2_FORM2_DATA_SIZE,
+    MODE2_PAYLOAD_SIZE, RAW_SECTOR_SIZE,
+};
+use iso2raw::cue::{
+    load_cue_sheet, CueIndex, CueSheet, CueTrack, FileType, Manifest, SourceKind, TrackMode,
+};
+use iso2raw::dat::Dat;
+use iso2raw::ecm;
+use iso2raw::hash::{ImageHasher, ImageHashes, SidecarFormat};
+use iso2raw::iso9660::{find_primary_volume_descriptor, VolumeDate};
+use iso2raw::subchannel::{self, SubchannelGenerator};
+use iso2raw::verify::{
+    group_failures, has_sync, repair_sector, verify_sector, DataLayout, Failure, FailureRange,
+    RepairOutcome,
+};
+
+use iso2raw::io::{
+    companion_path, create_batch_progress_bar, create_progress_bar, create_spinner, IsoReader,
+    IsoWriter, PcmReader, RawPatcher, RawReader, RawWriter,
+};
+use iso2raw::parallel::ParallelProcessor;
+use iso2raw::pipeline::{
+    self, encode_sectors, volume_size_warning, ConversionSummary, Converter, Input, Output,
+};
+use iso2raw::progress::{rate, BarObserver, BatchBarObserver, JsonLinesObserver, Warning};
+use iso2raw::report::{Json, JsonObject};
+
+/// Set by `--format json`, which replaces the text output of a command with
+/// one report at the end.
+static JSON_FORMAT: AtomicBool = AtomicBool::new(false);
+
+fn json_format() -> bool {
+    JSON_FORMAT.load(Ordering::Relaxed)
+}
+
+/// Prints a line of text output.
+macro_rules! text {
+    ($($arg:tt)*) => {
+        if !json_format() {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Prints a warning or note to stderr. In JSON mode the report carries
+/// them instead.
+macro_rules! notice {
+    ($($arg:tt)*) => {
+        if !json_format() {
+            eprintln!($($arg)*);
+        }
+    };
+}
 
-use crate::converter::{convert_iso_to_raw, ISO_SECTOR_SIZE, RAW_SECTOR_SIZE};
-use crate::io::{create_progress_bar, IsoReader, RawWriter};
-use crate::parallel::ParallelProcessor;
+/// Prints a status line to stdout, or to stderr while stdout carries the image.
+macro_rules! status {
+    ($image_on_stdout:expr, $($arg:tt)*) => {
+        if !json_format() {
+            if $image_on_stdout {
+                eprintln!($($arg)*);
+            } else {
+                println!($($arg)*);
+            }
+        }
+    };
+}
 
 #[derive(Parser, Debug)]
 #[command(name = "iso2raw")]
-#[command(about = "Convert ISO files to RAW (MODE1/2352) format", long_about = None)]
-struct Args {
+#[command(about = "Convert ISO files to RAW (MODE1/2352 or MODE2/2352) format", long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    convert: Option<ConvertArgs>,
+
+    /// Print text, or one JSON report of what the command did
+    #[arg(long, global = true, value_enum, value_name = "FORMAT", default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show the ISO 9660 Primary Volume Descriptor of an ISO image
+    Info(InfoArgs),
+    /// Convert a RAW (2352-byte sector) image back to cooked user data
+    Raw2iso(Raw2IsoArgs),
+    /// Check the EDC and P/Q parity of every sector in a RAW image
+    Verify(VerifyArgs),
+    /// Correct damaged Mode 1 sectors of a RAW image in place using P/Q parity
+    Repair(RepairArgs),
+    /// Build one multi-track BIN + CUE from a cue-like manifest of ISO and audio files
+    Assemble(AssembleArgs),
+    /// Strip regenerable EDC/ECC from a RAW image into an ECM file
+    Ecm(EcmArgs),
+    /// Restore the original RAW image from an ECM file
+    Unecm(UnecmArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct InfoArgs {
     /// Input ISO file path
     #[arg(value_name = "INPUT")]
     input: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct Raw2IsoArgs {
+    /// Input RAW file path
+    #[arg(value_name = "INPUT")]
+    input: PathBuf,
 
-    /// Output RAW file path (defaults to input with .bin extension)
+    /// Output ISO file path (defaults to input with .iso extension)
     #[arg(short, long, value_name = "OUTPUT")]
     output: Option<PathBuf>,
 
+    /// Disable progress bar
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct VerifyArgs {
+    /// Input RAW file path
+    #[arg(value_name = "INPUT")]
+    input: PathBuf,
+
+    /// Cue sheet listing the tracks of INPUT (defaults to the .cue next to
+    /// it); audio tracks and pregaps are skipped
+    #[arg(long, value_name = "CUE")]
+    cue: Option<PathBuf>,
+
     /// Number of worker threads (defaults to number of CPU cores)
-    #[arg(short = 'j', long)]
+    #[arg(short = 'j', long, value_parser = parse_threads)]
     threads: Option<usize>,
 
     /// Disable progress bar
@@ -34,102 +154,2171 @@ struct Args {
     quiet: bool,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+#[derive(clap::Args, Debug)]
+struct RepairArgs {
+    /// RAW file to repair in place
+    #[arg(value_name = "INPUT")]
+    input: PathBuf,
 
-    // Determine output path
-    let output_path = args.output.unwrap_or_else(|| {
-        let mut path = args.input.clone();
-        path.set_extension("bin");
-        path
-    });
+    /// Cue sheet listing the tracks of INPUT (defaults to the .cue next to
+    /// it); audio tracks and pregaps are skipped
+    #[arg(long, value_name = "CUE")]
+    cue: Option<PathBuf>,
 
-    // Validate input
-    if !args.input.exists() {
-        anyhow::bail!("Input file does not exist: {}", args.input.display());
+    /// Report what would be repaired without modifying the file
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Number of worker threads (defaults to number of CPU cores)
+    #[arg(short = 'j', long, value_parser = parse_threads)]
+    threads: Option<usize>,
+
+    /// Disable progress bar
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct AssembleArgs {
+    /// Manifest listing one FILE/TRACK pair per track
+    #[arg(value_name = "MANIFEST")]
+    manifest: PathBuf,
+
+    /// Output RAW file path (defaults to manifest with .bin extension)
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: Option<PathBuf>,
+
+    /// Write audio samples in big-endian (Motorola) byte order
+    #[arg(long)]
+    big_endian: bool,
+
+    /// Also generate P/Q subchannel data
+    #[arg(long, value_enum, value_name = "LAYOUT")]
+    subchannel: Option<SubchannelLayout>,
+
+    /// Print the CRC32, MD5 and SHA-1 of the output, hashed as it is
+    /// written; implied by --sidecar and --dat. Slows conversion down
+    /// noticeably, as hashing is slower than encoding
+    #[arg(long)]
+    hash: bool,
+
+    /// Also write a checksum file next to the output; may be repeated
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    sidecar: Vec<Sidecar>,
+
+    /// Check the output and its cue sheet against a Logiqx/Redump DAT file
+    #[arg(long, value_name = "PATH")]
+    dat: Option<PathBuf>,
+
+    /// Number of worker threads (defaults to number of CPU cores)
+    #[arg(short = 'j', long, value_parser = parse_threads)]
+    threads: Option<usize>,
+
+    /// Disable progress bar
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct EcmArgs {
+    /// Input RAW file path, or - for stdin
+    #[arg(value_name = "INPUT")]
+    input: PathBuf,
+
+    /// Output ECM file path, or - for stdout (defaults to input with .ecm
+    /// appended)
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: Option<PathBuf>,
+
+    /// Number of worker threads (defaults to number of CPU cores)
+    #[arg(short = 'j', long, value_parser = parse_threads)]
+    threads: Option<usize>,
+
+    /// Disable progress bar
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct UnecmArgs {
+    /// Input ECM file path, or - for stdin
+    #[arg(value_name = "INPUT")]
+    input: PathBuf,
+
+    /// Output RAW file path, or - for stdout (defaults to input without its
+    /// .ecm extension)
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: Option<PathBuf>,
+
+    /// Number of worker threads (defaults to number of CPU cores)
+    #[arg(short = 'j', long, value_parser = parse_threads)]
+    threads: Option<usize>,
+
+    /// Disable progress bar
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct ConvertArgs {
+    /// Input ISO file path, or - for stdin. Several files, or directories to
+    /// search for .iso files, are converted as a batch
+    #[arg(value_name = "INPUT", required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Output RAW file path, or - for stdout (defaults to input with .bin
+    /// extension, or stdout when reading stdin)
+    #[arg(short, long, value_name = "OUTPUT")]
+    output: Option<PathBuf>,
+
+    /// Write the outputs of a batch into this directory, keeping the
+    /// subdirectories of directory inputs (defaults to next to each input)
+    #[arg(short = 'd', long, value_name = "DIR", conflicts_with = "output")]
+    output_dir: Option<PathBuf>,
+
+    /// Keep converting the rest of a batch after a file fails
+    #[arg(long)]
+    continue_on_error: bool,
+
+    /// Number of worker threads (defaults to number of CPU cores)
+    #[arg(short = 'j', long, value_parser = parse_threads)]
+    threads: Option<usize>,
+
+    /// Disable progress bar
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Sector format to generate
+    #[arg(long, value_enum, default_value_t = SectorMode::Mode1)]
+    mode: SectorMode,
+
+    /// XA subheader file number (Mode 2 only)
+    #[arg(long, value_parser = parse_byte, default_value = "0")]
+    file_number: u8,
+
+    /// XA subheader channel number (Mode 2 only)
+    #[arg(long, value_parser = parse_byte, default_value = "0")]
+    channel: u8,
+
+    /// XA subheader submode byte (Mode 2 only, defaults to 0x08 for Form 1
+    /// and 0x20 for Form 2)
+    #[arg(long, value_parser = parse_byte)]
+    submode: Option<u8>,
+
+    /// XA subheader coding information byte (Mode 2 only)
+    #[arg(long, value_parser = parse_byte, default_value = "0")]
+    coding_info: u8,
+
+    /// Input sector size for Form 2: 2324 (user data, the default) or 2336
+    /// (subheader + user data + EDC)
+    #[arg(long, value_parser = parse_payload_size)]
+    payload_size: Option<usize>,
+
+    /// Leave the Form 2 EDC field zeroed instead of computing it
+    #[arg(long)]
+    no_edc: bool,
+
+    /// Do not write a .cue sheet next to the output file
+    #[arg(long)]
+    no_cue: bool,
+
+    /// Write audio samples in big-endian (Motorola) byte order (audio only)
+    #[arg(long)]
+    big_endian: bool,
+
+    /// Also generate P/Q subchannel data
+    #[arg(long, value_enum, value_name = "LAYOUT")]
+    subchannel: Option<SubchannelLayout>,
+
+    /// What to do when the input is already a 2352- or 2448-byte RAW image
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = RawInputPolicy::Refuse)]
+    raw_input: RawInputPolicy,
+
+    /// Preallocate the output file and let workers encode straight into a
+    /// memory map of it
+    #[arg(long)]
+    mmap_output: bool,
+
+    /// How progress and warnings are reported on stderr
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = ProgressFormat::Bar)]
+    progress: ProgressFormat,
+
+    /// Print the CRC32, MD5 and SHA-1 of the output, hashed as it is
+    /// written; implied by --sidecar and --dat. Slows conversion down
+    /// noticeably, as hashing is slower than encoding
+    #[arg(long)]
+    hash: bool,
+
+    /// Also write a checksum file next to the output; may be repeated
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    sidecar: Vec<Sidecar>,
+
+    /// Check the output and its cue sheet against a Logiqx/Redump DAT file
+    #[arg(long, value_name = "PATH")]
+    dat: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// Text for people
+    Text,
+    /// A JSON object on stdout (stderr while stdout carries the image),
+    /// also printed when the command fails
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ProgressFormat {
+    /// Progress bar for the terminal
+    Bar,
+    /// One JSON object per line for job runners
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum RawInputPolicy {
+    /// Stop with an error
+    Refuse,
+    /// Copy the raw sectors unchanged (dropping any subchannel data)
+    Passthrough,
+    /// Extract the user data and encode it again in the requested mode
+    Reencode,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SectorMode {
+    /// MODE1/2352
+    Mode1,
+    /// CD-ROM XA Mode 2 Form 1 (MODE2/2352)
+    Mode2Form1,
+    /// CD-ROM XA Mode 2 Form 2 from 2324- or 2336-byte payloads (MODE2/2352)
+    Mode2Form2,
+    /// CD-DA audio from a 44.1 kHz 16-bit stereo WAV file (AUDIO)
+    Audio,
+}
+
+impl SectorMode {
+    /// Name of the mode on the command line.
+    fn name(self) -> String {
+        self.to_possible_value()
+            .expect("no sector mode is skipped")
+            .get_name()
+            .to_string()
     }
+}
 
-    if args.input == output_path {
-        anyhow::bail!("Input and output files cannot be the same");
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Sidecar {
+    /// CRC32 in a .sfv file
+    Sfv,
+    /// MD5 in a .md5 file, as written by md5sum
+    Md5,
+    /// SHA-1 in a .sha1 file, as written by sha1sum
+    Sha1,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SubchannelLayout {
+    /// 96 bytes per sector in a separate .sub file
+    Sub,
+    /// 2448-byte sectors with the subchannel after each sector (no cue sheet)
+    Interleaved,
+}
+
+impl From<RawInputPolicy> for pipeline::RawInputPolicy {
+    fn from(policy: RawInputPolicy) -> Self {
+        match policy {
+            RawInputPolicy::Refuse => Self::Refuse,
+            RawInputPolicy::Passthrough => Self::Passthrough,
+            RawInputPolicy::Reencode => Self::Reencode,
+        }
     }
+}
 
-    println!(
-        "Converting {} to {}",
-        args.input.display(),
-        output_path.display()
-    );
+impl From<SubchannelLayout> for subchannel::SubchannelLayout {
+    fn from(layout: SubchannelLayout) -> Self {
+        match layout {
+            SubchannelLayout::Sub => Self::Separate,
+            SubchannelLayout::Interleaved => Self::Interleaved,
+        }
+    }
+}
 
-    let start_time = Instant::now();
+impl From<Sidecar> for SidecarFormat {
+    fn from(sidecar: Sidecar) -> Self {
+        match sidecar {
+            Sidecar::Sfv => Self::Sfv,
+            Sidecar::Md5 => Self::Md5,
+            Sidecar::Sha1 => Self::Sha1,
+        }
+    }
+}
 
-    // Open input ISO
-    let iso_reader = IsoReader::new(&args.input)?;
-    let total_sectors = iso_reader.total_sectors();
+/// Whether to hash the output: asked for with --hash, or needed for checksum
+/// files or DAT matching.
+fn hashing(hash: bool, sidecars: &[Sidecar], dat: Option<&Path>) -> bool {
+    hash || !sidecars.is_empty() || dat.is_some()
+}
 
-    println!(
-        "Total sectors: {} ({:.2} MB)",
-        total_sectors,
-        (total_sectors * ISO_SECTOR_SIZE) as f64 / (1024.0 * 1024.0)
+/// Checks, before anything is written, that the files named after the output
+/// image can be created: checksum files need an output file, and none of the
+/// cue sheet, subchannel or checksum files may overwrite the image itself.
+fn check_output_paths(
+    output_path: &Path,
+    cue_sheet: bool,
+    subchannel: Option<SubchannelLayout>,
+    sidecars: &[Sidecar],
+) -> Result<()> {
+    if is_stdio(output_path) {
+        if !sidecars.is_empty() {
+            anyhow::bail!("--sidecar needs an output file to name the checksum files after");
+        }
+        return Ok(());
+    }
+
+    if cue_sheet && subchannel != Some(SubchannelLayout::Interleaved) {
+        companion_path(output_path, "cue")?;
+    }
+    if subchannel == Some(SubchannelLayout::Sub) {
+        companion_path(output_path, "sub")?;
+    }
+    for &sidecar in sidecars {
+        companion_path(output_path, SidecarFormat::from(sidecar).extension())?;
+    }
+
+    Ok(())
+}
+
+/// Prints the size and hashes of the written image and writes the requested
+/// checksum files.
+fn report_hashes(
+    hashes: Option<&ImageHashes>,
+    sidecars: &[Sidecar],
+    output_path: &Path,
+    to_stdout: bool,
+    report: &mut JsonObject,
+) -> Result<()> {
+    report.insert("hashes", hashes);
+    let Some(hashes) = hashes else {
+        return Ok(());
+    };
+
+    status!(to_stdout, "Size: {} bytes", hashes.size);
+    status!(to_stdout, "CRC32: {}", hashes.crc32_hex());
+    status!(to_stdout, "MD5: {}", hashes.md5_hex());
+    status!(to_stdout, "SHA-1: {}", hashes.sha1_hex());
+
+    let mut paths = Vec::with_capacity(sidecars.len());
+    for &sidecar in sidecars {
+        let path = SidecarFormat::from(sidecar).write(output_path, hashes)?;
+        text!("Checksum file: {}", path.display());
+        paths.push(path);
+    }
+    report.insert("checksum_files", paths);
+
+    Ok(())
+}
+
+/// Looks up the written image, and its cue sheet if one was written, in a
+/// DAT. An image that matches no entry is an error, so scripts can rely on
+/// the exit status.
+fn check_dat(
+    dat: &Dat,
+    hashes: Option<&ImageHashes>,
+    output_path: &Path,
+    cue_path: Option<&Path>,
+    to_stdout: bool,
+    report: &mut JsonObject,
+) -> Result<()> {
+    let Some(hashes) = hashes else {
+        return Ok(());
+    };
+
+    let dat_name = dat.name.as_deref().unwrap_or("DAT");
+    let mut dat_report = JsonObject::new().with("name", dat.name.clone());
+    let Some(found) = dat.find(hashes) else {
+        report.insert("dat", dat_report.with("matched", false));
+        anyhow::bail!("Output image does not match any entry in {dat_name}");
+    };
+    status!(
+        to_stdout,
+        "DAT match: {} ({}, {dat_name})",
+        found.game.name,
+        found.rom.name
     );
+    dat_report.insert("matched", true);
+    dat_report.insert("game", found.game.name.as_str());
+    dat_report.insert("rom", found.rom.name.as_str());
 
-    // Create output writer
-    let mut raw_writer = RawWriter::new(&output_path)?;
+    if let Some(cue_path) = cue_path {
+        let mut hasher = ImageHasher::new();
+        hasher.update(
+            &std::fs::read(cue_path)
+                .with_context(|| format!("Failed to read cue sheet: {}", cue_path.display()))?,
+        );
+        let cue_rom = found.game.find_rom(&hasher.finish());
+        let bin_name = output_path.file_name().unwrap_or_default();
+        match cue_rom {
+            Some(rom) => status!(to_stdout, "Cue sheet matches {}", rom.name),
+            // Cue sheets name their BIN files, so they only match when the
+            // output is named as in the DAT
+            None if bin_name != found.rom.name.as_str() => status!(
+                to_stdout,
+                "Cue sheet does not match {}; it names the BIN, so name the output {}",
+                found.game.name,
+                found.rom.name
+            ),
+            None => status!(
+                to_stdout,
+                "Cue sheet does not match any file of {}",
+                found.game.name
+            ),
+        }
+        dat_report.insert("cue_rom", cue_rom.map(|rom| rom.name.as_str()));
+    }
 
-    // Setup progress bar
-    let progress = if !args.quiet {
-        Some(create_progress_bar(total_sectors))
+    report.insert("dat", dat_report);
+    Ok(())
+}
+
+impl ConvertArgs {
+    /// Data sector format to encode, or `None` for audio.
+    fn sector_format(&self) -> Option<SectorFormat> {
+        match self.mode {
+            SectorMode::Mode1 => Some(SectorFormat::Mode1),
+            SectorMode::Mode2Form1 => Some(SectorFormat::Mode2Form1(
+                self.subheader(Subheader::SUBMODE_DATA),
+            )),
+            SectorMode::Mode2Form2 => Some(SectorFormat::Mode2Form2 {
+                subheader: self.subheader(Subheader::SUBMODE_FORM2),
+                edc: !self.no_edc,
+            }),
+            SectorMode::Audio => None,
+        }
+    }
+
+    fn byte_order(&self) -> ByteOrder {
+        byte_order(self.big_endian)
+    }
+
+    /// Several inputs, a directory input or an output directory make a batch.
+    fn is_batch(&self) -> bool {
+        self.inputs.len() > 1 || self.output_dir.is_some() || self.inputs[0].is_dir()
+    }
+
+    /// Form 2 input options make no sense for the other modes, where they
+    /// would be silently ignored.
+    fn check_form2_options(&self) -> Result<()> {
+        if self.mode == SectorMode::Mode2Form2 {
+            return Ok(());
+        }
+        if self.payload_size.is_some() {
+            anyhow::bail!("--payload-size only applies to --mode mode2-form2");
+        }
+        if self.no_edc {
+            anyhow::bail!("--no-edc only applies to --mode mode2-form2");
+        }
+        Ok(())
+    }
+
+    /// Converter for one input, configured by every option except threads
+    /// and progress reporting.
+    fn converter(&self, sector_format: SectorFormat, input: Input, output: Output) -> Converter {
+        let mut converter = Converter::new(input, output)
+            .sector_format(sector_format)
+            .raw_input(self.raw_input.into())
+            .mmap_output(self.mmap_output)
+            .hash(hashing(self.hash, &self.sidecar, self.dat.as_deref()));
+
+        if let Some(payload_size) = self.payload_size {
+            converter = converter.payload_size(payload_size);
+        }
+        if let Some(layout) = self.subchannel {
+            converter = converter.subchannel(layout.into());
+        }
+
+        converter
+    }
+
+    fn subheader(&self, default_submode: u8) -> Subheader {
+        Subheader {
+            file: self.file_number,
+            channel: self.channel,
+            submode: self.submode.unwrap_or(default_submode),
+            coding_info: self.coding_info,
+        }
+    }
+}
+
+fn byte_order(big_endian: bool) -> ByteOrder {
+    if big_endian {
+        ByteOrder::Big
     } else {
-        None
+        ByteOrder::Little
+    }
+}
+
+/// Parses a byte given in decimal or `0x`-prefixed hexadecimal.
+fn parse_byte(value: &str) -> Result<u8, String> {
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse::<u8>(),
     };
 
-    // Setup parallel processor
-    let processor = ParallelProcessor::new(args.threads);
-    println!("Using {} worker threads", processor.num_workers());
+    parsed.map_err(|_| format!("'{value}' is not a byte value (0-255 or 0x00-0xFF)"))
+}
 
-    // Process sectors in parallel chunks
-    let chunk_size = processor.chunk_size();
-    let sectors_per_batch = chunk_size * processor.num_workers();
+fn parse_threads(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(threads) if threads > 0 => Ok(threads),
+        _ => Err("thread count must be a positive number".to_string()),
+    }
+}
 
-    for batch_start in (0..total_sectors).step_by(sectors_per_batch) {
-        let batch_end = (batch_start + sectors_per_batch).min(total_sectors);
+fn parse_payload_size(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(size @ (MODE2_FORM2_DATA_SIZE | MODE2_PAYLOAD_SIZE)) => Ok(size),
+        _ => Err(format!(
+            "payload size must be {MODE2_FORM2_DATA_SIZE} or {MODE2_PAYLOAD_SIZE}"
+        )),
+    }
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    JSON_FORMAT.store(cli.format == OutputFormat::Json, Ordering::Relaxed);
 
-        // Collect batch of sectors
-        let batch: Vec<(usize, Vec<u8>)> = (batch_start..batch_end)
-            .filter_map(|lba| iso_reader.read_sector(lba).map(|data| (lba, data.to_vec())))
-            .collect();
+    let command = match &cli.command {
+        Some(Command::Info(_)) => "info",
+        Some(Command::Raw2iso(_)) => "raw2iso",
+        Some(Command::Verify(_)) => "verify",
+        Some(Command::Repair(_)) => "repair",
+        Some(Command::Assemble(_)) => "assemble",
+        Some(Command::Ecm(_)) => "ecm",
+        Some(Command::Unecm(_)) => "unecm",
+        None => "convert",
+    };
+    let mut report = Report::new(command);
 
-        // Process batch in parallel and collect results
-        let mut results: Vec<(usize, Vec<u8>)> = batch
-            .into_par_iter()
-            .map(|(lba, data)| {
-                let raw_data = convert_iso_to_raw(lba as u32, &data).unwrap();
-                (lba, raw_data)
-            })
-            .collect();
+    let result = match cli.command {
+        Some(Command::Info(args)) => run_info(args, &mut report),
+        Some(Command::Raw2iso(args)) => run_raw2iso(args, &mut report),
+        Some(Command::Verify(args)) => run_verify(args, &mut report),
+        Some(Command::Repair(args)) => run_repair(args, &mut report),
+        Some(Command::Assemble(args)) => run_assemble(args, &mut report),
+        Some(Command::Ecm(args)) => run_ecm(args, &mut report),
+        Some(Command::Unecm(args)) => run_unecm(args, &mut report),
+        None => run_convert(
+            cli.convert
+                .expect("clap requires INPUT without a subcommand"),
+            &mut report,
+        ),
+    };
 
-        // Sort results to maintain order
-        results.sort_by_key(|(lba, _)| *lba);
+    if json_format() {
+        report.print(result.as_ref().err());
+        // The report carries the error; returning it would print it again
+        if result.is_err() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-        // Write results in order
-        for (_lba, raw_data) in results {
-            raw_writer.write_sector(&raw_data)?;
+    result
+}
 
-            if let Some(ref pb) = progress {
-                pb.inc(1);
-            }
+/// What a command did, printed as one JSON object by `--format json`.
+/// Commands fill it in as they go, so a failed command still reports what
+/// it got through.
+struct Report {
+    fields: JsonObject,
+    /// The report goes to stderr when the image goes to stdout
+    image_on_stdout: bool,
+}
+
+impl Report {
+    fn new(command: &str) -> Self {
+        Self {
+            fields: JsonObject::new().with("command", command),
+            image_on_stdout: false,
         }
     }
 
-    // Finalize progress
-    if let Some(ref pb) = progress {
-        pb.finish_with_message("Conversion complete");
+    fn insert(&mut self, name: &str, value: impl Into<Json>) {
+        self.fields.insert(name, value);
     }
 
-    let elapsed = start_time.elapsed();
-    let mb_per_sec =
-        (total_sectors * RAW_SECTOR_SIZE) as f64 / (1024.0 * 1024.0) / elapsed.as_secs_f64();
+    fn print(mut self, error: Option<&anyhow::Error>) {
+        self.insert("success", error.is_none());
+        self.insert("error", error.map(|error| format!("{error:#}")));
 
-    println!("\nConversion completed in {elapsed:.2?} ({mb_per_sec:.2} MB/s)");
-    println!("Output file: {}", output_path.display());
+        if self.image_on_stdout {
+            eprintln!("{}", self.fields);
+        } else {
+            println!("{}", self.fields);
+        }
+    }
+}
 
-    Ok(())
+/// Whether `path` is `-`, standing for stdin or stdout.
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Path for status messages, naming the stream for `-`.
+fn path_label(path: &Path, stdio_name: &str) -> String {
+    if is_stdio(path) {
+        stdio_name.to_string()
+    } else {
+        path.display().to_string()
+    }
+}
+
+/// Resolves the output path and checks it against the input.
+fn prepare_paths(input: &Path, output: Option<&Path>, extension: &str) -> Result<PathBuf> {
+    // Determine output path; piped input is piped out unless told otherwise
+    let output_path = output.map(Path::to_path_buf).unwrap_or_else(|| {
+        let mut path = input.to_path_buf();
+        if !is_stdio(input) {
+            path.set_extension(extension);
+        }
+        path
+    });
+
+    if is_stdio(input) {
+        return Ok(output_path);
+    }
+
+    // Validate input
+    if !input.exists() {
+        anyhow::bail!("Input file does not exist: {}", input.display());
+    }
+
+    if input == output_path {
+        anyhow::bail!("Input and output files cannot be the same");
+    }
+
+    Ok(output_path)
+}
+
+fn run_convert(args: ConvertArgs, report: &mut Report) -> Result<()> {
+    args.check_form2_options()?;
+    if args.is_batch() {
+        return run_batch(args, report);
+    }
+
+    let input_path = &args.inputs[0];
+    let output_path = prepare_paths(input_path, args.output.as_deref(), "bin")?;
+    let from_stdin = is_stdio(input_path);
+    let to_stdout = is_stdio(&output_path);
+    report.image_on_stdout = to_stdout;
+    report.insert("input", input_path);
+    report.insert("output", &output_path);
+    report.insert("sector_format", args.mode.name());
+
+    check_output_paths(&output_path, !args.no_cue, args.subchannel, &args.sidecar)?;
+    let dat = args.dat.as_deref().map(Dat::load).transpose()?;
+    let Some(sector_format) = args.sector_format() else {
+        return convert_wav(&args, input_path, &output_path, dat.as_ref(), report);
+    };
+
+    status!(
+        to_stdout,
+        "Converting {} to {}",
+        path_label(input_path, "stdin"),
+        path_label(&output_path, "stdout")
+    );
+
+    let start_time = Instant::now();
+
+    let input = if from_stdin {
+        Input::reader(std::io::stdin())
+    } else {
+        Input::from(input_path)
+    };
+    let output = if to_stdout {
+        Output::writer(std::io::stdout())
+    } else {
+        Output::from(&output_path)
+    };
+
+    let mut converter = args.converter(sector_format, input, output);
+    if let Some(threads) = args.threads {
+        converter = converter.threads(threads);
+    }
+
+    // Setup progress reporting; piped input has no known length
+    if !args.quiet {
+        converter = match args.progress {
+            ProgressFormat::Bar if from_stdin => {
+                converter.observer(BarObserver::new(create_spinner()))
+            }
+            ProgressFormat::Bar => converter.observer(BarObserver::new(create_progress_bar(0))),
+            ProgressFormat::Json => converter.observer(JsonLinesObserver::new(std::io::stderr())),
+        };
+    }
+
+    let summary = converter.run()?;
+    let elapsed = start_time.elapsed();
+    record_conversion(&mut report.fields, &summary, elapsed);
+
+    // Observers have already shown the warnings
+    if args.quiet {
+        for warning in &summary.warnings {
+            notice!("Warning: {warning}");
+        }
+    }
+
+    if summary.layout.is_raw() {
+        let action = match args.raw_input {
+            RawInputPolicy::Passthrough => "copied its sectors",
+            _ => "re-encoded its user data",
+        };
+        status!(
+            to_stdout,
+            "Input was a {}-byte RAW image; {action}",
+            summary.layout.sector_size()
+        );
+    }
+
+    let mb_per_sec = rate(summary.bytes_written as f64, elapsed) / (1024.0 * 1024.0);
+
+    status!(
+        to_stdout,
+        "\nConverted {} sectors in {elapsed:.2?} ({mb_per_sec:.2} MB/s) using {} worker threads",
+        summary.total_sectors,
+        summary.worker_threads
+    );
+    status!(
+        to_stdout,
+        "Output file: {}",
+        path_label(&output_path, "stdout")
+    );
+
+    if let Some(sub_path) = &summary.subchannel_path {
+        status!(to_stdout, "Subchannel file: {}", sub_path.display());
+    }
+
+    report_hashes(
+        summary.hashes.as_ref(),
+        &args.sidecar,
+        &output_path,
+        to_stdout,
+        &mut report.fields,
+    )?;
+
+    let cue_path = if !args.no_cue {
+        let cue_sheet = single_track_cue(&output_path, summary.track_mode, FileType::Binary)?;
+        write_cue_sheet(&output_path, &cue_sheet, args.subchannel)?
+    } else {
+        None
+    };
+    report.insert("cue_sheet", cue_path.as_deref());
+
+    if let Some(dat) = &dat {
+        check_dat(
+            dat,
+            summary.hashes.as_ref(),
+            &output_path,
+            cue_path.as_deref(),
+            to_stdout,
+            &mut report.fields,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Adds what the pipeline reports about a conversion to a report.
+fn record_conversion(report: &mut JsonObject, summary: &ConversionSummary, elapsed: Duration) {
+    report.insert("detected_layout", summary.layout);
+    report.insert("track_mode", summary.track_mode.as_str());
+    report.insert("total_sectors", summary.total_sectors);
+    report.insert("worker_threads", summary.worker_threads);
+    report.insert("elapsed_secs", elapsed);
+    report.insert(
+        "sectors_per_second",
+        rate(summary.total_sectors as f64, elapsed),
+    );
+    report.insert(
+        "bytes_per_second",
+        rate(summary.bytes_written as f64, elapsed),
+    );
+    report.insert("subchannel_file", summary.subchannel_path.as_deref());
+    report.insert(
+        "warnings",
+        summary.warnings.iter().collect::<Vec<&Warning>>(),
+    );
+}
+
+/// One file of a batch conversion.
+struct BatchJob {
+    input: PathBuf,
+    output: PathBuf,
+    input_bytes: u64,
+    /// Input bytes of the files before this one, where its part of the
+    /// progress bar starts
+    offset: u64,
+}
+
+enum BatchOutcome {
+    Converted {
+        sectors: usize,
+        elapsed: Duration,
+    },
+    Failed(anyhow::Error),
+    /// Not attempted after an earlier file failed
+    Skipped,
+}
+
+/// Converts several files on one pool of worker threads, with one progress
+/// bar across all of them.
+fn run_batch(args: ConvertArgs, report: &mut Report) -> Result<()> {
+    report.insert("inputs", args.inputs.iter().collect::<Vec<_>>());
+    report.insert("output_dir", args.output_dir.as_deref());
+    report.insert("sector_format", args.mode.name());
+
+    if args.output.is_some() {
+        anyhow::bail!("--output names a single file; use --output-dir with several inputs");
+    }
+    let Some(sector_format) = args.sector_format() else {
+        anyhow::bail!(
+            "Audio tracks cannot be converted as a batch; convert one WAV file at a time"
+        );
+    };
+
+    let jobs = batch_jobs(&args)?;
+    if jobs.is_empty() {
+        anyhow::bail!("No .iso files found in the given directories");
+    }
+    let dat = args.dat.as_deref().map(Dat::load).transpose()?;
+    let processor = ParallelProcessor::new(args.threads);
+    let total_bytes: u64 = jobs.iter().map(|job| job.input_bytes).sum();
+    report.insert("worker_threads", processor.num_workers());
+    report.insert("input_bytes", total_bytes);
+
+    text!(
+        "Converting {} files ({:.2} MB) using {} worker threads",
+        jobs.len(),
+        total_bytes as f64 / (1024.0 * 1024.0),
+        processor.num_workers()
+    );
+
+    let bar = (!args.quiet && args.progress == ProgressFormat::Bar)
+        .then(|| create_batch_progress_bar(total_bytes));
+    let start_time = Instant::now();
+    let mut outcomes = Vec::with_capacity(jobs.len());
+    let mut file_reports = Vec::with_capacity(jobs.len());
+
+    for job in &jobs {
+        let mut file_report = JsonObject::new()
+            .with("input", &job.input)
+            .with("output", &job.output);
+
+        let stopped = outcomes
+            .iter()
+            .any(|outcome| matches!(outcome, BatchOutcome::Failed(_)));
+
+        let outcome = if stopped && !args.continue_on_error {
+            BatchOutcome::Skipped
+        } else {
+            if let Some(bar) = &bar {
+                bar.set_message(job.input.display().to_string());
+            }
+
+            let job_start = Instant::now();
+            match convert_batch_job(
+                &args,
+                sector_format,
+                job,
+                &processor,
+                dat.as_ref(),
+                bar.as_ref(),
+                &mut file_report,
+            ) {
+                Ok(summary) => BatchOutcome::Converted {
+                    sectors: summary.total_sectors,
+                    elapsed: job_start.elapsed(),
+                },
+                Err(error) => {
+                    let print_error = || notice!("Error: {}: {error:#}", job.input.display());
+                    match &bar {
+                        Some(bar) => bar.suspend(print_error),
+                        None => print_error(),
+                    }
+                    BatchOutcome::Failed(error)
+                }
+            }
+        };
+
+        if let Some(bar) = &bar {
+            bar.set_position(job.offset + job.input_bytes);
+        }
+
+        let (status, error) = match &outcome {
+            BatchOutcome::Converted { .. } => ("converted", None),
+            BatchOutcome::Failed(error) => ("failed", Some(format!("{error:#}"))),
+            BatchOutcome::Skipped => ("skipped", None),
+        };
+        file_report.insert("status", status);
+        file_report.insert("error", error);
+        file_reports.push(file_report);
+        outcomes.push(outcome);
+    }
+
+    if let Some(bar) = &bar {
+        bar.finish_and_clear();
+    }
+
+    text!("\nBatch summary:");
+    let mut converted = 0;
+    let mut failed = 0;
+    for (job, outcome) in jobs.iter().zip(&outcomes) {
+        let input = job.input.display();
+        match outcome {
+            BatchOutcome::Converted { sectors, elapsed } => {
+                converted += 1;
+                text!(
+                    "  OK       {input} -> {} ({sectors} sectors in {elapsed:.2?})",
+                    job.output.display()
+                );
+            }
+            BatchOutcome::Failed(error) => {
+                failed += 1;
+                text!("  FAILED   {input}: {error:#}");
+            }
+            BatchOutcome::Skipped => text!("  SKIPPED  {input}"),
+        }
+    }
+    text!(
+        "Converted {converted} of {} files in {:.2?}",
+        jobs.len(),
+        start_time.elapsed()
+    );
+
+    report.insert("files", file_reports);
+    report.insert("converted", converted);
+    report.insert("failed", failed);
+    report.insert("skipped", jobs.len() - converted - failed);
+    report.insert("elapsed_secs", start_time.elapsed());
+
+    if failed > 0 {
+        anyhow::bail!("{failed} of {} files failed to convert", jobs.len());
+    }
+
+    Ok(())
+}
+
+/// Expands the inputs of a batch into files. Directories are searched
+/// recursively for .iso files, which are converted in path order.
+fn batch_jobs(args: &ConvertArgs) -> Result<Vec<BatchJob>> {
+    let output_dir = args.output_dir.as_deref();
+    let mut jobs = Vec::new();
+
+    for input in &args.inputs {
+        if is_stdio(input) {
+            anyhow::bail!("stdin cannot be converted as part of a batch");
+        }
+
+        if input.is_dir() {
+            let mut files = Vec::new();
+            find_iso_files(input, &mut files)?;
+            files.sort();
+
+            for file in files {
+                let relative = file
+                    .strip_prefix(input)
+                    .expect("files are found below their directory");
+                jobs.push(batch_job(&file, relative, output_dir)?);
+            }
+        } else {
+            if !input.exists() {
+                anyhow::bail!("Input file does not exist: {}", input.display());
+            }
+            let file_name = input
+                .file_name()
+                .with_context(|| format!("Invalid input file name: {}", input.display()))?;
+            jobs.push(batch_job(input, Path::new(file_name), output_dir)?);
+        }
+    }
+
+    // Two inputs must not overwrite each other's output
+    let mut outputs = HashSet::new();
+    for job in &jobs {
+        if !outputs.insert(&job.output) {
+            anyhow::bail!(
+                "Several inputs would be converted to {}",
+                job.output.display()
+            );
+        }
+    }
+
+    let mut offset = 0;
+    for job in &mut jobs {
+        job.offset = offset;
+        offset += job.input_bytes;
+    }
+
+    Ok(jobs)
+}
+
+/// `relative` is where the output goes below `output_dir`; without one the
+/// output is written next to the input.
+fn batch_job(input: &Path, relative: &Path, output_dir: Option<&Path>) -> Result<BatchJob> {
+    let output = match output_dir {
+        Some(dir) => dir.join(relative),
+        None => input.to_path_buf(),
+    }
+    .with_extension("bin");
+
+    if output == input {
+        anyhow::bail!(
+            "Input and output files cannot be the same: {}",
+            input.display()
+        );
+    }
+
+    let input_bytes = std::fs::metadata(input)
+        .with_context(|| format!("Failed to read input file: {}", input.display()))?
+        .len();
+
+    Ok(BatchJob {
+        input: input.to_path_buf(),
+        output,
+        input_bytes,
+        offset: 0,
+    })
+}
+
+/// Symbolic links to directories are not followed, so links cannot form a
+/// cycle.
+fn find_iso_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            find_iso_files(&path, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("iso"))
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts one file of a batch and reports on it like a single conversion.
+fn convert_batch_job(
+    args: &ConvertArgs,
+    sector_format: SectorFormat,
+    job: &BatchJob,
+    processor: &ParallelProcessor,
+    dat: Option<&Dat>,
+    bar: Option<&ProgressBar>,
+    report: &mut JsonObject,
+) -> Result<ConversionSummary> {
+    check_output_paths(&job.output, !args.no_cue, args.subchannel, &args.sidecar)?;
+    if let Some(parent) = job.output.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+
+    let mut converter = args.converter(
+        sector_format,
+        Input::from(&job.input),
+        Output::from(&job.output),
+    );
+    if let Some(bar) = bar {
+        converter = converter.observer(BatchBarObserver::new(
+            bar.clone(),
+            job.input.display().to_string(),
+            job.offset,
+            job.input_bytes,
+        ));
+    } else if !args.quiet {
+        converter = converter.observer(JsonLinesObserver::new(std::io::stderr()));
+    }
+
+    let start_time = Instant::now();
+    let summary = converter.run_on(processor)?;
+    record_conversion(report, &summary, start_time.elapsed());
+
+    // The bar is hidden while the file's results are printed
+    let mut print_results = || -> Result<()> {
+        text!(
+            "\nConverted {} to {} ({} sectors)",
+            job.input.display(),
+            job.output.display(),
+            summary.total_sectors
+        );
+
+        // Observers have already shown the warnings
+        if args.quiet {
+            for warning in &summary.warnings {
+                notice!("Warning: {}: {warning}", job.input.display());
+            }
+        }
+
+        if let Some(sub_path) = &summary.subchannel_path {
+            text!("Subchannel file: {}", sub_path.display());
+        }
+
+        report_hashes(
+            summary.hashes.as_ref(),
+            &args.sidecar,
+            &job.output,
+            false,
+            report,
+        )?;
+
+        let cue_path = if !args.no_cue {
+            let cue_sheet = single_track_cue(&job.output, summary.track_mode, FileType::Binary)?;
+            write_cue_sheet(&job.output, &cue_sheet, args.subchannel)?
+        } else {
+            None
+        };
+        report.insert("cue_sheet", cue_path.as_deref());
+
+        if let Some(dat) = dat {
+            check_dat(
+                dat,
+                summary.hashes.as_ref(),
+                &job.output,
+                cue_path.as_deref(),
+                false,
+                report,
+            )?;
+        }
+
+        Ok(())
+    };
+
+    match bar {
+        Some(bar) => bar.suspend(print_results)?,
+        None => print_results()?,
+    }
+
+    Ok(summary)
+}
+
+fn run_info(args: InfoArgs, report: &mut Report) -> Result<()> {
+    report.insert("input", &args.input);
+
+    let iso_reader = IsoReader::new(&args.input)?;
+    let pvd = find_primary_volume_descriptor(|index| iso_reader.read_sector(index))
+        .with_context(|| format!("Not an ISO 9660 image: {}", args.input.display()))?;
+
+    report.insert("volume_id", pvd.volume_id.as_str());
+    report.insert("system_id", pvd.system_id.as_str());
+    report.insert("volume_space_size", pvd.volume_space_size);
+    report.insert("logical_block_size", pvd.logical_block_size);
+    report.insert("created", pvd.creation_date.map(|date| date.to_string()));
+    report.insert(
+        "modified",
+        pvd.modification_date.map(|date| date.to_string()),
+    );
+    report.insert("total_sectors", iso_reader.total_sectors());
+
+    text!("File: {}", args.input.display());
+    text!("Volume ID: {}", pvd.volume_id);
+    text!("System ID: {}", pvd.system_id);
+    text!("Volume space size: {} blocks", pvd.volume_space_size);
+    text!("Logical block size: {} bytes", pvd.logical_block_size);
+    text!("Created: {}", format_date(pvd.creation_date));
+    text!("Modified: {}", format_date(pvd.modification_date));
+    text!("Image size: {} sectors", iso_reader.total_sectors());
+
+    let warning = volume_size_warning(pvd.volume_sectors(), iso_reader.total_sectors());
+    if let Some(warning) = &warning {
+        notice!("Warning: {warning}");
+    }
+    report.insert(
+        "warnings",
+        warning.map(Warning::new).iter().collect::<Vec<_>>(),
+    );
+
+    Ok(())
+}
+
+fn format_date(date: Option<VolumeDate>) -> String {
+    date.map_or_else(|| "not set".to_string(), |date| date.to_string())
+}
+
+/// Reads a whole input file, or stdin for `-`.
+fn read_input(path: &Path) -> std::io::Result<Vec<u8>> {
+    if !is_stdio(path) {
+        return std::fs::read(path);
+    }
+
+    let mut bytes = Vec::new();
+    std::io::stdin().read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Converts a CD-DA WAV file into audio sectors.
+fn convert_wav(
+    args: &ConvertArgs,
+    input_path: &Path,
+    output_path: &Path,
+    dat: Option<&Dat>,
+    report: &mut Report,
+) -> Result<()> {
+    let to_stdout = is_stdio(output_path);
+    status!(
+        to_stdout,
+        "Converting {} to {}",
+        path_label(input_path, "stdin"),
+        path_label(output_path, "stdout")
+    );
+
+    let start_time = Instant::now();
+
+    let bytes = read_input(input_path)
+        .with_context(|| format!("Failed to read WAV file: {}", input_path.display()))?;
+    let wav =
+        parse_wav(&bytes).with_context(|| format!("Invalid WAV file: {}", input_path.display()))?;
+    let total_sectors = audio_sector_count(wav.data.len());
+
+    status!(
+        to_stdout,
+        "Total sectors: {} ({:.2} MB of audio)",
+        total_sectors,
+        wav.data.len() as f64 / (1024.0 * 1024.0)
+    );
+
+    let cue_sheet = single_track_cue(
+        output_path,
+        TrackMode::Audio,
+        FileType::from(args.byte_order()),
+    )?;
+    let mut raw_writer = create_raw_writer(
+        output_path,
+        args.subchannel,
+        &cue_sheet,
+        hashing(args.hash, &args.sidecar, args.dat.as_deref()),
+    )?;
+
+    let progress = if !args.quiet {
+        Some(create_progress_bar(total_sectors))
+    } else {
+        None
+    };
+
+    for sector in audio_sectors(wav.data, args.byte_order()) {
+        raw_writer.write_sector(&sector)?;
+
+        if let Some(ref pb) = progress {
+            pb.inc(1);
+        }
+    }
+
+    let hashes = raw_writer.finish()?;
+    let elapsed = start_time.elapsed();
+
+    if let Some(ref pb) = progress {
+        pb.finish_with_message("Conversion complete");
+    }
+
+    report.insert("track_mode", TrackMode::Audio.as_str());
+    report.insert("total_sectors", total_sectors);
+    report.insert("elapsed_secs", elapsed);
+    report.insert("sectors_per_second", rate(total_sectors as f64, elapsed));
+    report.insert(
+        "bytes_per_second",
+        rate((total_sectors * RAW_SECTOR_SIZE) as f64, elapsed),
+    );
+
+    status!(to_stdout, "\nConversion completed in {elapsed:.2?}");
+    status!(
+        to_stdout,
+        "Output file: {}",
+        path_label(output_path, "stdout")
+    );
+    report_hashes(
+        hashes.as_ref(),
+        &args.sidecar,
+        output_path,
+        to_stdout,
+        &mut report.fields,
+    )?;
+
+    let cue_path = if !args.no_cue {
+        write_cue_sheet(output_path, &cue_sheet, args.subchannel)?
+    } else {
+        None
+    };
+    report.insert("cue_sheet", cue_path.as_deref());
+
+    if let Some(dat) = dat {
+        check_dat(
+            dat,
+            hashes.as_ref(),
+            output_path,
+            cue_path.as_deref(),
+            to_stdout,
+            &mut report.fields,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Cue sheet for a BIN at `bin_path` holding a single track.
+fn single_track_cue(bin_path: &Path, mode: TrackMode, file_type: FileType) -> Result<CueSheet> {
+    let mut cue_sheet = CueSheet::single_track(bin_file_name(bin_path)?, mode);
+    cue_sheet.file_type = file_type;
+
+    Ok(cue_sheet)
+}
+
+/// Opens the output BIN, generating subchannel data for the tracks of
+/// `cue_sheet` if a layout was requested.
+fn create_raw_writer(
+    output_path: &Path,
+    subchannel: Option<SubchannelLayout>,
+    cue_sheet: &CueSheet,
+    hash: bool,
+) -> Result<RawWriter> {
+    let mut raw_writer = if is_stdio(output_path) {
+        RawWriter::from_writer(std::io::stdout())
+    } else {
+        RawWriter::new(output_path)?
+    };
+    if hash {
+        raw_writer = raw_writer.with_hashing();
+    }
+    let Some(layout) = subchannel else {
+        return Ok(raw_writer);
+    };
+
+    let generator = SubchannelGenerator::new(cue_sheet);
+    match layout {
+        SubchannelLayout::Sub if is_stdio(output_path) => {
+            anyhow::bail!("--subchannel sub needs an output file to name the .sub file after")
+        }
+        SubchannelLayout::Sub => {
+            let sub_path = companion_path(output_path, "sub")?;
+            text!("Subchannel file: {}", sub_path.display());
+            raw_writer.with_subchannel_file(generator, &sub_path)
+        }
+        SubchannelLayout::Interleaved => Ok(raw_writer.with_interleaved_subchannel(generator)),
+    }
+}
+
+/// Writes `cue_sheet` next to `bin_path` and returns its path. Cue sheets
+/// cannot describe 2448-byte sectors, so none is written for interleaved
+/// subchannel output.
+fn write_cue_sheet(
+    bin_path: &Path,
+    cue_sheet: &CueSheet,
+    subchannel: Option<SubchannelLayout>,
+) -> Result<Option<PathBuf>> {
+    if subchannel == Some(SubchannelLayout::Interleaved) {
+        notice!("Note: no cue sheet written for 2448-byte sectors");
+        return Ok(None);
+    }
+
+    if is_stdio(bin_path) {
+        notice!("Note: no cue sheet written for output to stdout");
+        return Ok(None);
+    }
+
+    let cue_path = companion_path(bin_path, "cue")?;
+    cue_sheet.write(&cue_path)?;
+    text!("Cue sheet: {}", cue_path.display());
+
+    Ok(Some(cue_path))
+}
+
+fn bin_file_name(bin_path: &Path) -> Result<String> {
+    bin_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| anyhow::anyhow!("Invalid output file name: {}", bin_path.display()))
+}
+
+fn run_raw2iso(args: Raw2IsoArgs, report: &mut Report) -> Result<()> {
+    report.insert("input", &args.input);
+    let output_path = prepare_paths(&args.input, args.output.as_deref(), "iso")?;
+    report.insert("output", &output_path);
+
+    text!(
+        "Extracting {} to {}",
+        args.input.display(),
+        output_path.display()
+    );
+
+    let start_time = Instant::now();
+
+    let raw_reader = RawReader::new(&args.input)?;
+    let total_sectors = raw_reader.total_sectors();
+    report.insert("total_sectors", total_sectors);
+
+    text!(
+        "Total sectors: {} ({:.2} MB)",
+        total_sectors,
+        (total_sectors * RAW_SECTOR_SIZE) as f64 / (1024.0 * 1024.0)
+    );
+
+    let mut iso_writer = IsoWriter::new(&output_path)?;
+
+    let progress = if !args.quiet {
+        Some(create_progress_bar(total_sectors))
+    } else {
+        None
+    };
+
+    let mut kinds: BTreeMap<SectorKind, usize> = BTreeMap::new();
+
+    for lba in 0..total_sectors {
+        let Some(raw_data) = raw_reader.read_sector(lba) else {
+            break;
+        };
+
+        let kind = match extract_user_data(raw_data) {
+            Some((kind, user_data)) => {
+                iso_writer.write_sector(user_data)?;
+                kind
+            }
+            None => SectorKind::detect(raw_data),
+        };
+        *kinds.entry(kind).or_default() += 1;
+
+        if let Some(ref pb) = progress {
+            pb.inc(1);
+        }
+    }
+
+    if let Some(ref pb) = progress {
+        pb.finish_with_message("Extraction complete");
+    }
+
+    let elapsed = start_time.elapsed();
+
+    text!("\nExtraction completed in {elapsed:.2?}");
+    let mut sector_kinds = JsonObject::new();
+    for (kind, count) in &kinds {
+        text!("  {kind:?}: {count} sectors");
+        sector_kinds.insert(&format!("{kind:?}"), *count);
+    }
+
+    let mut warnings = Vec::new();
+    let skipped: usize = [SectorKind::Mode0, SectorKind::Unknown]
+        .iter()
+        .filter_map(|kind| kinds.get(kind))
+        .sum();
+    if skipped > 0 {
+        warnings.push(Warning::new(format!(
+            "skipped {skipped} sectors without user data"
+        )));
+    }
+
+    if kinds.contains_key(&SectorKind::Mode2Form2) || kinds.contains_key(&SectorKind::Mode2Formless)
+    {
+        warnings.push(Warning::new(
+            "output contains Form 2 or formless sectors and is not a plain 2048-byte ISO",
+        ));
+    }
+
+    for warning in &warnings {
+        notice!("Warning: {warning}");
+    }
+
+    report.insert("sector_kinds", sector_kinds);
+    report.insert("bytes_written", iso_writer.bytes_written());
+    report.insert("elapsed_secs", elapsed);
+    report.insert(
+        "bytes_per_second",
+        rate((total_sectors * RAW_SECTOR_SIZE) as f64, elapsed),
+    );
+    report.insert("warnings", warnings.iter().collect::<Vec<_>>());
+
+    text!(
+        "Output file: {} ({:.2} MB)",
+        output_path.display(),
+        iso_writer.bytes_written() as f64 / (1024.0 * 1024.0)
+    );
+
+    Ok(())
+}
+
+fn run_verify(args: VerifyArgs, report: &mut Report) -> Result<()> {
+    report.insert("input", &args.input);
+    if !args.input.exists() {
+        anyhow::bail!("Input file does not exist: {}", args.input.display());
+    }
+
+    text!("Verifying {}", args.input.display());
+
+    let start_time = Instant::now();
+
+    let raw_reader = RawReader::new(&args.input)?;
+    let total_sectors = raw_reader.total_sectors();
+
+    text!("Total sectors: {total_sectors}");
+
+    let layout = data_layout(&args.input, args.cue.as_deref(), total_sectors)?;
+    if let Some((cue_path, _)) = &layout {
+        text!("Cue sheet: {}", cue_path.display());
+    }
+    report.insert("cue_sheet", layout.as_ref().map(|(cue_path, _)| cue_path));
+
+    let progress = if !args.quiet {
+        Some(create_progress_bar(total_sectors))
+    } else {
+        None
+    };
+
+    let processor = ParallelProcessor::new(args.threads);
+    let sectors_per_batch = processor.sectors_per_batch();
+
+    let mut results: Vec<(u32, Vec<Failure>)> = Vec::new();
+    let mut skipped_sectors = 0;
+
+    for batch_start in (0..total_sectors).step_by(sectors_per_batch) {
+        let batch_end = (batch_start + sectors_per_batch).min(total_sectors);
+
+        // Only failing and skipped sectors are kept, the skipped ones without
+        // failures; collect preserves LBA order
+        let checked: Vec<(u32, Option<Vec<Failure>>)> = processor.install(|| {
+            (batch_start..batch_end)
+                .into_par_iter()
+                .filter_map(|sector| {
+                    let raw_data = raw_reader.read_sector(sector)?;
+                    let lba = match &layout {
+                        Some((_, layout)) => layout.lba(sector as u32),
+                        // Without a cue sheet, sectors without a sync
+                        // pattern are taken for audio
+                        None => has_sync(raw_data).then_some(sector as u32),
+                    };
+                    let Some(lba) = lba else {
+                        return Some((sector as u32, None));
+                    };
+                    let failures = verify_sector(lba, raw_data);
+                    (!failures.is_empty()).then_some((lba, Some(failures)))
+                })
+                .collect()
+        });
+        for (lba, failures) in checked {
+            match failures {
+                Some(failures) => results.push((lba, failures)),
+                None => skipped_sectors += 1,
+            }
+        }
+
+        if let Some(ref pb) = progress {
+            pb.inc((batch_end - batch_start) as u64);
+        }
+    }
+
+    if let Some(ref pb) = progress {
+        pb.finish_with_message("Verification complete");
+    }
+
+    let failed_sectors = results.len();
+    let checked_sectors = total_sectors - skipped_sectors;
+    let ranges = group_failures(results);
+    let elapsed = start_time.elapsed();
+
+    report.insert("total_sectors", total_sectors);
+    report.insert("skipped_sectors", skipped_sectors);
+    report.insert("failed_sectors", failed_sectors);
+    report.insert("failures", ranges.iter().collect::<Vec<_>>());
+    report.insert("elapsed_secs", elapsed);
+    report.insert("sectors_per_second", rate(total_sectors as f64, elapsed));
+
+    text!("\nVerification completed in {elapsed:.2?}");
+
+    if skipped_sectors > 0 {
+        match layout {
+            Some(_) => text!("Skipped {skipped_sectors} audio and pregap sectors"),
+            None => text!(
+                "Skipped {skipped_sectors} sectors without a sync pattern; \
+                 they are not data sectors"
+            ),
+        }
+    }
+
+    if ranges.is_empty() {
+        text!("All {checked_sectors} data sectors are consistent");
+        return Ok(());
+    }
+
+    print_failure_ranges(&ranges);
+
+    anyhow::bail!("{failed_sectors} of {checked_sectors} sectors failed verification")
+}
+
+/// Where the data sectors of the BIN at `bin_path` lie, from the cue sheet
+/// at `cue_path` or the one next to the BIN, together with the cue sheet's
+/// path. `None` if there is no cue sheet to go by.
+fn data_layout(
+    bin_path: &Path,
+    cue_path: Option<&Path>,
+    total_sectors: usize,
+) -> Result<Option<(PathBuf, DataLayout)>> {
+    let cue_path = match cue_path {
+        Some(cue_path) => cue_path.to_path_buf(),
+        None => match companion_path(bin_path, "cue") {
+            Ok(cue_path) if cue_path.exists() => cue_path,
+            _ => return Ok(None),
+        },
+    };
+
+    let files = load_cue_sheet(&cue_path)?;
+    let bin_name = bin_file_name(bin_path)?;
+    let index = match files.iter().position(|file| file.file_name == bin_name) {
+        Some(index) => index,
+        None if files.len() == 1 => 0,
+        None => anyhow::bail!("{} does not list {bin_name}", cue_path.display()),
+    };
+
+    // The files before the BIN, and their PREGAPs, take the LBAs before it
+    let cue_dir = cue_path.parent().unwrap_or_else(|| Path::new(""));
+    let mut start_lba = 0;
+    for file in &files[..index] {
+        let path = cue_dir.join(&file.file_name);
+        let size = std::fs::metadata(&path)
+            .with_context(|| format!("Failed to read track file: {}", path.display()))?
+            .len();
+        let pregaps: u32 = file.tracks.iter().map(|track| track.pregap).sum();
+        start_lba += (size / RAW_SECTOR_SIZE as u64) as u32 + pregaps;
+    }
+
+    let layout = DataLayout::from_cue(&files[index], start_lba, total_sectors as u32);
+    Ok(Some((cue_path, layout)))
+}
+
+fn print_failure_ranges(ranges: &[FailureRange]) {
+    for range in ranges {
+        let failures: Vec<String> = range.failures.iter().map(Failure::to_string).collect();
+        let (start, end) = (*range.lbas.start(), *range.lbas.end());
+        if start == end {
+            text!("  LBA {start}: {}", failures.join(", "));
+        } else {
+            text!(
+                "  LBA {start}-{end} ({} sectors): {}",
+                end - start + 1,
+                failures.join(", ")
+            );
+        }
+    }
+}
+
+fn run_repair(args: RepairArgs, report: &mut Report) -> Result<()> {
+    report.insert("input", &args.input);
+    report.insert("dry_run", args.dry_run);
+    if !args.input.exists() {
+        anyhow::bail!("Input file does not exist: {}", args.input.display());
+    }
+
+    text!("Repairing {}", args.input.display());
+
+    let start_time = Instant::now();
+
+    let raw_reader = RawReader::new(&args.input)?;
+    let total_sectors = raw_reader.total_sectors();
+
+    text!("Total sectors: {total_sectors}");
+
+    let layout = data_layout(&args.input, args.cue.as_deref(), total_sectors)?;
+    if let Some((cue_path, _)) = &layout {
+        text!("Cue sheet: {}", cue_path.display());
+    }
+
+    let mut patcher = if args.dry_run {
+        None
+    } else {
+        Some(RawPatcher::new(&args.input)?)
+    };
+
+    let progress = if !args.quiet {
+        Some(create_progress_bar(total_sectors))
+    } else {
+        None
+    };
+
+    let processor = ParallelProcessor::new(args.threads);
+    let sectors_per_batch = processor.sectors_per_batch();
+
+    let mut repaired_sectors = 0;
+    let mut repaired_bytes = 0;
+    let mut unrepairable: Vec<(u32, Vec<Failure>)> = Vec::new();
+
+    for batch_start in (0..total_sectors).step_by(sectors_per_batch) {
+        let batch_end = (batch_start + sectors_per_batch).min(total_sectors);
+
+        // Repair copies of the damaged data sectors; intact ones are dropped
+        let results: Vec<(usize, u32, Vec<u8>, RepairOutcome)> = processor.install(|| {
+            (batch_start..batch_end)
+                .into_par_iter()
+                .filter_map(|index| {
+                    let raw_data = raw_reader.read_sector(index)?;
+                    let lba = match &layout {
+                        Some((_, layout)) => layout.lba(index as u32)?,
+                        None if has_sync(raw_data) => index as u32,
+                        None => return None,
+                    };
+                    let mut sector = raw_data.to_vec();
+                    match repair_sector(lba, &mut sector) {
+                        RepairOutcome::Intact => None,
+                        outcome => Some((index, lba, sector, outcome)),
+                    }
+                })
+                .collect()
+        });
+
+        for (index, lba, sector, outcome) in results {
+            match outcome {
+                RepairOutcome::Repaired { bytes_corrected } => {
+                    if let Some(ref mut patcher) = patcher {
+                        patcher.write_sector(index, &sector)?;
+                    }
+                    repaired_sectors += 1;
+                    repaired_bytes += bytes_corrected;
+                }
+                RepairOutcome::Unrepairable => {
+                    unrepairable.push((lba, verify_sector(lba, &sector)));
+                }
+                RepairOutcome::Intact => {}
+            }
+        }
+
+        if let Some(ref pb) = progress {
+            pb.inc((batch_end - batch_start) as u64);
+        }
+    }
+
+    if let Some(ref pb) = progress {
+        pb.finish_with_message("Repair complete");
+    }
+
+    let elapsed = start_time.elapsed();
+    let unrepairable_sectors = unrepairable.len();
+    let unrepairable = group_failures(unrepairable);
+
+    report.insert("total_sectors", total_sectors);
+    report.insert("repaired_sectors", repaired_sectors);
+    report.insert("bytes_corrected", repaired_bytes);
+    report.insert("unrepairable_sectors", unrepairable_sectors);
+    report.insert("unrepairable", unrepairable.iter().collect::<Vec<_>>());
+    report.insert("elapsed_secs", elapsed);
+
+    text!("\nRepair completed in {elapsed:.2?}");
+
+    let verb = if args.dry_run {
+        "Repairable"
+    } else {
+        "Repaired"
+    };
+    text!("{verb}: {repaired_sectors} sectors ({repaired_bytes} bytes corrected)");
+
+    if unrepairable.is_empty() {
+        return Ok(());
+    }
+
+    text!("Could not repair:");
+    print_failure_ranges(&unrepairable);
+
+    anyhow::bail!("{unrepairable_sectors} of {total_sectors} sectors could not be repaired")
+}
+
+/// Input of one track in a multi-track image.
+enum TrackSource {
+    Data(IsoReader, SectorFormat),
+    Audio(PcmReader),
+}
+
+impl TrackSource {
+    fn total_sectors(&self) -> usize {
+        match self {
+            Self::Data(iso_reader, _) => iso_reader.total_sectors(),
+            Self::Audio(pcm_reader) => pcm_reader.total_sectors(),
+        }
+    }
+}
+
+/// Opens the ISO of a data track, refusing images that are already RAW.
+fn open_track_iso(path: &Path) -> Result<IsoReader> {
+    let iso_reader = IsoReader::new(path)?;
+    if iso_reader.layout().is_raw() {
+        anyhow::bail!(
+            "{} is already a {}-byte RAW image",
+            path.display(),
+            iso_reader.layout().sector_size()
+        );
+    }
+
+    Ok(iso_reader)
+}
+
+fn run_assemble(args: AssembleArgs, report: &mut Report) -> Result<()> {
+    report.insert("input", &args.manifest);
+    let output_path = prepare_paths(&args.manifest, args.output.as_deref(), "bin")?;
+    report.insert("output", &output_path);
+    check_output_paths(&output_path, true, args.subchannel, &args.sidecar)?;
+    let dat = args.dat.as_deref().map(Dat::load).transpose()?;
+    let manifest = Manifest::load(&args.manifest)?;
+
+    text!(
+        "Assembling {} tracks from {} to {}",
+        manifest.tracks.len(),
+        args.manifest.display(),
+        output_path.display()
+    );
+
+    let start_time = Instant::now();
+
+    // Open every source up front so that errors surface before writing
+    let mut sources = Vec::with_capacity(manifest.tracks.len());
+    for track in &manifest.tracks {
+        let source = match track.source_kind {
+            SourceKind::Iso => TrackSource::Data(
+                open_track_iso(&track.source)?,
+                track
+                    .mode
+                    .sector_format()
+                    .context("ISO files need a data track mode")?,
+            ),
+            SourceKind::Wave => TrackSource::Audio(PcmReader::wav(&track.source)?),
+            SourceKind::Binary => TrackSource::Audio(PcmReader::raw(&track.source)?),
+        };
+        sources.push(source);
+    }
+
+    let total_sectors: usize = manifest
+        .tracks
+        .iter()
+        .zip(&sources)
+        .map(|(track, source)| track.pregap as usize + source.total_sectors())
+        .sum();
+
+    text!("Total sectors: {total_sectors}");
+    report.insert("tracks", manifest.tracks.len());
+    report.insert("total_sectors", total_sectors);
+
+    // Lay out the tracks first so the subchannel generator knows them
+    let byte_order = byte_order(args.big_endian);
+    let mut cue_tracks = Vec::with_capacity(manifest.tracks.len());
+    let mut position = 0u32;
+
+    for (track, source) in manifest.tracks.iter().zip(&sources) {
+        let mut indexes = Vec::new();
+
+        if track.pregap > 0 {
+            indexes.push(CueIndex {
+                number: 0,
+                position,
+            });
+            position += track.pregap;
+        }
+
+        indexes.push(CueIndex {
+            number: 1,
+            position,
+        });
+        position += source.total_sectors() as u32;
+
+        cue_tracks.push(CueTrack {
+            number: track.number,
+            mode: track.mode,
+            pregap: 0,
+            indexes,
+        });
+    }
+
+    let cue_sheet = CueSheet {
+        file_name: bin_file_name(&output_path)?,
+        file_type: FileType::from(byte_order),
+        tracks: cue_tracks,
+    };
+
+    let mut raw_writer = create_raw_writer(
+        &output_path,
+        args.subchannel,
+        &cue_sheet,
+        hashing(args.hash, &args.sidecar, args.dat.as_deref()),
+    )?;
+
+    let progress = if !args.quiet {
+        Some(create_progress_bar(total_sectors))
+    } else {
+        None
+    };
+
+    let processor = ParallelProcessor::new(args.threads);
+    let mut position = 0u32;
+    let mut warnings = Vec::new();
+
+    for (track, source) in manifest.tracks.iter().zip(&sources) {
+        // Pregap sectors are encoded in the mode of the track they belong to
+        for lba in position..position + track.pregap {
+            let sector = match track.mode.sector_format() {
+                Some(sector_format) => sector_format.encode(lba, &[0u8; ISO_SECTOR_SIZE])?,
+                None => vec![0u8; RAW_SECTOR_SIZE],
+            };
+            raw_writer.write_sector(&sector)?;
+        }
+
+        if let Some(ref pb) = progress {
+            pb.inc(track.pregap as u64);
+        }
+        position += track.pregap;
+
+        match source {
+            TrackSource::Data(iso_reader, sector_format) => encode_sectors(
+                iso_reader,
+                *sector_format,
+                position,
+                &processor,
+                &mut raw_writer,
+                &mut |batch| {
+                    if let Some(ref pb) = progress {
+                        pb.inc(batch.sectors as u64);
+                    }
+                    for warning in batch.warnings {
+                        notice!("Warning: {warning}");
+                        warnings.push(warning);
+                    }
+                    Ok(())
+                },
+            )?,
+            TrackSource::Audio(pcm_reader) => {
+                for sector in audio_sectors(pcm_reader.pcm(), byte_order) {
+                    raw_writer.write_sector(&sector)?;
+
+                    if let Some(ref pb) = progress {
+                        pb.inc(1);
+                    }
+                }
+            }
+        }
+        position += source.total_sectors() as u32;
+    }
+
+    let hashes = raw_writer.finish()?;
+    let elapsed = start_time.elapsed();
+
+    if let Some(ref pb) = progress {
+        pb.finish_with_message("Assembly complete");
+    }
+
+    report.insert("elapsed_secs", elapsed);
+    report.insert(
+        "bytes_per_second",
+        rate((total_sectors * RAW_SECTOR_SIZE) as f64, elapsed),
+    );
+    report.insert("warnings", warnings.iter().collect::<Vec<_>>());
+
+    text!("\nAssembly completed in {elapsed:.2?}");
+    text!("Output file: {}", output_path.display());
+    report_hashes(
+        hashes.as_ref(),
+        &args.sidecar,
+        &output_path,
+        false,
+        &mut report.fields,
+    )?;
+    let cue_path = write_cue_sheet(&output_path, &cue_sheet, args.subchannel)?;
+    report.insert("cue_sheet", cue_path.as_deref());
+
+    if let Some(dat) = &dat {
+        check_dat(
+            dat,
+            hashes.as_ref(),
+            &output_path,
+            cue_path.as_deref(),
+            false,
+            &mut report.fields,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn run_ecm(args: EcmArgs, report: &mut Report) -> Result<()> {
+    report.insert("input", &args.input);
+    // game.bin becomes game.bin.ecm, as with the reference tools
+    let output = match args.output {
+        Some(output) => output,
+        None if is_stdio(&args.input) => PathBuf::from("-"),
+        None => {
+            let mut name = args.input.clone().into_os_string();
+            name.push(".ecm");
+            PathBuf::from(name)
+        }
+    };
+    let output_path = prepare_paths(&args.input, Some(&output), "ecm")?;
+
+    let to_stdout = is_stdio(&output_path);
+    report.image_on_stdout = to_stdout;
+    report.insert("output", &output_path);
+    status!(
+        to_stdout,
+        "Encoding {} to {}",
+        path_label(&args.input, "stdin"),
+        path_label(&output_path, "stdout")
+    );
+
+    let start_time = Instant::now();
+
+    let progress = (!args.quiet).then(|| {
+        if is_stdio(&args.input) {
+            create_spinner()
+        } else {
+            let bin_bytes = std::fs::metadata(&args.input).map_or(0, |meta| meta.len());
+            create_progress_bar(bin_bytes.div_ceil(RAW_SECTOR_SIZE as u64) as usize)
+        }
+    });
+
+    let processor = ParallelProcessor::new(args.threads);
+    let summary = ecm::encode(
+        open_input(&args.input)?,
+        create_output(&output_path)?,
+        &processor,
+        &mut |summary| {
+            if let Some(ref pb) = progress {
+                pb.set_position(summary.bin_bytes / RAW_SECTOR_SIZE as u64);
+            }
+        },
+    )?;
+
+    let elapsed = start_time.elapsed();
+
+    if let Some(ref pb) = progress {
+        pb.finish_with_message("Encoding complete");
+    }
+
+    record_ecm(&mut report.fields, &summary, elapsed);
+    status!(
+        to_stdout,
+        "\nEncoded {} bytes in {elapsed:.2?}",
+        summary.bin_bytes
+    );
+    print_ecm_summary(&summary, to_stdout);
+    status!(
+        to_stdout,
+        "Output file: {} ({} bytes, {:.1}% of the RAW image)",
+        path_label(&output_path, "stdout"),
+        summary.ecm_bytes,
+        summary.ratio() * 100.0
+    );
+
+    Ok(())
+}
+
+fn run_unecm(args: UnecmArgs, report: &mut Report) -> Result<()> {
+    report.insert("input", &args.input);
+    let output = match args.output {
+        Some(output) => output,
+        None if is_stdio(&args.input) => PathBuf::from("-"),
+        None if args.input.extension().is_some_and(|ext| ext == "ecm") => {
+            args.input.with_extension("")
+        }
+        None => anyhow::bail!(
+            "Cannot derive an output name from {} without an .ecm extension; use --output",
+            args.input.display()
+        ),
+    };
+    let output_path = prepare_paths(&args.input, Some(&output), "bin")?;
+
+    let to_stdout = is_stdio(&output_path);
+    report.image_on_stdout = to_stdout;
+    report.insert("output", &output_path);
+    status!(
+        to_stdout,
+        "Decoding {} to {}",
+        path_label(&args.input, "stdin"),
+        path_label(&output_path, "stdout")
+    );
+
+    let start_time = Instant::now();
+
+    // The decoded size is only known once the end marker is reached
+    let progress = (!args.quiet).then(create_spinner);
+
+    let processor = ParallelProcessor::new(args.threads);
+    let summary = ecm::decode(
+        open_input(&args.input)?,
+        create_output(&output_path)?,
+        &processor,
+        &mut |summary| {
+            if let Some(ref pb) = progress {
+                pb.set_position(summary.bin_bytes / RAW_SECTOR_SIZE as u64);
+            }
+        },
+    )?;
+
+    let elapsed = start_time.elapsed();
+
+    if let Some(ref pb) = progress {
+        pb.finish_with_message("Decoding complete");
+    }
+
+    record_ecm(&mut report.fields, &summary, elapsed);
+    status!(
+        to_stdout,
+        "\nDecoded {} bytes in {elapsed:.2?}; EDC matches",
+        summary.bin_bytes
+    );
+    print_ecm_summary(&summary, to_stdout);
+    status!(
+        to_stdout,
+        "Output file: {}",
+        path_label(&output_path, "stdout")
+    );
+
+    Ok(())
+}
+
+/// Adds the sector counts and sizes of an ECM run to a report.
+fn record_ecm(report: &mut JsonObject, summary: &ecm::EcmSummary, elapsed: Duration) {
+    report.insert("summary", summary);
+    report.insert("elapsed_secs", elapsed);
+    report.insert("bytes_per_second", rate(summary.bin_bytes as f64, elapsed));
+}
+
+fn print_ecm_summary(summary: &ecm::EcmSummary, to_stdout: bool) {
+    status!(to_stdout, "  Mode 1: {} sectors", summary.mode1_sectors);
+    status!(
+        to_stdout,
+        "  Mode 2 Form 1: {} sectors",
+        summary.mode2_form1_sectors
+    );
+    status!(
+        to_stdout,
+        "  Mode 2 Form 2: {} sectors",
+        summary.mode2_form2_sectors
+    );
+    status!(to_stdout, "  Literal: {} bytes", summary.literal_bytes);
+}
+
+/// Opens an input file, or stdin for `-`.
+fn open_input(path: &Path) -> Result<Box<dyn Read + Send>> {
+    if is_stdio(path) {
+        return Ok(Box::new(std::io::stdin()));
+    }
+
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open input file: {}", path.display()))?;
+    Ok(Box::new(file))
+}
+
+/// Creates a buffered output file, or writes to stdout for `-`.
+fn create_output(path: &Path) -> Result<Box<dyn Write + Send>> {
+    if is_stdio(path) {
+        return Ok(Box::new(BufWriter::new(std::io::stdout())));
+    }
+
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create output file: {}", path.display()))?;
+    Ok(Box::new(BufWriter::new(file)))
 }
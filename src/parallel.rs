@@ -1,6 +1,9 @@
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
 pub struct ParallelProcessor {
     num_workers: usize,
     chunk_size: usize,
+    pool: Option<ThreadPool>,
 }
 
 impl ParallelProcessor {
@@ -13,9 +16,16 @@ impl ParallelProcessor {
         // Chunk size optimized for cache efficiency
         let chunk_size = 64; // Process 64 sectors at a time
 
+        // Falls back to rayon's global pool if the threads cannot be spawned
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_workers)
+            .build()
+            .ok();
+
         Self {
             num_workers,
             chunk_size,
+            pool,
         }
     }
 
@@ -26,4 +36,22 @@ impl ParallelProcessor {
     pub fn chunk_size(&self) -> usize {
         self.chunk_size
     }
+
+    /// Number of sectors handed to the workers at once.
+    pub fn sectors_per_batch(&self) -> usize {
+        self.chunk_size * self.num_workers
+    }
+
+    /// Runs `op` on this processor's worker threads, so that parallel
+    /// iterators inside it use `num_workers` threads.
+    pub fn install<OP, R>(&self, op: OP) -> R
+    where
+        OP: FnOnce() -> R + Send,
+        R: Send,
+    {
+        match &self.pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
 }
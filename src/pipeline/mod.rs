@@ -0,0 +1,1036 @@
+// ISO to RAW conversion pipeline
+//
+// Converter drives a whole conversion the way the CLI does: it opens the
+// input (a memory-mapped file or any reader), applies the RAW input policy,
+// encodes sectors in parallel batches on its own worker threads and writes
+// them in order. Progress and warnings go to a ProgressObserver, and a
+// cancellation token is checked between batches.
+
+use anyhow::{bail, Context, Result};
+use rayon::prelude::*;
+use std::fmt;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::converter::{
+    detect_layout, InputLayout, SectorFormat, SectorKind, ISO_SECTOR_SIZE, MODE2_FORM2_DATA_SIZE,
+    RAW_SECTOR_SIZE,
+};
+use crate::cue::{CueSheet, TrackMode};
+use crate::hash::ImageHashes;
+use crate::io::{companion_path, IsoReader, MmapRawWriter, RawWriter, SectorStream};
+use crate::iso9660::find_primary_volume_descriptor;
+use crate::parallel::ParallelProcessor;
+use crate::progress::{Progress, ProgressObserver, Warning};
+use crate::subchannel::{SubchannelGenerator, SubchannelLayout};
+use crate::verify::verify_sector;
+
+/// What to do when the input is already a RAW image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RawInputPolicy {
+    /// Fail with an error
+    #[default]
+    Refuse,
+    /// Copy the raw sectors unchanged, dropping any subchannel data
+    Passthrough,
+    /// Extract the user data and encode it again
+    Reencode,
+}
+
+pub enum Input {
+    /// A file, memory-mapped and checked for RAW layouts
+    File(PathBuf),
+    /// Any stream of sectors, such as stdin, read in batches
+    Reader(Box<dyn Read + Send>),
+}
+
+impl Input {
+    pub fn reader<R: Read + Send + 'static>(reader: R) -> Self {
+        Self::Reader(Box::new(reader))
+    }
+}
+
+impl<P: AsRef<Path>> From<P> for Input {
+    fn from(path: P) -> Self {
+        Self::File(path.as_ref().to_path_buf())
+    }
+}
+
+pub enum Output {
+    File(PathBuf),
+    Writer(Box<dyn Write + Send>),
+}
+
+impl Output {
+    pub fn writer<W: Write + Send + 'static>(writer: W) -> Self {
+        Self::Writer(Box::new(writer))
+    }
+}
+
+impl<P: AsRef<Path>> From<P> for Output {
+    fn from(path: P) -> Self {
+        Self::File(path.as_ref().to_path_buf())
+    }
+}
+
+/// Shared flag that stops a running conversion before its next batch.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Error returned by [`Converter::run`] when its token was cancelled. The
+/// output then holds only the sectors written so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("conversion cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionSummary {
+    pub total_sectors: usize,
+    /// Bytes written to the output, including interleaved subchannel data,
+    /// as in the observer's final progress report
+    pub bytes_written: u64,
+    /// Layout detected from the input
+    pub layout: InputLayout,
+    /// Track mode for the cue sheet; taken from the sectors themselves when
+    /// RAW input was passed through
+    pub track_mode: TrackMode,
+    pub worker_threads: usize,
+    pub subchannel_path: Option<PathBuf>,
+    /// Size and hashes of the output, if hashing was enabled
+    pub hashes: Option<ImageHashes>,
+    /// Problems with the input that did not stop the conversion, also
+    /// reported to the observer as they were found
+    pub warnings: Vec<Warning>,
+}
+
+/// Builder for an ISO to RAW conversion.
+pub struct Converter {
+    input: Input,
+    output: Output,
+    threads: Option<usize>,
+    sector_format: SectorFormat,
+    payload_size: usize,
+    raw_input: RawInputPolicy,
+    subchannel: Option<SubchannelLayout>,
+    mmap_output: bool,
+    hash: bool,
+    observer: Option<Box<dyn ProgressObserver>>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl Converter {
+    /// Converts `input` to MODE1/2352 sectors in `output` unless configured
+    /// otherwise.
+    pub fn new(input: impl Into<Input>, output: impl Into<Output>) -> Self {
+        Self {
+            input: input.into(),
+            output: output.into(),
+            threads: None,
+            sector_format: SectorFormat::Mode1,
+            payload_size: MODE2_FORM2_DATA_SIZE,
+            raw_input: RawInputPolicy::default(),
+            subchannel: None,
+            mmap_output: false,
+            hash: false,
+            observer: None,
+            cancellation: None,
+        }
+    }
+
+    /// Number of worker threads (defaults to the number of CPU cores, up to 8).
+    /// Running with 0 fails.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    pub fn sector_format(mut self, sector_format: SectorFormat) -> Self {
+        self.sector_format = sector_format;
+        self
+    }
+
+    /// Input sector size for Form 2: 2324 (user data, the default) or 2336
+    /// (subheader + user data + EDC).
+    pub fn payload_size(mut self, payload_size: usize) -> Self {
+        self.payload_size = payload_size;
+        self
+    }
+
+    pub fn raw_input(mut self, policy: RawInputPolicy) -> Self {
+        self.raw_input = policy;
+        self
+    }
+
+    /// Also generates P/Q subchannel data. A separate `.sub` file is named
+    /// after the output file.
+    pub fn subchannel(mut self, layout: SubchannelLayout) -> Self {
+        self.subchannel = Some(layout);
+        self
+    }
+
+    /// Writes a file output through a preallocated memory map that workers
+    /// encode into directly, instead of through a buffered stream. Has no
+    /// effect on writer outputs or RAW input passed through.
+    pub fn mmap_output(mut self, enabled: bool) -> Self {
+        self.mmap_output = enabled;
+        self
+    }
+
+    /// Computes CRC32, MD5 and SHA-1 of the output as it is written,
+    /// including any interleaved subchannel data. Off by default: hashing
+    /// runs on the writing thread and is slower than encoding, so it can
+    /// more than double the time of a conversion.
+    pub fn hash(mut self, enabled: bool) -> Self {
+        self.hash = enabled;
+        self
+    }
+
+    pub fn observer(mut self, observer: impl ProgressObserver + 'static) -> Self {
+        self.observer = Some(Box::new(observer));
+        self
+    }
+
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    fn input_sector_size(&self) -> usize {
+        match self.sector_format {
+            SectorFormat::Mode2Form2 { .. } => self.payload_size,
+            SectorFormat::Mode1 | SectorFormat::Mode2Form1(_) => ISO_SECTOR_SIZE,
+        }
+    }
+
+    pub fn run(self) -> Result<ConversionSummary> {
+        let processor = ParallelProcessor::new(self.threads);
+        self.run_on(&processor)
+    }
+
+    /// Runs the conversion on an existing processor, so that a batch of
+    /// conversions shares one pool of worker threads. The `threads` setting
+    /// is ignored.
+    pub fn run_on(mut self, processor: &ParallelProcessor) -> Result<ConversionSummary> {
+        if processor.num_workers() == 0 {
+            bail!("Cannot convert with 0 worker threads");
+        }
+
+        let input = std::mem::replace(&mut self.input, Input::File(PathBuf::new()));
+        let mut reporter = Reporter {
+            observer: self.observer.take(),
+            cancellation: self.cancellation.take(),
+            started: Instant::now(),
+            warnings: Vec::new(),
+        };
+
+        let mut summary = match input {
+            Input::File(path) => self.convert_file(&path, processor, &mut reporter),
+            Input::Reader(reader) => self.convert_stream(reader, processor, &mut reporter),
+        }?;
+
+        summary.warnings = reporter.warnings;
+        Ok(summary)
+    }
+
+    fn convert_file(
+        mut self,
+        path: &Path,
+        processor: &ParallelProcessor,
+        reporter: &mut Reporter,
+    ) -> Result<ConversionSummary> {
+        let iso_reader = IsoReader::with_sector_size(path, self.input_sector_size())?;
+        let total_sectors = iso_reader.total_sectors();
+        let layout = iso_reader.layout();
+
+        let mut summary = self.summary(layout, processor);
+
+        let passthrough = layout.is_raw() && self.check_raw_input(layout)?;
+        reporter.started(Some(total_sectors));
+
+        if passthrough {
+            summary.track_mode = raw_track_mode(&iso_reader).unwrap_or(summary.track_mode);
+        } else if !self.is_form2() {
+            if let Some(warning) = check_iso_volume(&iso_reader) {
+                reporter.warn(warning);
+            }
+        }
+
+        let mut sectors_done = 0;
+        let mut on_batch = |batch: WrittenBatch| {
+            sectors_done += batch.sectors;
+            for warning in batch.warnings {
+                reporter.warn(warning);
+            }
+            reporter.progress(sectors_done, Some(total_sectors), batch.bytes_written)
+        };
+
+        let mapped_path = match &self.output {
+            Output::File(path) if self.mmap_output && !passthrough => Some(path.clone()),
+            _ => None,
+        };
+
+        if let Some(path) = mapped_path {
+            let mut writer =
+                self.create_mmap_writer(&path, total_sectors, summary.track_mode, &mut summary)?;
+            let encoded = encode_sectors_mapped(
+                &iso_reader,
+                self.sector_format,
+                processor,
+                &mut writer,
+                &mut on_batch,
+            );
+            if let Err(err) = encoded {
+                writer.abort()?;
+                return Err(err);
+            }
+
+            let bytes_written = writer.bytes_written();
+            summary.hashes = writer.finish()?;
+            reporter.finished(total_sectors, Some(total_sectors), bytes_written);
+
+            summary.total_sectors = total_sectors;
+            summary.bytes_written = bytes_written;
+            return Ok(summary);
+        }
+
+        let mut raw_writer = self.create_raw_writer(summary.track_mode, &mut summary)?;
+
+        if passthrough {
+            for batch_start in (0..total_sectors).step_by(processor.sectors_per_batch()) {
+                let batch_end = (batch_start + processor.sectors_per_batch()).min(total_sectors);
+
+                for index in batch_start..batch_end {
+                    if let Some(raw_data) = iso_reader.read_raw_sector(index) {
+                        raw_writer.write_sector(raw_data)?;
+                    }
+                }
+                on_batch(WrittenBatch {
+                    sectors: batch_end - batch_start,
+                    bytes_written: raw_writer.bytes_written(),
+                    warnings: Vec::new(),
+                })?;
+            }
+        } else {
+            encode_sectors(
+                &iso_reader,
+                self.sector_format,
+                0,
+                processor,
+                &mut raw_writer,
+                &mut on_batch,
+            )?;
+        }
+
+        let bytes_written = raw_writer.bytes_written();
+        summary.hashes = raw_writer.finish()?;
+        reporter.finished(total_sectors, Some(total_sectors), bytes_written);
+
+        summary.total_sectors = total_sectors;
+        summary.bytes_written = bytes_written;
+        Ok(summary)
+    }
+
+    fn convert_stream(
+        mut self,
+        reader: Box<dyn Read + Send>,
+        processor: &ParallelProcessor,
+        reporter: &mut Reporter,
+    ) -> Result<ConversionSummary> {
+        let sector_size = self.input_sector_size();
+        let sectors_per_batch = processor.sectors_per_batch();
+        let mut stream = SectorStream::new(reader, sector_size);
+
+        // The layout is only known from the first batch, which is checked
+        // before the output is created so that refused input leaves an
+        // existing file alone. A short RAW image does not split into whole
+        // input sectors, so its layout is checked first.
+        let mut batch = stream.read_partial_batch(sectors_per_batch)?;
+        let warning = if batch.is_empty() {
+            None
+        } else {
+            self.check_stream_start(&batch)?
+        };
+        stream.check_whole_sectors(&batch)?;
+
+        let mut summary = self.summary(InputLayout::Iso, processor);
+        let mut raw_writer = self.create_raw_writer(summary.track_mode, &mut summary)?;
+        let mut buffer = vec![0u8; sectors_per_batch * RAW_SECTOR_SIZE];
+        reporter.started(None);
+        if let Some(warning) = warning {
+            reporter.warn(warning);
+        }
+
+        while !batch.is_empty() {
+            // Each worker encodes straight into its own slice of the output
+            let sectors = batch.len() / sector_size;
+            let output = &mut buffer[..sectors * RAW_SECTOR_SIZE];
+            let start_lba = summary.total_sectors as u32;
+            let sector_format = self.sector_format;
+            processor.install(|| {
+                output
+                    .par_chunks_mut(RAW_SECTOR_SIZE)
+                    .zip(batch.par_chunks(sector_size))
+                    .enumerate()
+                    .try_for_each(|(index, (raw, data))| {
+                        let lba = start_lba + index as u32;
+                        sector_format
+                            .encode_into(lba, data, raw)
+                            .with_context(|| format!("Failed to encode sector {lba}"))
+                    })
+            })?;
+
+            raw_writer.write_sectors(output)?;
+
+            summary.total_sectors += sectors;
+            reporter.progress(summary.total_sectors, None, raw_writer.bytes_written())?;
+
+            batch = stream.read_batch(sectors_per_batch)?;
+        }
+
+        let bytes_written = raw_writer.bytes_written();
+        summary.hashes = raw_writer.finish()?;
+        reporter.finished(summary.total_sectors, None, bytes_written);
+
+        summary.bytes_written = bytes_written;
+
+        Ok(summary)
+    }
+
+    fn summary(&self, layout: InputLayout, processor: &ParallelProcessor) -> ConversionSummary {
+        ConversionSummary {
+            total_sectors: 0,
+            bytes_written: 0,
+            layout,
+            track_mode: TrackMode::from(self.sector_format),
+            worker_threads: processor.num_workers(),
+            subchannel_path: None,
+            hashes: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn is_form2(&self) -> bool {
+        matches!(self.sector_format, SectorFormat::Mode2Form2 { .. })
+    }
+
+    /// Applies the RAW input policy and returns whether sectors should be
+    /// passed through.
+    fn check_raw_input(&self, layout: InputLayout) -> Result<bool> {
+        match self.raw_input {
+            RawInputPolicy::Refuse => bail!(
+                "Input is already a {}-byte RAW image; use --raw-input passthrough or \
+                 --raw-input reencode to convert it anyway",
+                layout.sector_size()
+            ),
+            RawInputPolicy::Passthrough => Ok(true),
+            RawInputPolicy::Reencode => Ok(false),
+        }
+    }
+
+    /// Checks the first batch of streamed input, which cannot be rewound:
+    /// RAW images are refused and missing ISO 9660 descriptors reported.
+    fn check_stream_start(&self, batch: &[u8]) -> Result<Option<Warning>> {
+        let layout = detect_layout(batch);
+        if layout.is_raw() {
+            bail!(
+                "Input is already a {}-byte RAW image; piped RAW input cannot be \
+                 passed through or re-encoded",
+                layout.sector_size()
+            );
+        }
+
+        if !self.is_form2() {
+            let read_sector =
+                |index: usize| batch.get(index * ISO_SECTOR_SIZE..(index + 1) * ISO_SECTOR_SIZE);
+            if let Err(err) = find_primary_volume_descriptor(read_sector) {
+                return Ok(Some(not_iso_warning(err)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Opens the output, generating subchannel data for a single track of
+    /// `track_mode` if a layout was requested.
+    fn create_raw_writer(
+        &mut self,
+        track_mode: TrackMode,
+        summary: &mut ConversionSummary,
+    ) -> Result<RawWriter> {
+        let output = std::mem::replace(&mut self.output, Output::File(PathBuf::new()));
+        let (mut raw_writer, sub_path) = match output {
+            Output::File(path) => {
+                let sub_path = self.subchannel_path(&path)?;
+                (RawWriter::new(&path)?, sub_path)
+            }
+            Output::Writer(writer) => (RawWriter::from_writer(writer), None),
+        };
+
+        if self.hash {
+            raw_writer = raw_writer.with_hashing();
+        }
+
+        let Some(layout) = self.subchannel else {
+            return Ok(raw_writer);
+        };
+
+        let generator = SubchannelGenerator::new(&CueSheet::single_track("", track_mode));
+        match (layout, sub_path) {
+            (SubchannelLayout::Separate, Some(sub_path)) => {
+                let raw_writer = raw_writer.with_subchannel_file(generator, &sub_path)?;
+                summary.subchannel_path = Some(sub_path);
+                Ok(raw_writer)
+            }
+            (SubchannelLayout::Separate, None) => {
+                bail!("A separate subchannel file needs an output file to be named after")
+            }
+            (SubchannelLayout::Interleaved, _) => {
+                Ok(raw_writer.with_interleaved_subchannel(generator))
+            }
+        }
+    }
+
+    /// Creates `path` as a preallocated memory map for `total_sectors`, with
+    /// subchannel data as in [`Self::create_raw_writer`].
+    fn create_mmap_writer(
+        &self,
+        path: &Path,
+        total_sectors: usize,
+        track_mode: TrackMode,
+        summary: &mut ConversionSummary,
+    ) -> Result<MmapRawWriter> {
+        let sub_path = self.subchannel_path(path)?;
+        let mut writer = MmapRawWriter::create(path, total_sectors)?;
+
+        if self.hash {
+            writer = writer.with_hashing();
+        }
+
+        let Some(layout) = self.subchannel else {
+            return Ok(writer);
+        };
+
+        let generator = SubchannelGenerator::new(&CueSheet::single_track("", track_mode));
+        match layout {
+            SubchannelLayout::Separate => {
+                let sub_path = sub_path.context("A separate subchannel file needs a path")?;
+                let writer = writer.with_subchannel_file(generator, &sub_path)?;
+                summary.subchannel_path = Some(sub_path);
+                Ok(writer)
+            }
+            SubchannelLayout::Interleaved => writer.with_interleaved_subchannel(generator),
+        }
+    }
+
+    /// The `.sub` file next to the output image `path`, if one is written.
+    /// Checked before the image is created, so an output named `.sub` is
+    /// refused without being truncated.
+    fn subchannel_path(&self, path: &Path) -> Result<Option<PathBuf>> {
+        match self.subchannel {
+            Some(SubchannelLayout::Separate) => companion_path(path, "sub").map(Some),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Forwards the events of a conversion to its observer and keeps the
+/// warnings for the summary.
+struct Reporter {
+    observer: Option<Box<dyn ProgressObserver>>,
+    cancellation: Option<CancellationToken>,
+    started: Instant,
+    warnings: Vec<Warning>,
+}
+
+impl Reporter {
+    fn started(&mut self, total_sectors: Option<usize>) {
+        if let Some(observer) = &mut self.observer {
+            observer.started(total_sectors);
+        }
+    }
+
+    fn warn(&mut self, warning: Warning) {
+        if let Some(observer) = &mut self.observer {
+            observer.warning(&warning);
+        }
+        self.warnings.push(warning);
+    }
+
+    /// Reports a written batch, then fails with [`Cancelled`] if the token
+    /// was cancelled.
+    fn progress(
+        &mut self,
+        sectors_done: usize,
+        total_sectors: Option<usize>,
+        bytes_written: u64,
+    ) -> Result<()> {
+        let progress = self.snapshot(sectors_done, total_sectors, bytes_written);
+        if let Some(observer) = &mut self.observer {
+            observer.progress(&progress);
+        }
+
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(Cancelled.into());
+        }
+
+        Ok(())
+    }
+
+    fn finished(&mut self, sectors_done: usize, total_sectors: Option<usize>, bytes_written: u64) {
+        let progress = self.snapshot(sectors_done, total_sectors, bytes_written);
+        if let Some(observer) = &mut self.observer {
+            observer.finished(&progress);
+        }
+    }
+
+    fn snapshot(
+        &self,
+        sectors_done: usize,
+        total_sectors: Option<usize>,
+        bytes_written: u64,
+    ) -> Progress {
+        Progress {
+            sectors_done,
+            total_sectors,
+            bytes_written,
+            elapsed: self.started.elapsed(),
+        }
+    }
+}
+
+/// A batch written by [`encode_sectors`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrittenBatch {
+    pub sectors: usize,
+    /// Total bytes written to the output so far
+    pub bytes_written: u64,
+    /// Problems found in the batch's input sectors
+    pub warnings: Vec<Warning>,
+}
+
+/// Encodes every sector of `iso_reader` in parallel batches and writes them in
+/// order, numbering them from `start_lba`. `on_batch` is called once each
+/// batch is written and may stop the conversion by failing. Sectors of a RAW
+/// input that fail verification are reported as warnings, since re-encoding
+/// gives them valid EDC/ECC again.
+pub fn encode_sectors(
+    iso_reader: &IsoReader,
+    sector_format: SectorFormat,
+    start_lba: u32,
+    processor: &ParallelProcessor,
+    raw_writer: &mut RawWriter,
+    on_batch: &mut dyn FnMut(WrittenBatch) -> Result<()>,
+) -> Result<()> {
+    let total_sectors = iso_reader.total_sectors();
+    let sectors_per_batch = processor.sectors_per_batch();
+
+    // Reused for every batch; sectors are encoded in place and written in order
+    let mut buffer = vec![0u8; sectors_per_batch.min(total_sectors) * RAW_SECTOR_SIZE];
+
+    for batch_start in (0..total_sectors).step_by(sectors_per_batch) {
+        let batch = batch_start..(batch_start + sectors_per_batch).min(total_sectors);
+        let output = &mut buffer[..batch.len() * RAW_SECTOR_SIZE];
+
+        let warnings = encode_batch(
+            iso_reader,
+            sector_format,
+            start_lba,
+            batch.clone(),
+            output,
+            RAW_SECTOR_SIZE,
+            processor,
+        )?;
+        raw_writer.write_sectors(output)?;
+
+        on_batch(WrittenBatch {
+            sectors: batch.len(),
+            bytes_written: raw_writer.bytes_written(),
+            warnings,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Like [`encode_sectors`], but workers encode straight into the memory-mapped
+/// output file, each batch into its own range of sectors.
+pub fn encode_sectors_mapped(
+    iso_reader: &IsoReader,
+    sector_format: SectorFormat,
+    processor: &ParallelProcessor,
+    writer: &mut MmapRawWriter,
+    on_batch: &mut dyn FnMut(WrittenBatch) -> Result<()>,
+) -> Result<()> {
+    let total_sectors = iso_reader.total_sectors();
+    if writer.total_sectors() != total_sectors {
+        bail!(
+            "Output was allocated for {} sectors but the input has {}",
+            writer.total_sectors(),
+            total_sectors
+        );
+    }
+
+    let sectors_per_batch = processor.sectors_per_batch();
+    let sector_stride = writer.sector_stride();
+
+    for batch_start in (0..total_sectors).step_by(sectors_per_batch) {
+        let batch = batch_start..(batch_start + sectors_per_batch).min(total_sectors);
+
+        let warnings = encode_batch(
+            iso_reader,
+            sector_format,
+            0,
+            batch.clone(),
+            writer.sectors_mut(batch.clone()),
+            sector_stride,
+            processor,
+        )?;
+        writer.commit(batch.clone())?;
+
+        on_batch(WrittenBatch {
+            sectors: batch.len(),
+            bytes_written: writer.bytes_written(),
+            warnings,
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Encodes the sectors of `batch` into `output`, `sector_stride` bytes apart.
+/// Each worker reads from the mapped input and encodes into its own slice.
+fn encode_batch(
+    iso_reader: &IsoReader,
+    sector_format: SectorFormat,
+    start_lba: u32,
+    batch: Range<usize>,
+    output: &mut [u8],
+    sector_stride: usize,
+    processor: &ParallelProcessor,
+) -> Result<Vec<Warning>> {
+    let raw_input = iso_reader.layout().is_raw();
+
+    let warnings: Vec<Option<Warning>> = processor.install(|| {
+        output
+            .par_chunks_mut(sector_stride)
+            .enumerate()
+            .map(|(offset, slot)| {
+                let index = batch.start + offset;
+                let data = iso_reader
+                    .read_sector(index)
+                    .with_context(|| format!("Sector {index} has no user data to encode"))?;
+                sector_format
+                    .encode_into(start_lba + index as u32, data, &mut slot[..RAW_SECTOR_SIZE])
+                    .with_context(|| format!("Failed to encode sector {index}"))?;
+                Ok(raw_input
+                    .then(|| source_sector_warning(iso_reader, index))
+                    .flatten())
+            })
+            .collect::<Result<_>>()
+    })?;
+
+    Ok(warnings.into_iter().flatten().collect())
+}
+
+/// Track mode matching the first sector of a RAW input, if it is a data sector.
+fn raw_track_mode(iso_reader: &IsoReader) -> Option<TrackMode> {
+    match SectorKind::detect(iso_reader.read_raw_sector(0)?) {
+        SectorKind::Mode1 => Some(TrackMode::Mode1),
+        SectorKind::Mode2Form1 | SectorKind::Mode2Form2 | SectorKind::Mode2Formless => {
+            Some(TrackMode::Mode2)
+        }
+        SectorKind::Mode0 | SectorKind::Unknown => None,
+    }
+}
+
+/// Warns about a RAW input sector whose stored EDC/ECC or header does not
+/// match its contents.
+fn source_sector_warning(iso_reader: &IsoReader, index: usize) -> Option<Warning> {
+    let failures = verify_sector(index as u32, iso_reader.read_raw_sector(index)?);
+    if failures.is_empty() {
+        return None;
+    }
+
+    let fields: Vec<String> = failures.iter().map(ToString::to_string).collect();
+    Some(Warning::sector(
+        index,
+        format!(
+            "input sector fails {} check; re-encoding replaces its EDC/ECC",
+            fields.join(", ")
+        ),
+    ))
+}
+
+/// Reports a 2048-byte input that does not look like a complete ISO 9660
+/// volume.
+fn check_iso_volume(iso_reader: &IsoReader) -> Option<Warning> {
+    match find_primary_volume_descriptor(|index| iso_reader.read_sector(index)) {
+        Ok(pvd) => {
+            volume_size_warning(pvd.volume_sectors(), iso_reader.total_sectors()).map(Warning::new)
+        }
+        Err(err) => Some(not_iso_warning(err)),
+    }
+}
+
+fn not_iso_warning(err: anyhow::Error) -> Warning {
+    Warning::new(format!("{err}; input may not be an ISO 9660 image"))
+}
+
+/// Warning for a volume space size that disagrees with the image size.
+pub fn volume_size_warning(volume_sectors: u64, total_sectors: usize) -> Option<String> {
+    (volume_sectors != total_sectors as u64).then(|| {
+        format!("volume space size is {volume_sectors} sectors but the image holds {total_sectors}")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::convert_iso_to_raw;
+    use crate::hash::ImageHasher;
+    use std::fs;
+    use std::sync::Mutex;
+
+    /// Shared in-memory output.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Records every progress report it receives.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<Progress>>>);
+
+    impl ProgressObserver for Recorder {
+        fn progress(&mut self, progress: &Progress) {
+            self.0.lock().unwrap().push(*progress);
+        }
+    }
+
+    /// Cancels its token on the first progress report.
+    struct Canceller(CancellationToken);
+
+    impl ProgressObserver for Canceller {
+        fn progress(&mut self, _progress: &Progress) {
+            self.0.cancel();
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("iso2raw-{name}-{}", std::process::id()))
+    }
+
+    fn test_iso(sectors: usize) -> Vec<u8> {
+        (0..sectors * ISO_SECTOR_SIZE)
+            .map(|i| (i % 241) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_converts_stream_to_writer() {
+        let iso = test_iso(700);
+        let output = SharedBuffer::default();
+        let recorder = Recorder::default();
+
+        let summary = Converter::new(
+            Input::reader(std::io::Cursor::new(iso.clone())),
+            Output::writer(output.clone()),
+        )
+        .threads(2)
+        .observer(recorder.clone())
+        .run()
+        .unwrap();
+
+        assert_eq!(summary.total_sectors, 700);
+        assert_eq!(summary.worker_threads, 2);
+
+        let reports = recorder.0.lock().unwrap();
+        assert_eq!(reports.len(), 700usize.div_ceil(128));
+        assert!(reports.iter().all(|report| report.total_sectors.is_none()));
+        let last = reports.last().unwrap();
+        assert_eq!(last.sectors_done, 700);
+        assert_eq!(last.bytes_written, 700 * RAW_SECTOR_SIZE as u64);
+
+        let expected: Vec<u8> = iso
+            .chunks(ISO_SECTOR_SIZE)
+            .enumerate()
+            .flat_map(|(lba, data)| convert_iso_to_raw(lba as u32, data).unwrap())
+            .collect();
+        assert!(*output.0.lock().unwrap() == expected);
+    }
+
+    #[test]
+    fn test_refused_stream_keeps_existing_output() {
+        // Three RAW sectors are shorter than a batch and not a whole number
+        // of 2048-byte sectors
+        let raw: Vec<u8> = test_iso(3)
+            .chunks(ISO_SECTOR_SIZE)
+            .enumerate()
+            .flat_map(|(lba, data)| convert_iso_to_raw(lba as u32, data).unwrap())
+            .collect();
+        let bin_path = temp_path("refused.bin");
+        fs::write(&bin_path, b"keep this").unwrap();
+
+        let result = Converter::new(Input::reader(std::io::Cursor::new(raw)), &bin_path).run();
+
+        let kept = fs::read(&bin_path).unwrap();
+        let _ = fs::remove_file(&bin_path);
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("already a 2352-byte RAW image"), "{error}");
+        assert_eq!(kept, b"keep this");
+    }
+
+    #[test]
+    fn test_zero_threads() {
+        let result = Converter::new(
+            Input::reader(std::io::Cursor::new(test_iso(4))),
+            Output::writer(std::io::sink()),
+        )
+        .threads(0)
+        .run();
+        assert!(result.unwrap_err().to_string().contains("0 worker threads"));
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let path = temp_path("cancel.iso");
+        fs::write(&path, test_iso(1000)).unwrap();
+
+        let token = CancellationToken::new();
+        let result = Converter::new(&path, Output::writer(std::io::sink()))
+            .threads(1)
+            .observer(Canceller(token.clone()))
+            .cancellation_token(token)
+            .run();
+
+        let _ = fs::remove_file(&path);
+        assert!(result.unwrap_err().is::<Cancelled>());
+    }
+
+    #[test]
+    fn test_mmap_output_matches_stream_output() {
+        let iso_path = temp_path("mapped.iso");
+        let streamed = temp_path("streamed.bin");
+        let mapped = temp_path("mapped.bin");
+        fs::write(&iso_path, test_iso(300)).unwrap();
+
+        for layout in [None, Some(SubchannelLayout::Interleaved)] {
+            let mut hashes = Vec::new();
+            for (output, mmap_output) in [(&streamed, false), (&mapped, true)] {
+                let mut converter = Converter::new(&iso_path, output)
+                    .threads(2)
+                    .mmap_output(mmap_output)
+                    .hash(true);
+                if let Some(layout) = layout {
+                    converter = converter.subchannel(layout);
+                }
+                let summary = converter.run().unwrap();
+                assert_eq!(summary.bytes_written, fs::metadata(output).unwrap().len());
+                hashes.push(summary.hashes.unwrap());
+            }
+
+            let written = fs::read(&streamed).unwrap();
+            assert!(written == fs::read(&mapped).unwrap());
+
+            // Both writers hash exactly the bytes that reached the file
+            let mut hasher = ImageHasher::new();
+            hasher.update(&written);
+            let expected = hasher.finish();
+            assert_eq!(hashes, [expected, expected]);
+        }
+
+        let sizes = fs::metadata(&mapped).unwrap().len();
+        for path in [&iso_path, &streamed, &mapped] {
+            let _ = fs::remove_file(path);
+        }
+        assert_eq!(sizes, 300 * 2448);
+    }
+
+    #[test]
+    fn test_cancelled_mmap_output_keeps_written_sectors() {
+        let iso_path = temp_path("mapped-cancel.iso");
+        let bin_path = temp_path("mapped-cancel.bin");
+        fs::write(&iso_path, test_iso(1000)).unwrap();
+
+        let token = CancellationToken::new();
+        let result = Converter::new(&iso_path, &bin_path)
+            .threads(1)
+            .mmap_output(true)
+            .observer(Canceller(token.clone()))
+            .cancellation_token(token)
+            .run();
+
+        let written = fs::metadata(&bin_path).unwrap().len();
+        let _ = fs::remove_file(&iso_path);
+        let _ = fs::remove_file(&bin_path);
+
+        assert!(result.unwrap_err().is::<Cancelled>());
+        assert_eq!(written, 64 * RAW_SECTOR_SIZE as u64);
+    }
+
+    #[test]
+    fn test_reencode_warns_about_damaged_sectors() {
+        let mut raw: Vec<u8> = test_iso(20)
+            .chunks(ISO_SECTOR_SIZE)
+            .enumerate()
+            .flat_map(|(lba, data)| convert_iso_to_raw(lba as u32, data).unwrap())
+            .collect();
+        raw[5 * RAW_SECTOR_SIZE + 100] ^= 0xFF;
+
+        let path = temp_path("damaged.bin");
+        fs::write(&path, &raw).unwrap();
+
+        let summary = Converter::new(&path, Output::writer(std::io::sink()))
+            .raw_input(RawInputPolicy::Reencode)
+            .run();
+
+        let _ = fs::remove_file(&path);
+        let sector_warnings: Vec<Warning> = summary
+            .unwrap()
+            .warnings
+            .into_iter()
+            .filter(|warning| warning.sector.is_some())
+            .collect();
+        assert_eq!(sector_warnings.len(), 1);
+        assert_eq!(sector_warnings[0].sector, Some(5));
+        assert!(sector_warnings[0].message.contains("EDC"));
+    }
+}
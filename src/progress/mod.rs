@@ -0,0 +1,354 @@
+// Conversion progress reporting
+//
+// The pipeline reports to a ProgressObserver: once when it starts, after
+// every written batch, for every warning about the input, and once when it
+// finishes. BarObserver drives an indicatif bar for the terminal and
+// BatchBarObserver one bar across the files of a batch;
+// JsonLinesObserver writes one JSON object per event for job runners.
+
+use indicatif::ProgressBar;
+use std::fmt;
+use std::io::Write;
+use std::time::Duration;
+
+/// Conversion progress after a written batch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub sectors_done: usize,
+    /// Unknown for streamed input
+    pub total_sectors: Option<usize>,
+    /// Bytes written to the output, including interleaved subchannel data
+    pub bytes_written: u64,
+    /// Time since the conversion started
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    pub fn sectors_per_second(&self) -> f64 {
+        rate(self.sectors_done as f64, self.elapsed)
+    }
+
+    pub fn bytes_per_second(&self) -> f64 {
+        rate(self.bytes_written as f64, self.elapsed)
+    }
+}
+
+/// Rate of `amount` per second, or 0.0 before any measurable time has
+/// passed. Progress events and command reports both use it, so a run too
+/// short to time reports the same rate in either.
+pub fn rate(amount: f64, elapsed: Duration) -> f64 {
+    let seconds = elapsed.as_secs_f64();
+    if seconds > 0.0 {
+        amount / seconds
+    } else {
+        0.0
+    }
+}
+
+/// A problem with the input that did not stop the conversion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    /// Sector the warning is about, if it concerns a single one
+    pub sector: Option<usize>,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            sector: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn sector(sector: usize, message: impl Into<String>) -> Self {
+        Self {
+            sector: Some(sector),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.sector {
+            Some(sector) => write!(f, "sector {sector}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+/// Receives the events of a running conversion. Every method is called from
+/// the thread that runs the conversion, in order.
+pub trait ProgressObserver: Send {
+    /// Called before the first sector is written.
+    fn started(&mut self, _total_sectors: Option<usize>) {}
+
+    /// Called after every written batch.
+    fn progress(&mut self, progress: &Progress);
+
+    fn warning(&mut self, _warning: &Warning) {}
+
+    /// Called once the output is flushed, with the final progress.
+    fn finished(&mut self, _progress: &Progress) {}
+}
+
+/// Shows progress on an indicatif bar or spinner, printing warnings above it.
+pub struct BarObserver {
+    bar: ProgressBar,
+}
+
+impl BarObserver {
+    pub fn new(bar: ProgressBar) -> Self {
+        Self { bar }
+    }
+}
+
+impl ProgressObserver for BarObserver {
+    fn started(&mut self, total_sectors: Option<usize>) {
+        if let Some(total_sectors) = total_sectors {
+            self.bar.set_length(total_sectors as u64);
+        }
+    }
+
+    fn progress(&mut self, progress: &Progress) {
+        self.bar.set_position(progress.sectors_done as u64);
+    }
+
+    fn warning(&mut self, warning: &Warning) {
+        self.bar.suspend(|| eprintln!("Warning: {warning}"));
+    }
+
+    fn finished(&mut self, _progress: &Progress) {
+        self.bar.finish_with_message("Conversion complete");
+    }
+}
+
+/// Advances a bar shared by the conversions of a batch. The bar counts input
+/// bytes, since the sector count of each file is only known once it is
+/// opened; this conversion covers `input_bytes` of it from `offset` on.
+pub struct BatchBarObserver {
+    bar: ProgressBar,
+    label: String,
+    offset: u64,
+    input_bytes: u64,
+    total_sectors: Option<usize>,
+}
+
+impl BatchBarObserver {
+    /// `label` names the file in warnings.
+    pub fn new(bar: ProgressBar, label: impl Into<String>, offset: u64, input_bytes: u64) -> Self {
+        Self {
+            bar,
+            label: label.into(),
+            offset,
+            input_bytes,
+            total_sectors: None,
+        }
+    }
+
+    fn position(&self, sectors_done: usize) -> u64 {
+        match self.total_sectors {
+            Some(total) if total > 0 => {
+                let done = sectors_done.min(total) as u128 * self.input_bytes as u128;
+                self.offset + (done / total as u128) as u64
+            }
+            _ => self.offset,
+        }
+    }
+}
+
+impl ProgressObserver for BatchBarObserver {
+    fn started(&mut self, total_sectors: Option<usize>) {
+        self.total_sectors = total_sectors;
+        self.bar.set_position(self.offset);
+    }
+
+    fn progress(&mut self, progress: &Progress) {
+        self.bar.set_position(self.position(progress.sectors_done));
+    }
+
+    fn warning(&mut self, warning: &Warning) {
+        self.bar
+            .suspend(|| eprintln!("Warning: {}: {warning}", self.label));
+    }
+
+    fn finished(&mut self, _progress: &Progress) {
+        self.bar.set_position(self.offset + self.input_bytes);
+    }
+}
+
+/// Writes every event as one JSON object per line, such as
+/// `{"event":"progress","sectors_done":128,...}`. Write errors are ignored so
+/// that a closed pipe never stops the conversion.
+pub struct JsonLinesObserver<W> {
+    writer: W,
+}
+
+impl<W: Write + Send> JsonLinesObserver<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn emit(&mut self, event: &str, fields: &[(&str, String)]) {
+        let mut line = format!("{{\"event\":\"{event}\"");
+        for (name, value) in fields {
+            line.push_str(&format!(",\"{name}\":{value}"));
+        }
+        line.push('}');
+
+        let _ = writeln!(self.writer, "{line}");
+        let _ = self.writer.flush();
+    }
+
+    fn emit_progress(&mut self, event: &str, progress: &Progress) {
+        self.emit(
+            event,
+            &[
+                ("sectors_done", progress.sectors_done.to_string()),
+                ("total_sectors", json_option(progress.total_sectors)),
+                ("bytes_written", progress.bytes_written.to_string()),
+                (
+                    "elapsed_secs",
+                    format!("{:.3}", progress.elapsed.as_secs_f64()),
+                ),
+                (
+                    "sectors_per_second",
+                    format!("{:.1}", progress.sectors_per_second()),
+                ),
+                (
+                    "bytes_per_second",
+                    format!("{:.1}", progress.bytes_per_second()),
+                ),
+            ],
+        );
+    }
+}
+
+impl<W: Write + Send> ProgressObserver for JsonLinesObserver<W> {
+    fn started(&mut self, total_sectors: Option<usize>) {
+        self.emit("started", &[("total_sectors", json_option(total_sectors))]);
+    }
+
+    fn progress(&mut self, progress: &Progress) {
+        self.emit_progress("progress", progress);
+    }
+
+    fn warning(&mut self, warning: &Warning) {
+        self.emit(
+            "warning",
+            &[
+                ("sector", json_option(warning.sector)),
+                ("message", json_string(&warning.message)),
+            ],
+        );
+    }
+
+    fn finished(&mut self, progress: &Progress) {
+        self.emit_progress("finished", progress);
+    }
+}
+
+fn json_option<T: fmt::Display>(value: Option<T>) -> String {
+    value.map_or_else(|| "null".to_string(), |value| value.to_string())
+}
+
+/// Quotes and escapes a string as a JSON string literal.
+pub fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_lines() {
+        let mut observer = JsonLinesObserver::new(Vec::new());
+        let progress = Progress {
+            sectors_done: 128,
+            total_sectors: None,
+            bytes_written: 128 * 2352,
+            elapsed: Duration::from_millis(500),
+        };
+
+        observer.started(Some(1000));
+        observer.progress(&progress);
+        observer.warning(&Warning::sector(7, "EDC \"mismatch\"\n"));
+        observer.warning(&Warning::new("no volume descriptor"));
+
+        let output = String::from_utf8(observer.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], r#"{"event":"started","total_sectors":1000}"#);
+        assert_eq!(
+            lines[1],
+            r#"{"event":"progress","sectors_done":128,"total_sectors":null,"bytes_written":301056,"elapsed_secs":0.500,"sectors_per_second":256.0,"bytes_per_second":602112.0}"#
+        );
+        assert_eq!(
+            lines[2],
+            r#"{"event":"warning","sector":7,"message":"EDC \"mismatch\"\n"}"#
+        );
+        assert_eq!(
+            lines[3],
+            r#"{"event":"warning","sector":null,"message":"no volume descriptor"}"#
+        );
+    }
+
+    #[test]
+    fn test_rate() {
+        assert_eq!(rate(300.0, Duration::from_millis(1500)), 200.0);
+        assert_eq!(rate(300.0, Duration::ZERO), 0.0);
+    }
+
+    #[test]
+    fn test_batch_bar_position() {
+        let bar = ProgressBar::hidden();
+        bar.set_length(3000);
+        let mut observer = BatchBarObserver::new(bar.clone(), "b.iso", 1000, 2000);
+        let progress = |sectors_done| Progress {
+            sectors_done,
+            total_sectors: Some(4),
+            bytes_written: 0,
+            elapsed: Duration::ZERO,
+        };
+
+        observer.started(Some(4));
+        assert_eq!(bar.position(), 1000);
+        observer.progress(&progress(1));
+        assert_eq!(bar.position(), 1500);
+        observer.finished(&progress(4));
+        assert_eq!(bar.position(), 3000);
+    }
+
+    #[test]
+    fn test_throughput_before_any_time_elapsed() {
+        let progress = Progress {
+            sectors_done: 0,
+            total_sectors: Some(10),
+            bytes_written: 0,
+            elapsed: Duration::ZERO,
+        };
+        assert_eq!(progress.sectors_per_second(), 0.0);
+        assert_eq!(progress.bytes_per_second(), 0.0);
+    }
+}
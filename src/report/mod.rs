@@ -0,0 +1,281 @@
+// Machine-readable command reports
+//
+// With --format json every command prints one JSON object describing what it
+// did instead of its text output. JsonObject collects the fields as the
+// command runs, keeping them in insertion order, and Json converts the
+// values the commands work with: numbers, strings, paths, options, lists
+// and the summaries of the library.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::converter::InputLayout;
+use crate::ecm::EcmSummary;
+use crate::hash::ImageHashes;
+use crate::progress::{json_string, Warning};
+use crate::verify::FailureRange;
+
+/// A JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    /// A number, already formatted
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(JsonObject),
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool(value) => write!(f, "{value}"),
+            Self::Number(value) => f.write_str(value),
+            Self::String(value) => f.write_str(&json_string(value)),
+            Self::Array(values) => {
+                f.write_str("[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                f.write_str("]")
+            }
+            Self::Object(object) => write!(f, "{object}"),
+        }
+    }
+}
+
+/// A JSON object whose fields keep the order they were inserted in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsonObject {
+    fields: Vec<(String, Json)>,
+}
+
+impl JsonObject {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets a field, replacing the value of an existing one in place.
+    pub fn insert(&mut self, name: &str, value: impl Into<Json>) {
+        let value = value.into();
+        match self.fields.iter_mut().find(|(field, _)| field == name) {
+            Some((_, existing)) => *existing = value,
+            None => self.fields.push((name.to_string(), value)),
+        }
+    }
+
+    /// Builder form of `insert`.
+    pub fn with(mut self, name: &str, value: impl Into<Json>) -> Self {
+        self.insert(name, value);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Json> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+}
+
+impl fmt::Display for JsonObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{")?;
+        for (i, (name, value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}:{value}", json_string(name))?;
+        }
+        f.write_str("}")
+    }
+}
+
+impl From<JsonObject> for Json {
+    fn from(object: JsonObject) -> Self {
+        Self::Object(object)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+macro_rules! impl_from_integer {
+    ($($integer:ty),*) => {
+        $(
+            impl From<$integer> for Json {
+                fn from(value: $integer) -> Self {
+                    Self::Number(value.to_string())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_integer!(u8, u16, u32, u64, usize, i32, i64);
+
+impl From<f64> for Json {
+    /// Rates and ratios, rounded to three decimals. JSON has no infinity or
+    /// NaN, so those become null.
+    fn from(value: f64) -> Self {
+        if value.is_finite() {
+            Self::Number(format!("{value:.3}"))
+        } else {
+            Self::Null
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&Path> for Json {
+    fn from(path: &Path) -> Self {
+        Self::String(path.to_string_lossy().into_owned())
+    }
+}
+
+impl From<&PathBuf> for Json {
+    fn from(path: &PathBuf) -> Self {
+        path.as_path().into()
+    }
+}
+
+impl From<PathBuf> for Json {
+    fn from(path: PathBuf) -> Self {
+        path.as_path().into()
+    }
+}
+
+/// Seconds, with millisecond precision.
+impl From<Duration> for Json {
+    fn from(duration: Duration) -> Self {
+        Self::Number(format!("{:.3}", duration.as_secs_f64()))
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Self::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(values: Vec<T>) -> Self {
+        Self::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<InputLayout> for Json {
+    fn from(layout: InputLayout) -> Self {
+        let name = match layout {
+            InputLayout::Iso => "iso",
+            InputLayout::Mode2Payload => "mode2-payload",
+            InputLayout::Raw => "raw",
+            InputLayout::RawWithSubchannel => "raw-with-subchannel",
+        };
+        name.into()
+    }
+}
+
+/// `{"size":…,"crc32":"…","md5":"…","sha1":"…"}` with lowercase hex digests.
+impl From<&ImageHashes> for Json {
+    fn from(hashes: &ImageHashes) -> Self {
+        JsonObject::new()
+            .with("size", hashes.size)
+            .with("crc32", hashes.crc32_hex())
+            .with("md5", hashes.md5_hex())
+            .with("sha1", hashes.sha1_hex())
+            .into()
+    }
+}
+
+impl From<&Warning> for Json {
+    fn from(warning: &Warning) -> Self {
+        JsonObject::new()
+            .with("sector", warning.sector)
+            .with("message", warning.message.as_str())
+            .into()
+    }
+}
+
+/// `{"first_lba":…,"last_lba":…,"failures":["EDC",…]}`
+impl From<&FailureRange> for Json {
+    fn from(range: &FailureRange) -> Self {
+        let failures: Vec<String> = range.failures.iter().map(ToString::to_string).collect();
+        JsonObject::new()
+            .with("first_lba", *range.lbas.start())
+            .with("last_lba", *range.lbas.end())
+            .with("failures", failures)
+            .into()
+    }
+}
+
+impl From<&EcmSummary> for Json {
+    fn from(summary: &EcmSummary) -> Self {
+        JsonObject::new()
+            .with("bin_bytes", summary.bin_bytes)
+            .with("ecm_bytes", summary.ecm_bytes)
+            .with("ratio", summary.ratio())
+            .with("mode1_sectors", summary.mode1_sectors)
+            .with("mode2_form1_sectors", summary.mode2_form1_sectors)
+            .with("mode2_form2_sectors", summary.mode2_form2_sectors)
+            .with("literal_bytes", summary.literal_bytes)
+            .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_output() {
+        let mut report = JsonObject::new()
+            .with("command", "verify")
+            .with("input", Path::new("C:\\games\\\"a\".bin"))
+            .with("sectors", 300usize)
+            .with("elapsed_secs", Duration::from_millis(1500))
+            .with("throughput", f64::INFINITY)
+            .with("hashes", None::<&ImageHashes>)
+            .with("warnings", vec![&Warning::sector(3, "bad EDC")]);
+        report.insert("sectors", 301usize);
+        report.insert("success", true);
+
+        assert_eq!(
+            report.to_string(),
+            r#"{"command":"verify","input":"C:\\games\\\"a\".bin","sectors":301,"elapsed_secs":1.500,"throughput":null,"hashes":null,"warnings":[{"sector":3,"message":"bad EDC"}],"success":true}"#
+        );
+        assert_eq!(report.get("success"), Some(&Json::Bool(true)));
+    }
+
+    #[test]
+    fn test_failure_range() {
+        let range = FailureRange {
+            lbas: 16..=17,
+            failures: vec![crate::verify::Failure::Edc],
+        };
+        assert_eq!(
+            Json::from(&range).to_string(),
+            r#"{"first_lba":16,"last_lba":17,"failures":["EDC"]}"#
+        );
+    }
+}
@@ -0,0 +1,245 @@
+// P and Q subchannel generation
+//
+// Every 2352-byte sector is accompanied by 96 bytes of subchannel data, one
+// bit per channel (P, Q, R..W) in each byte when interleaved as read from the
+// disc. Only P and Q carry information on data and audio discs: P flags the
+// pause before a track, Q holds the position (mode 1 ADR). R..W are left zero.
+
+use crate::converter::{SectorAddress, RAW_SECTOR_SIZE};
+use crate::cue::{CueSheet, CueTrack, TrackMode};
+
+pub const SUBCHANNEL_SIZE: usize = 96;
+pub const RAW_SECTOR_WITH_SUBCHANNEL_SIZE: usize = RAW_SECTOR_SIZE + SUBCHANNEL_SIZE;
+
+const CHANNEL_SIZE: usize = 12;
+const ADR_POSITION: u8 = 0x01;
+const CONTROL_DATA: u8 = 0x04;
+
+/// Where subchannel data is written alongside the sectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubchannelLayout {
+    /// 96 bytes per sector in a separate `.sub` file
+    Separate,
+    /// Appended to every sector, giving 2448-byte sectors
+    Interleaved,
+}
+
+/// P and Q channels of one sector. R..W are always zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subchannel {
+    pub p: [u8; CHANNEL_SIZE],
+    pub q: [u8; CHANNEL_SIZE],
+}
+
+impl Subchannel {
+    /// Channels one after another (P, Q, R..W), the layout of `.sub` files.
+    pub fn to_bytes(&self) -> [u8; SUBCHANNEL_SIZE] {
+        let mut bytes = [0u8; SUBCHANNEL_SIZE];
+        bytes[..CHANNEL_SIZE].copy_from_slice(&self.p);
+        bytes[CHANNEL_SIZE..2 * CHANNEL_SIZE].copy_from_slice(&self.q);
+        bytes
+    }
+
+    /// One bit per channel in every byte (P in bit 7, Q in bit 6), the raw
+    /// layout that follows each sector in 2448-byte images.
+    pub fn to_interleaved(&self) -> [u8; SUBCHANNEL_SIZE] {
+        let mut bytes = [0u8; SUBCHANNEL_SIZE];
+
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            let mask = 0x80 >> (i % 8);
+            if self.p[i / 8] & mask != 0 {
+                *byte |= 0x80;
+            }
+            if self.q[i / 8] & mask != 0 {
+                *byte |= 0x40;
+            }
+        }
+
+        bytes
+    }
+}
+
+/// Builds the subchannel of every sector of a BIN file from its cue sheet.
+#[derive(Debug, Clone)]
+pub struct SubchannelGenerator {
+    tracks: Vec<CueTrack>,
+}
+
+impl SubchannelGenerator {
+    pub fn new(cue_sheet: &CueSheet) -> Self {
+        Self {
+            tracks: cue_sheet.tracks.clone(),
+        }
+    }
+
+    /// Subchannel of the sector at `lba`, counted from the start of the BIN.
+    pub fn generate(&self, lba: u32) -> Subchannel {
+        let track = self
+            .tracks
+            .iter()
+            .rev()
+            .find(|track| start_of(track) <= lba)
+            .or(self.tracks.first());
+
+        let (number, control, index, relative) = match track {
+            Some(track) => {
+                let index = track
+                    .indexes
+                    .iter()
+                    .rev()
+                    .find(|index| index.position <= lba)
+                    .map_or(1, |index| index.number);
+                let index_one = track
+                    .indexes
+                    .iter()
+                    .find(|index| index.number == 1)
+                    .map_or(start_of(track), |index| index.position);
+
+                // Relative time counts down to INDEX 01 within the pregap
+                let relative = index_one.abs_diff(lba);
+                let control = match track.mode {
+                    TrackMode::Audio => 0,
+                    TrackMode::Mode1 | TrackMode::Mode2 => CONTROL_DATA,
+                };
+
+                (track.number, control, index, relative)
+            }
+            None => (1, CONTROL_DATA, 1, lba),
+        };
+
+        let mut q = [0u8; CHANNEL_SIZE];
+        q[0] = (control << 4) | ADR_POSITION;
+        q[1] = to_bcd(number);
+        q[2] = to_bcd(index);
+        q[3..6].copy_from_slice(&SectorAddress::from_frames(relative).to_bcd());
+        q[7..10].copy_from_slice(&SectorAddress::from_lba(lba).to_bcd());
+
+        let crc = !crc16(&q[..10]);
+        q[10..12].copy_from_slice(&crc.to_be_bytes());
+
+        let pause = if index == 0 { 0xFF } else { 0x00 };
+
+        Subchannel {
+            p: [pause; CHANNEL_SIZE],
+            q,
+        }
+    }
+}
+
+fn start_of(track: &CueTrack) -> u32 {
+    track.indexes.first().map_or(0, |index| index.position)
+}
+
+fn to_bcd(value: u8) -> u8 {
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// CRC-16/CCITT (polynomial 0x1021, initial value 0) protecting the Q channel.
+/// The value is stored inverted.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cue::{CueIndex, FileType};
+
+    fn two_track_cue() -> CueSheet {
+        CueSheet {
+            file_name: "disc.bin".into(),
+            file_type: FileType::Binary,
+            tracks: vec![
+                CueTrack {
+                    number: 1,
+                    mode: TrackMode::Mode1,
+                    pregap: 0,
+                    indexes: vec![CueIndex {
+                        number: 1,
+                        position: 0,
+                    }],
+                },
+                CueTrack {
+                    number: 2,
+                    mode: TrackMode::Audio,
+                    pregap: 0,
+                    indexes: vec![
+                        CueIndex {
+                            number: 0,
+                            position: 1000,
+                        },
+                        CueIndex {
+                            number: 1,
+                            position: 1150,
+                        },
+                    ],
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_crc16() {
+        // CRC-16/XMODEM check value
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+    }
+
+    #[test]
+    fn test_q_channel() {
+        let generator = SubchannelGenerator::new(&two_track_cue());
+
+        let sub = generator.generate(0);
+        assert_eq!(
+            &sub.q[..10],
+            &[0x41, 0x01, 0x01, 0, 0, 0, 0, 0x00, 0x02, 0x00]
+        );
+        assert_eq!(sub.p, [0; 12]);
+
+        // Pregap of track 2 counts down to INDEX 01
+        let sub = generator.generate(1000);
+        assert_eq!(
+            &sub.q[..10],
+            &[0x01, 0x02, 0x00, 0, 0x02, 0, 0, 0x00, 0x15, 0x25]
+        );
+        assert_eq!(sub.p, [0xFF; 12]);
+
+        let sub = generator.generate(1151);
+        assert_eq!(
+            &sub.q[..10],
+            &[0x01, 0x02, 0x01, 0, 0, 0x01, 0, 0x00, 0x17, 0x26]
+        );
+
+        let crc = !crc16(&sub.q[..10]);
+        assert_eq!(sub.q[10..12], crc.to_be_bytes());
+    }
+
+    #[test]
+    fn test_interleaving() {
+        let generator = SubchannelGenerator::new(&two_track_cue());
+        let sub = generator.generate(1000);
+
+        let deinterleaved = sub.to_bytes();
+        assert_eq!(&deinterleaved[..12], &sub.p);
+        assert_eq!(&deinterleaved[12..24], &sub.q);
+        assert!(deinterleaved[24..].iter().all(|&byte| byte == 0));
+
+        let interleaved = sub.to_interleaved();
+        for (i, byte) in interleaved.iter().enumerate() {
+            let q_bit = (sub.q[i / 8] >> (7 - i % 8)) & 1;
+            assert_eq!(*byte, 0x80 | (q_bit << 6));
+        }
+    }
+}
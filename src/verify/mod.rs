@@ -0,0 +1,399 @@
+use crate::converter::{SectorAddress, SectorKind, RAW_SECTOR_SIZE, SYNC_PATTERN};
+use crate::cue::{CueSheet, TrackMode};
+use crate::edc_ecc;
+use std::fmt;
+use std::ops::{Range, RangeInclusive};
+
+/// A field of a raw sector that does not match its recomputed value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Failure {
+    Sync,
+    HeaderMsf,
+    Mode,
+    Edc,
+    P,
+    Q,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Sync => "sync",
+            Self::HeaderMsf => "header MSF",
+            Self::Mode => "mode",
+            Self::Edc => "EDC",
+            Self::P => "P parity",
+            Self::Q => "Q parity",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Consecutive LBAs that failed verification in the same way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailureRange {
+    pub lbas: RangeInclusive<u32>,
+    pub failures: Vec<Failure>,
+}
+
+/// Sectors matching the sync pattern in fewer bytes than this are taken for
+/// audio or other non-data sectors rather than data sectors with a damaged
+/// sync.
+const MIN_SYNC_BYTES: usize = 6;
+
+/// Whether `raw` starts with a sync pattern, allowing for a few damaged
+/// bytes. Audio sectors, which have none, match only by chance.
+pub fn has_sync(raw: &[u8]) -> bool {
+    let matching = raw
+        .iter()
+        .zip(SYNC_PATTERN.iter())
+        .filter(|(actual, expected)| actual == expected)
+        .count();
+    matching >= MIN_SYNC_BYTES
+}
+
+/// The data sectors of a BIN as laid out by its cue sheet: the sectors of
+/// each data track from INDEX 01 up to the next track, with their LBAs.
+/// Audio tracks and pregaps are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DataLayout {
+    tracks: Vec<DataTrack>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DataTrack {
+    /// Sectors of the track, counted from the start of the BIN
+    sectors: Range<u32>,
+    /// LBA of the first of them
+    first_lba: u32,
+}
+
+impl DataLayout {
+    /// Layout of a BIN holding the `total_sectors` sectors of the tracks of
+    /// `cue`, whose first sector is at `start_lba`. Each `PREGAP` moves the
+    /// LBAs of its track and the ones after it, as it is not stored.
+    pub fn from_cue(cue: &CueSheet, start_lba: u32, total_sectors: u32) -> Self {
+        let mut tracks = Vec::new();
+        let mut pregaps = 0;
+
+        for (i, track) in cue.tracks.iter().enumerate() {
+            pregaps += track.pregap;
+            if track.mode == TrackMode::Audio {
+                continue;
+            }
+            let Some(start) = track.indexes.iter().find(|index| index.number == 1) else {
+                continue;
+            };
+
+            let end = cue.tracks[i + 1..]
+                .iter()
+                .find_map(|next| next.indexes.first())
+                .map_or(total_sectors, |index| index.position)
+                .min(total_sectors);
+            if start.position < end {
+                tracks.push(DataTrack {
+                    sectors: start.position..end,
+                    first_lba: start_lba + pregaps + start.position,
+                });
+            }
+        }
+
+        Self { tracks }
+    }
+
+    /// LBA of the sector at index `sector` of the BIN, or `None` if it is
+    /// not a data sector.
+    pub fn lba(&self, sector: u32) -> Option<u32> {
+        self.tracks
+            .iter()
+            .find(|track| track.sectors.contains(&sector))
+            .map(|track| track.first_lba + (sector - track.sectors.start))
+    }
+}
+
+/// Recomputes sync, header address, EDC and P/Q parity of a 2352-byte sector
+/// and returns every field that disagrees. An empty result means the sector
+/// is consistent. Mode 0 and formless Mode 2 sectors carry no EDC/ECC, and a
+/// zeroed Form 2 EDC is accepted as "not present".
+pub fn verify_sector(lba: u32, raw: &[u8]) -> Vec<Failure> {
+    let mut failures = Vec::new();
+
+    if raw.len() != RAW_SECTOR_SIZE || raw[0..12] != SYNC_PATTERN {
+        failures.push(Failure::Sync);
+        return failures;
+    }
+
+    if raw[12..15] != SectorAddress::from_lba(lba).to_bcd() {
+        failures.push(Failure::HeaderMsf);
+    }
+
+    match SectorKind::detect(raw) {
+        SectorKind::Mode1 => {
+            if !edc_matches(raw, 0..2064, 2064) {
+                failures.push(Failure::Edc);
+            }
+            check_parity(raw, false, &mut failures);
+        }
+        SectorKind::Mode2Form1 => {
+            if !edc_matches(raw, 16..2072, 2072) {
+                failures.push(Failure::Edc);
+            }
+            check_parity(raw, true, &mut failures);
+        }
+        SectorKind::Mode2Form2 => {
+            if raw[2348..2352] != [0; 4] && !edc_matches(raw, 16..2348, 2348) {
+                failures.push(Failure::Edc);
+            }
+        }
+        SectorKind::Mode0 | SectorKind::Mode2Formless => {}
+        SectorKind::Unknown => failures.push(Failure::Mode),
+    }
+
+    failures
+}
+
+fn edc_matches(raw: &[u8], range: Range<usize>, edc_offset: usize) -> bool {
+    let edc = edc_ecc::calc_edc(&raw[range]);
+    raw[edc_offset..edc_offset + 4] == edc.to_le_bytes()
+}
+
+fn check_parity(raw: &[u8], zero_header: bool, failures: &mut Vec<Failure>) {
+    let mut sector = [0u8; RAW_SECTOR_SIZE];
+    sector.copy_from_slice(raw);
+
+    if zero_header {
+        sector[12..16].fill(0);
+    }
+
+    // Q is checked first so that it covers the P bytes as stored
+    edc_ecc::calc_q_parity(&mut sector);
+    let q_ok = sector[2248..2352] == raw[2248..2352];
+
+    edc_ecc::calc_p_parity(&mut sector);
+    if sector[2076..2248] != raw[2076..2248] {
+        failures.push(Failure::P);
+    }
+
+    if !q_ok {
+        failures.push(Failure::Q);
+    }
+}
+
+/// Outcome of an attempt to repair a sector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepairOutcome {
+    /// The sector already verified
+    Intact,
+    /// The sector was corrected and now verifies
+    Repaired { bytes_corrected: usize },
+    /// The sector could not be corrected; it was left unchanged
+    Unrepairable,
+}
+
+/// Repairs a damaged Mode 1 sector in place with the P/Q RS-PC decoder and
+/// accepts the result only if the corrected sector then verifies. The sync
+/// pattern, which parity does not cover, is restored directly; the mode byte
+/// is covered, so it is checked only after correction. A sector that needs no
+/// correction yet fails, such as one whose header MSF names another LBA, is
+/// unrepairable. Other sector types are only verified, since they cannot be
+/// corrected.
+pub fn repair_sector(lba: u32, raw: &mut [u8]) -> RepairOutcome {
+    if verify_sector(lba, raw).is_empty() {
+        return RepairOutcome::Intact;
+    }
+
+    if raw.len() != RAW_SECTOR_SIZE {
+        return RepairOutcome::Unrepairable;
+    }
+
+    let mut sector = [0u8; RAW_SECTOR_SIZE];
+    sector.copy_from_slice(raw);
+
+    let sync_errors = sector[0..12]
+        .iter()
+        .zip(SYNC_PATTERN.iter())
+        .filter(|(actual, expected)| actual != expected)
+        .count();
+    sector[0..12].copy_from_slice(&SYNC_PATTERN);
+
+    let correction = edc_ecc::correct_pq_parity(&mut sector);
+    let bytes_corrected = sync_errors + correction.bytes_corrected;
+    if !correction.consistent
+        || bytes_corrected == 0
+        || sector[15] != 0x01
+        || !verify_sector(lba, &sector).is_empty()
+    {
+        return RepairOutcome::Unrepairable;
+    }
+
+    raw.copy_from_slice(&sector);
+
+    RepairOutcome::Repaired { bytes_corrected }
+}
+
+/// Groups per-sector results (ordered by LBA) into ranges of consecutive
+/// LBAs with identical failures. Sectors without failures are skipped.
+pub fn group_failures<I>(results: I) -> Vec<FailureRange>
+where
+    I: IntoIterator<Item = (u32, Vec<Failure>)>,
+{
+    let mut ranges: Vec<FailureRange> = Vec::new();
+
+    for (lba, failures) in results {
+        if failures.is_empty() {
+            continue;
+        }
+
+        match ranges.last_mut() {
+            Some(last) if *last.lbas.end() + 1 == lba && last.failures == failures => {
+                last.lbas = *last.lbas.start()..=lba;
+            }
+            _ => ranges.push(FailureRange {
+                lbas: lba..=lba,
+                failures,
+            }),
+        }
+    }
+
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::{convert_iso_to_mode2_form1, convert_iso_to_raw, Subheader};
+
+    #[test]
+    fn test_generated_sectors_verify() {
+        let data = vec![0x42u8; 2048];
+
+        let raw = convert_iso_to_raw(10, &data).unwrap();
+        assert!(verify_sector(10, &raw).is_empty());
+
+        let raw = convert_iso_to_mode2_form1(10, &data, Subheader::data()).unwrap();
+        assert!(verify_sector(10, &raw).is_empty());
+    }
+
+    #[test]
+    fn test_failure_classification() {
+        let data = vec![0x42u8; 2048];
+        let raw = convert_iso_to_raw(10, &data).unwrap();
+
+        assert_eq!(verify_sector(11, &raw), vec![Failure::HeaderMsf]);
+
+        let mut damaged = raw.clone();
+        damaged[100] ^= 0x01;
+        assert_eq!(
+            verify_sector(10, &damaged),
+            vec![Failure::Edc, Failure::P, Failure::Q]
+        );
+
+        let mut damaged = raw.clone();
+        damaged[2100] ^= 0x01; // P parity byte
+        assert_eq!(verify_sector(10, &damaged), vec![Failure::P, Failure::Q]);
+
+        let mut damaged = raw;
+        damaged[0] = 0xFF;
+        assert_eq!(verify_sector(10, &damaged), vec![Failure::Sync]);
+    }
+
+    #[test]
+    fn test_repair_sector() {
+        let data: Vec<u8> = (0..2048).map(|i| (i % 256) as u8).collect();
+        let original = convert_iso_to_raw(42, &data).unwrap();
+
+        let mut sector = original.clone();
+        assert_eq!(repair_sector(42, &mut sector), RepairOutcome::Intact);
+
+        sector[3] = 0x00;
+        sector[16] ^= 0x10;
+        sector[1000] ^= 0x01;
+        assert_eq!(
+            repair_sector(42, &mut sector),
+            RepairOutcome::Repaired { bytes_corrected: 3 }
+        );
+        assert_eq!(sector, original);
+
+        // The mode byte is covered by parity as well
+        sector[15] = 0x81;
+        assert_eq!(
+            repair_sector(42, &mut sector),
+            RepairOutcome::Repaired { bytes_corrected: 1 }
+        );
+        assert_eq!(sector, original);
+
+        // Too many errors in one row: left untouched
+        sector[16..216].iter_mut().for_each(|byte| *byte ^= 0xFF);
+        let damaged = sector.clone();
+        assert_eq!(repair_sector(42, &mut sector), RepairOutcome::Unrepairable);
+        assert_eq!(sector, damaged);
+
+        // A consistent sector of another LBA needs no correction, but is
+        // still wrong here
+        let mut sector = original.clone();
+        assert_eq!(repair_sector(43, &mut sector), RepairOutcome::Unrepairable);
+        assert_eq!(sector, original);
+    }
+
+    #[test]
+    fn test_sync_detection() {
+        let raw = convert_iso_to_raw(10, &[0x42; 2048]).unwrap();
+        assert!(has_sync(&raw));
+
+        let mut damaged = raw;
+        damaged[1..4].fill(0x00);
+        assert!(has_sync(&damaged));
+
+        // Digital silence and noise
+        assert!(!has_sync(&[0u8; RAW_SECTOR_SIZE]));
+        assert!(!has_sync(&[0x5A; RAW_SECTOR_SIZE]));
+    }
+
+    #[test]
+    fn test_data_layout() {
+        let cue = crate::cue::parse_cue_sheet(
+            "FILE \"disc.bin\" BINARY\n\
+             TRACK 01 MODE1/2352\n\
+             INDEX 01 00:00:00\n\
+             TRACK 02 AUDIO\n\
+             INDEX 00 00:00:10\n\
+             INDEX 01 00:00:20\n\
+             TRACK 03 MODE1/2352\n\
+             PREGAP 00:00:05\n\
+             INDEX 00 00:00:30\n\
+             INDEX 01 00:00:40\n",
+        )
+        .unwrap();
+
+        let layout = DataLayout::from_cue(&cue[0], 0, 50);
+        assert_eq!(layout.lba(0), Some(0));
+        assert_eq!(layout.lba(9), Some(9));
+        // Audio track and the pregap of track 03
+        assert_eq!(layout.lba(10), None);
+        assert_eq!(layout.lba(39), None);
+        // PREGAP sectors are not in the BIN but still take LBAs
+        assert_eq!(layout.lba(40), Some(45));
+        assert_eq!(layout.lba(49), Some(54));
+        assert_eq!(layout.lba(50), None);
+
+        let layout = DataLayout::from_cue(&cue[0], 1000, 50);
+        assert_eq!(layout.lba(0), Some(1000));
+    }
+
+    #[test]
+    fn test_group_failures() {
+        let results = vec![
+            (0, vec![]),
+            (1, vec![Failure::Edc]),
+            (2, vec![Failure::Edc]),
+            (3, vec![Failure::Q]),
+            (5, vec![Failure::Q]),
+        ];
+
+        let ranges = group_failures(results);
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges[0].lbas, 1..=2);
+        assert_eq!(ranges[1].lbas, 3..=3);
+        assert_eq!(ranges[2].lbas, 5..=5);
+    }
+}
@@ -1,4 +1,4 @@
-use iso2raw::converter::{Mode1Sector, RAW_SECTOR_SIZE, SYNC_PATTERN};
+use iso2raw::converter::{encode_mode1_into, RAW_SECTOR_SIZE, SYNC_PATTERN};
 use iso2raw::edc_ecc::calc_edc;
 
 #[test]
@@ -32,12 +32,8 @@ fn test_edc_calculation_properties() {
 fn test_complete_sector_generation() {
     // Create a test sector with known data
     let test_data = vec![0xAAu8; 2048];
-    let mut sector = Mode1Sector::new(0, &test_data).unwrap();
-    sector.calculate_edc_ecc();
-
-    // Convert to bytes
     let mut raw_sector = vec![0u8; RAW_SECTOR_SIZE];
-    sector.to_bytes(&mut raw_sector);
+    encode_mode1_into(0, &test_data, &mut raw_sector).unwrap();
 
     // Verify structure
     assert_eq!(&raw_sector[0..12], &SYNC_PATTERN);
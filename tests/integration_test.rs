@@ -1,6 +1,7 @@
 use std::fs;
+use std::io::Write;
 use std::path::Path;
-use std::process::Command;
+use std::process::{Command, Stdio};
 
 #[test]
 fn test_iso_to_raw_conversion() {
@@ -45,4 +46,955 @@ fn test_iso_to_raw_conversion() {
     // Clean up
     let _ = fs::remove_file(test_iso);
     let _ = fs::remove_file(test_bin);
+    let _ = fs::remove_file(test_bin.replace(".bin", ".cue"));
+}
+
+#[test]
+fn test_raw_to_iso_round_trip() {
+    let test_iso = "test_raw2iso.iso";
+    let test_bin = "test_raw2iso.bin";
+    let test_out = "test_raw2iso_out.iso";
+
+    let iso_data: Vec<u8> = (0..2048 * 8).map(|i| (i % 251) as u8).collect();
+    fs::write(test_iso, &iso_data).expect("Failed to create test ISO");
+
+    let output = Command::new("cargo")
+        .args(["run", "--", test_iso, "-o", test_bin, "-q"])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(output.status.success(), "iso2raw failed");
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "raw2iso", test_bin, "-o", test_out, "-q"])
+        .output()
+        .expect("Failed to execute iso2raw raw2iso");
+
+    if !output.status.success() {
+        eprintln!("stdout: {}", String::from_utf8_lossy(&output.stdout));
+        eprintln!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+        panic!("iso2raw raw2iso failed");
+    }
+
+    let round_trip = fs::read(test_out).expect("Failed to read extracted ISO");
+    assert_eq!(round_trip, iso_data, "Extracted ISO differs from original");
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_bin);
+    let _ = fs::remove_file(test_out);
+    let _ = fs::remove_file(test_bin.replace(".bin", ".cue"));
+}
+
+#[test]
+fn test_verify_detects_damaged_sector() {
+    let test_iso = "test_verify.iso";
+    let test_bin = "test_verify.bin";
+
+    fs::write(test_iso, vec![0x5Au8; 2048 * 4]).expect("Failed to create test ISO");
+
+    let output = Command::new("cargo")
+        .args(["run", "--", test_iso, "-o", test_bin, "-q"])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(output.status.success(), "iso2raw failed");
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "verify", test_bin, "-q"])
+        .output()
+        .expect("Failed to execute iso2raw verify");
+    assert!(
+        output.status.success(),
+        "Freshly generated image failed to verify"
+    );
+
+    // Flip one user data byte in sector 2
+    let mut bin_data = fs::read(test_bin).expect("Failed to read output file");
+    bin_data[2352 * 2 + 100] ^= 0xFF;
+    fs::write(test_bin, &bin_data).expect("Failed to write damaged image");
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "verify", test_bin, "-q"])
+        .output()
+        .expect("Failed to execute iso2raw verify");
+    assert!(
+        !output.status.success(),
+        "Damaged image passed verification"
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("LBA 2: EDC, P parity, Q parity"),
+        "{stdout}"
+    );
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_bin);
+    let _ = fs::remove_file(test_bin.replace(".bin", ".cue"));
+}
+
+/// A 44.1 kHz, 16-bit stereo WAVE file holding `pcm`.
+fn wav_file(pcm: &[u8]) -> Vec<u8> {
+    let mut bytes = b"RIFF".to_vec();
+    bytes.extend_from_slice(&(36 + pcm.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&44_100u32.to_le_bytes());
+    bytes.extend_from_slice(&176_400u32.to_le_bytes());
+    bytes.extend_from_slice(&4u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&(pcm.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(pcm);
+    bytes
+}
+
+#[test]
+fn test_assemble_mixed_mode_image() {
+    let test_iso = "test_assemble.iso";
+    let test_wav = "test_assemble.wav";
+    let test_manifest = "test_assemble.txt";
+    let test_bin = "test_assemble.bin";
+    let test_cue = "test_assemble.cue";
+
+    fs::write(test_iso, vec![0x5Au8; 2048 * 4]).expect("Failed to create test ISO");
+    let pcm: Vec<u8> = (0..7000).map(|i| (i % 251) as u8).collect();
+    fs::write(test_wav, wav_file(&pcm)).expect("Failed to create test WAV");
+    fs::write(
+        test_manifest,
+        format!(
+            "FILE \"{test_iso}\" ISO\n  TRACK 01 MODE1/2352\n\
+             FILE \"{test_wav}\" WAVE\n  TRACK 02 AUDIO\n  PREGAP 00:00:10\n\
+             FILE \"{test_iso}\" ISO\n  TRACK 03 MODE1/2352\n  PREGAP 00:00:05\n"
+        ),
+    )
+    .expect("Failed to create manifest");
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "assemble", test_manifest, "-o", test_bin, "-q"])
+        .output()
+        .expect("Failed to execute iso2raw assemble");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Track 02: pregap at 4, audio at 14; track 03: pregap at 17, data at 22
+    let cue = fs::read_to_string(test_cue).expect("Failed to read cue sheet");
+    assert_eq!(
+        cue,
+        "FILE \"test_assemble.bin\" BINARY\r\n\
+         \x20 TRACK 01 MODE1/2352\r\n\
+         \x20   INDEX 01 00:00:00\r\n\
+         \x20 TRACK 02 AUDIO\r\n\
+         \x20   INDEX 00 00:00:04\r\n\
+         \x20   INDEX 01 00:00:14\r\n\
+         \x20 TRACK 03 MODE1/2352\r\n\
+         \x20   INDEX 00 00:00:17\r\n\
+         \x20   INDEX 01 00:00:22\r\n"
+    );
+
+    // Pregap sectors are stored, and the partial audio sector is padded
+    let bin = fs::read(test_bin).expect("Failed to read output file");
+    assert_eq!(bin.len(), 2352 * (4 + 10 + 3 + 5 + 4));
+    assert_eq!(&bin[2352 * 14..2352 * 14 + 7000], &pcm[..]);
+    assert!(bin[2352 * 14 + 7000..2352 * 17].iter().all(|&b| b == 0));
+
+    // Data sector headers carry on from the LBAs of the earlier tracks:
+    // LBA 17 is 00:02:17 and LBA 22 is 00:02:22
+    assert_eq!(
+        &bin[2352 * 17 + 12..2352 * 17 + 16],
+        &[0x00, 0x02, 0x17, 0x01]
+    );
+    assert_eq!(
+        &bin[2352 * 22 + 12..2352 * 22 + 16],
+        &[0x00, 0x02, 0x22, 0x01]
+    );
+    assert_eq!(&bin[2352 * 22 + 16..2352 * 22 + 20], &[0x5A; 4]);
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "verify", test_bin, "-q"])
+        .output()
+        .expect("Failed to execute iso2raw verify");
+    assert!(output.status.success(), "Assembled image failed to verify");
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_wav);
+    let _ = fs::remove_file(test_manifest);
+    let _ = fs::remove_file(test_bin);
+    let _ = fs::remove_file(test_cue);
+}
+
+#[test]
+fn test_verify_skips_audio_tracks() {
+    let test_iso = "test_verify_mixed.iso";
+    let test_pcm = "test_verify_mixed.pcm";
+    let test_manifest = "test_verify_mixed.txt";
+    let test_bin = "test_verify_mixed.bin";
+    let test_cue = "test_verify_mixed.cue";
+
+    fs::write(test_iso, vec![0x5Au8; 2048 * 4]).expect("Failed to create test ISO");
+    fs::write(test_pcm, vec![0x33u8; 2352 * 3]).expect("Failed to create test PCM");
+    fs::write(
+        test_manifest,
+        format!("FILE \"{test_iso}\" ISO\n  TRACK 01 MODE1/2352\nFILE \"{test_pcm}\" BINARY\n  TRACK 02 AUDIO\n"),
+    )
+    .expect("Failed to create manifest");
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "assemble", test_manifest, "-o", test_bin, "-q"])
+        .output()
+        .expect("Failed to execute iso2raw assemble");
+    assert!(output.status.success(), "iso2raw assemble failed");
+
+    // The cue sheet next to the BIN marks the pregap and audio sectors
+    let output = Command::new("cargo")
+        .args(["run", "--", "verify", test_bin, "-q"])
+        .output()
+        .expect("Failed to execute iso2raw verify");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+    assert!(
+        stdout.contains("Skipped 153 audio and pregap sectors"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("All 4 data sectors are consistent"),
+        "{stdout}"
+    );
+
+    // Without it, sectors lacking a sync pattern are not data sectors;
+    // the zeroed pregap of the audio track has none either
+    fs::remove_file(test_cue).expect("Failed to remove cue sheet");
+    let output = Command::new("cargo")
+        .args(["run", "--", "verify", test_bin, "-q"])
+        .output()
+        .expect("Failed to execute iso2raw verify");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+    assert!(
+        stdout.contains("Skipped 153 sectors without a sync pattern"),
+        "{stdout}"
+    );
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_pcm);
+    let _ = fs::remove_file(test_manifest);
+    let _ = fs::remove_file(test_bin);
+}
+
+#[test]
+fn test_cue_sheet_generation() {
+    let test_iso = "test_cue.iso";
+    let test_bin = "test_cue.bin";
+    let test_cue = "test_cue.cue";
+
+    fs::write(test_iso, vec![0u8; 2048 * 2]).expect("Failed to create test ISO");
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            test_iso,
+            "-o",
+            test_bin,
+            "-q",
+            "--mode",
+            "mode2-form1",
+        ])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(output.status.success(), "iso2raw failed");
+
+    let cue = fs::read_to_string(test_cue).expect("Failed to read cue sheet");
+    assert_eq!(
+        cue,
+        "FILE \"test_cue.bin\" BINARY\r\n  TRACK 01 MODE2/2352\r\n    INDEX 01 00:00:00\r\n"
+    );
+
+    // Opting out leaves no cue sheet behind
+    let _ = fs::remove_file(test_cue);
+    let output = Command::new("cargo")
+        .args(["run", "--", test_iso, "-o", test_bin, "-q", "--no-cue"])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(output.status.success(), "iso2raw failed");
+    assert!(!Path::new(test_cue).exists());
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_bin);
+}
+
+#[test]
+fn test_subchannel_output() {
+    let test_iso = "test_sub.iso";
+    let test_bin = "test_sub.bin";
+    let test_sub = "test_sub.sub";
+    let test_cue = "test_sub.cue";
+
+    fs::write(test_iso, vec![0u8; 2048 * 3]).expect("Failed to create test ISO");
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            test_iso,
+            "-o",
+            test_bin,
+            "-q",
+            "--subchannel",
+            "sub",
+        ])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(output.status.success(), "iso2raw failed");
+
+    let sub = fs::read(test_sub).expect("Failed to read subchannel file");
+    assert_eq!(sub.len(), 96 * 3);
+    // Q channel of LBA 2: data track 01, index 01, absolute 00:02:02
+    assert_eq!(
+        &sub[96 * 2 + 12..96 * 2 + 22],
+        &[0x41, 1, 1, 0, 0, 2, 0, 0, 2, 2]
+    );
+    assert!(Path::new(test_cue).exists());
+
+    // Interleaved output makes 2448-byte sectors and no cue sheet
+    let _ = fs::remove_file(test_cue);
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            test_iso,
+            "-o",
+            test_bin,
+            "-q",
+            "--subchannel",
+            "interleaved",
+        ])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(output.status.success(), "iso2raw failed");
+    assert_eq!(fs::metadata(test_bin).unwrap().len(), 2448 * 3);
+    assert!(!Path::new(test_cue).exists());
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_bin);
+    let _ = fs::remove_file(test_sub);
+}
+
+#[test]
+fn test_output_named_like_companion_file() {
+    let test_iso = "test_clobber.iso";
+    let test_cue = "test_clobber.cue";
+    let test_sub = "test_clobber.SUB";
+    let test_md5 = "test_clobber.md5";
+
+    fs::write(test_iso, vec![0u8; 2048 * 2]).expect("Failed to create test ISO");
+
+    // The cue sheet, .sub and checksum files would each replace the image
+    let cases: [(&str, &[&str]); 3] = [
+        (test_cue, &[]),
+        (test_sub, &["--subchannel", "sub"]),
+        (test_md5, &["--sidecar", "md5"]),
+    ];
+    for (output_path, options) in cases {
+        let output = Command::new("cargo")
+            .args(["run", "--", test_iso, "-o", output_path, "-q"])
+            .args(options)
+            .output()
+            .expect("Failed to execute iso2raw");
+        assert!(!output.status.success(), "{output_path} was accepted");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("would overwrite the output"), "{stderr}");
+        assert!(!Path::new(output_path).exists());
+    }
+
+    // Without a cue sheet, a .cue output holds the image
+    let output = Command::new("cargo")
+        .args(["run", "--", test_iso, "-o", test_cue, "-q", "--no-cue"])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(output.status.success(), "iso2raw failed");
+    assert_eq!(fs::metadata(test_cue).unwrap().len(), 2352 * 2);
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_cue);
+}
+
+#[test]
+fn test_form2_options_need_form2_mode() {
+    let test_iso = "test_form2_options.iso";
+    let test_raw = "test_form2_options.bin";
+
+    fs::write(test_iso, vec![0u8; 2048 * 2]).expect("Failed to create test ISO");
+
+    for options in [&["--payload-size", "2336"][..], &["--no-edc"]] {
+        let output = Command::new("cargo")
+            .args([
+                "run", "--", test_iso, "-o", test_raw, "-q", "--mode", "mode1",
+            ])
+            .args(options)
+            .output()
+            .expect("Failed to execute iso2raw");
+        assert!(!output.status.success(), "{options:?} was accepted");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains("only applies to --mode mode2-form2"),
+            "{stderr}"
+        );
+        assert!(!Path::new(test_raw).exists());
+    }
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+}
+
+#[test]
+fn test_info_reads_primary_volume_descriptor() {
+    let test_iso = "test_info.iso";
+
+    // 20 sectors with a PVD at sector 16 and a terminator at 17
+    let mut iso = vec![0u8; 2048 * 20];
+    let pvd = &mut iso[2048 * 16..2048 * 17];
+    pvd[0] = 1;
+    pvd[1..6].copy_from_slice(b"CD001");
+    pvd[40..47].copy_from_slice(b"TESTVOL");
+    pvd[80..84].copy_from_slice(&24u32.to_le_bytes());
+    pvd[128..130].copy_from_slice(&2048u16.to_le_bytes());
+    pvd[813..829].copy_from_slice(b"2001020304050600");
+    iso[2048 * 17] = 255;
+    iso[2048 * 17 + 1..2048 * 17 + 6].copy_from_slice(b"CD001");
+    fs::write(test_iso, &iso).expect("Failed to create test ISO");
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "info", test_iso])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(output.status.success(), "iso2raw info failed");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Volume ID: TESTVOL"));
+    assert!(stdout.contains("Volume space size: 24 blocks"));
+    assert!(stdout.contains("Created: 2001-02-03 04:05:06 +00:00"));
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("volume space size is 24 sectors but the image holds 20"));
+
+    // A blob without descriptors is rejected
+    fs::write(test_iso, vec![0u8; 2048 * 20]).expect("Failed to create test ISO");
+    let output = Command::new("cargo")
+        .args(["run", "--", "info", test_iso])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(!output.status.success());
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+}
+
+#[test]
+fn test_raw_input_policies() {
+    let test_iso = "test_policy.iso";
+    let test_bin = "test_policy.bin";
+    let test_rebin = "test_policy_again.bin";
+
+    // 2048 sectors of 2352 bytes are also a multiple of 2048
+    let iso_data: Vec<u8> = (0..2048 * 2048).map(|i| (i % 253) as u8).collect();
+    fs::write(test_iso, &iso_data).expect("Failed to create test ISO");
+
+    let run = |extra: &[&str]| {
+        Command::new("cargo")
+            .args(["run", "--"])
+            .args(extra)
+            .output()
+            .expect("Failed to execute iso2raw")
+    };
+
+    let output = run(&[test_iso, "-o", test_bin, "-q", "--no-cue"]);
+    assert!(output.status.success(), "iso2raw failed");
+    let raw_data = fs::read(test_bin).expect("Failed to read output");
+
+    // Converting the BIN again is refused by default
+    let output = run(&[test_bin, "-o", test_rebin, "-q", "--no-cue"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already a 2352-byte RAW image"));
+
+    for policy in ["passthrough", "reencode"] {
+        let output = run(&[
+            test_bin,
+            "-o",
+            test_rebin,
+            "-q",
+            "--no-cue",
+            "--raw-input",
+            policy,
+        ]);
+        assert!(
+            output.status.success(),
+            "iso2raw --raw-input {policy} failed"
+        );
+        assert_eq!(fs::read(test_rebin).unwrap(), raw_data);
+    }
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_bin);
+    let _ = fs::remove_file(test_rebin);
+}
+
+#[test]
+fn test_streaming_stdin_to_stdout() {
+    let test_iso = "test_stream.iso";
+    let test_bin = "test_stream.bin";
+
+    // Several batches worth of sectors
+    let iso_data: Vec<u8> = (0..2048 * 1500).map(|i| (i % 251) as u8).collect();
+    fs::write(test_iso, &iso_data).expect("Failed to create test ISO");
+
+    let output = Command::new("cargo")
+        .args(["run", "--", test_iso, "-o", test_bin, "-q", "--no-cue"])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(output.status.success(), "iso2raw failed");
+
+    let mut child = Command::new("cargo")
+        .args(["run", "--", "-", "-q"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute iso2raw");
+
+    // Feed stdin from another thread so a full stdout pipe cannot block us
+    let mut stdin = child.stdin.take().unwrap();
+    let feeder = std::thread::spawn(move || stdin.write_all(&iso_data));
+
+    let output = child
+        .wait_with_output()
+        .expect("Failed to wait for iso2raw");
+    feeder.join().unwrap().expect("Failed to write stdin");
+    assert!(output.status.success(), "iso2raw - failed");
+
+    // Status messages stay out of the image on stdout
+    assert_eq!(output.stdout, fs::read(test_bin).unwrap());
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_bin);
+}
+
+#[test]
+fn test_json_progress() {
+    let test_iso = "test_json_progress.iso";
+    let test_bin = "test_json_progress.bin";
+
+    let iso_data: Vec<u8> = (0..300 * 2048).map(|i| (i % 251) as u8).collect();
+    fs::write(test_iso, &iso_data).expect("Failed to create test ISO");
+
+    let output = Command::new("cargo")
+        .args(["run", "--", test_iso, "-o", test_bin, "--no-cue"])
+        .args(["--progress", "json"])
+        .output()
+        .expect("Failed to execute iso2raw");
+
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_bin);
+
+    assert!(output.status.success(), "iso2raw --progress json failed");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let events: Vec<&str> = stderr
+        .lines()
+        .filter(|line| line.starts_with("{\"event\""))
+        .collect();
+
+    assert_eq!(events[0], r#"{"event":"started","total_sectors":300}"#);
+    assert!(events
+        .iter()
+        .any(|line| line.contains(r#""event":"warning""#)));
+    assert!(events.last().unwrap().starts_with(
+        r#"{"event":"finished","sectors_done":300,"total_sectors":300,"bytes_written":705600,"#
+    ));
+}
+
+#[test]
+fn test_ecm_round_trip() {
+    let test_iso = "test_ecm.iso";
+    let test_bin = "test_ecm.bin";
+    let test_ecm = "test_ecm.bin.ecm";
+    let restored_bin = "test_ecm_restored.bin";
+
+    let iso_data: Vec<u8> = (0..2048 * 50).map(|i| (i * 31 % 251) as u8).collect();
+    fs::write(test_iso, &iso_data).expect("Failed to create test ISO");
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            test_iso,
+            "-o",
+            test_bin,
+            "-q",
+            "--mode",
+            "mode2-form1",
+            "--no-cue",
+        ])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(output.status.success(), "iso2raw failed");
+
+    // Append a partial sector, which ECM keeps as literal bytes
+    let mut bin_data = fs::read(test_bin).expect("Failed to read output file");
+    bin_data.extend_from_slice(b"trailing bytes");
+    fs::write(test_bin, &bin_data).expect("Failed to extend image");
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "ecm", test_bin, "-q"])
+        .output()
+        .expect("Failed to execute iso2raw ecm");
+    assert!(output.status.success(), "iso2raw ecm failed");
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Mode 2 Form 1: 50 sectors"), "{stdout}");
+
+    let ecm_data = fs::read(test_ecm).expect("Failed to read ECM file");
+    assert_eq!(&ecm_data[..4], b"ECM\0");
+    assert!(ecm_data.len() < bin_data.len() * 9 / 10);
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "unecm", test_ecm, "-o", restored_bin, "-q"])
+        .output()
+        .expect("Failed to execute iso2raw unecm");
+    assert!(output.status.success(), "iso2raw unecm failed");
+
+    let restored = fs::read(restored_bin).expect("Failed to read restored image");
+    assert!(
+        restored == bin_data,
+        "Restored image differs from the original"
+    );
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_bin);
+    let _ = fs::remove_file(test_ecm);
+    let _ = fs::remove_file(restored_bin);
+}
+
+#[test]
+fn test_hash_sidecars() {
+    let test_iso = "test_hash.iso";
+    let test_bin = "test_hash.bin";
+    let sidecars = ["test_hash.sfv", "test_hash.md5", "test_hash.sha1"];
+
+    let iso_data: Vec<u8> = (0..2048 * 20).map(|i| (i * 7 % 253) as u8).collect();
+    fs::write(test_iso, &iso_data).expect("Failed to create test ISO");
+
+    let output = Command::new("cargo")
+        .args([
+            "run",
+            "--",
+            test_iso,
+            "-o",
+            test_bin,
+            "-q",
+            "--no-cue",
+            "--sidecar",
+            "sfv",
+            "--sidecar",
+            "md5",
+            "--sidecar",
+            "sha1",
+        ])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(output.status.success(), "iso2raw failed");
+
+    let mut hasher = iso2raw::hash::ImageHasher::new();
+    hasher.update(&fs::read(test_bin).expect("Failed to read output file"));
+    let hashes = hasher.finish();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(&format!("CRC32: {}", hashes.crc32_hex())),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(&format!("MD5: {}", hashes.md5_hex())),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(&format!("SHA-1: {}", hashes.sha1_hex())),
+        "{stdout}"
+    );
+
+    let sfv = fs::read_to_string(sidecars[0]).expect("Failed to read SFV file");
+    assert!(sfv.ends_with(&format!(
+        "test_hash.bin {}\n",
+        hashes.crc32_hex().to_uppercase()
+    )));
+    let md5 = fs::read_to_string(sidecars[1]).expect("Failed to read MD5 file");
+    assert_eq!(md5, format!("{}  test_hash.bin\n", hashes.md5_hex()));
+    let sha1 = fs::read_to_string(sidecars[2]).expect("Failed to read SHA-1 file");
+    assert_eq!(sha1, format!("{}  test_hash.bin\n", hashes.sha1_hex()));
+
+    // Hashing is opt-in otherwise
+    let convert = |extra: &[&str]| {
+        Command::new("cargo")
+            .args(["run", "--", test_iso, "-o", test_bin, "-q", "--no-cue"])
+            .args(extra)
+            .output()
+            .expect("Failed to execute iso2raw")
+    };
+    let stdout = String::from_utf8_lossy(&convert(&[]).stdout).into_owned();
+    assert!(!stdout.contains("CRC32:"), "{stdout}");
+    let stdout = String::from_utf8_lossy(&convert(&["--hash"]).stdout).into_owned();
+    assert!(
+        stdout.contains(&format!("SHA-1: {}", hashes.sha1_hex())),
+        "{stdout}"
+    );
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_bin);
+    for sidecar in sidecars {
+        let _ = fs::remove_file(sidecar);
+    }
+}
+
+#[test]
+fn test_dat_matching() {
+    let test_iso = "test_dat.iso";
+    let test_bin = "test_dat.bin";
+    let test_cue = "test_dat.cue";
+    let test_dat = "test_dat.dat";
+    let redump_bin = "Test Disc (World).bin";
+    let redump_cue = "Test Disc (World).cue";
+
+    let iso_data: Vec<u8> = (0..2048 * 20).map(|i| (i * 13 % 241) as u8).collect();
+    fs::write(test_iso, &iso_data).expect("Failed to create test ISO");
+
+    // Entry in the form Redump publishes it. The cue hashes are those of
+    // the CRLF cue sheet Redump keeps for a single Mode 1 track:
+    // FILE "Test Disc (World).bin" BINARY\r\n  TRACK 01 MODE1/2352\r\n
+    //     INDEX 01 00:00:00\r\n
+    let dat = r#"<?xml version="1.0"?>
+<!DOCTYPE datafile PUBLIC "-//Logiqx//DTD ROM Management Datafile//EN" "http://www.logiqx.com/Dats/datafile.dtd">
+<datafile>
+	<header>
+		<name>Test System</name>
+	</header>
+	<game name="Test Disc (World)">
+		<category>Games</category>
+		<description>Test Disc (World)</description>
+		<rom name="Test Disc (World).cue" size="83" crc="cb0ca584" md5="a3feab0d06bd732bbe6f616d0856f541" sha1="1480db04e219d44d8224c1d528dd3194644592b3"/>
+		<rom name="Test Disc (World).bin" size="47040" crc="77a47a93" md5="9645d7dfc6f3a6b7cc7bc29f23e0d909" sha1="b9a27e0dac5cb32430ae7d3d7aa8957776e3c008"/>
+	</game>
+</datafile>
+"#;
+    fs::write(test_dat, dat).expect("Failed to write DAT file");
+
+    let convert = |output_path: &str| {
+        Command::new("cargo")
+            .args([
+                "run",
+                "--",
+                test_iso,
+                "-o",
+                output_path,
+                "-q",
+                "--dat",
+                test_dat,
+            ])
+            .output()
+            .expect("Failed to execute iso2raw")
+    };
+
+    let output = convert(redump_bin);
+    assert!(output.status.success(), "iso2raw --dat failed");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("DAT match: Test Disc (World) (Test Disc (World).bin, Test System)"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("Cue sheet matches Test Disc (World).cue"),
+        "{stdout}"
+    );
+
+    // The cue sheet names its BIN, so only the image matches under another name
+    let output = convert(test_bin);
+    assert!(output.status.success(), "iso2raw --dat failed");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("DAT match: Test Disc (World)"), "{stdout}");
+    assert!(
+        stdout.contains("name the output Test Disc (World).bin"),
+        "{stdout}"
+    );
+
+    // A different image matches nothing and fails the command
+    fs::write(test_iso, vec![0u8; 2048 * 20]).expect("Failed to create test ISO");
+    let output = convert(test_bin);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("does not match any entry in Test System"),
+        "{stderr}"
+    );
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_bin);
+    let _ = fs::remove_file(test_cue);
+    let _ = fs::remove_file(redump_bin);
+    let _ = fs::remove_file(redump_cue);
+    let _ = fs::remove_file(test_dat);
+}
+
+#[test]
+fn test_batch_conversion() {
+    let input_dir = "test_batch_in";
+    let output_dir = "test_batch_out";
+    let _ = fs::remove_dir_all(input_dir);
+    let _ = fs::remove_dir_all(output_dir);
+    fs::create_dir_all(format!("{input_dir}/disc2")).expect("Failed to create input tree");
+
+    let iso_data: Vec<u8> = (0..2048 * 10).map(|i| (i % 256) as u8).collect();
+    fs::write(format!("{input_dir}/disc1.iso"), &iso_data).expect("Failed to create test ISO");
+    fs::write(format!("{input_dir}/disc2/track.iso"), &iso_data)
+        .expect("Failed to create test ISO");
+    fs::write(format!("{input_dir}/notes.txt"), "not an image").expect("Failed to create file");
+
+    // A lone RAW sector, which is refused by default
+    let mut raw_sector = vec![0u8; 2352];
+    raw_sector[1..11].fill(0xFF);
+    raw_sector[12..16].copy_from_slice(&[0x00, 0x02, 0x00, 0x01]);
+    fs::write(format!("{input_dir}/bad.iso"), &raw_sector).expect("Failed to create test ISO");
+
+    let batch = |extra: &[&str]| {
+        Command::new("cargo")
+            .args(["run", "--", input_dir, "-d", output_dir, "-q"])
+            .args(extra)
+            .output()
+            .expect("Failed to execute iso2raw")
+    };
+
+    // Files are converted in path order, so the failure stops the batch first
+    let output = batch(&[]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("FAILED   test_batch_in/bad.iso"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("SKIPPED  test_batch_in/disc1.iso"),
+        "{stdout}"
+    );
+    assert!(!Path::new(&format!("{output_dir}/disc1.bin")).exists());
+
+    let output = batch(&["--continue-on-error"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Converted 2 of 3 files"), "{stdout}");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1 of 3 files failed to convert"));
+
+    for bin in ["disc1.bin", "disc2/track.bin"] {
+        let raw = fs::read(format!("{output_dir}/{bin}")).expect("Failed to read output file");
+        assert_eq!(raw.len(), 10 * 2352, "{bin}");
+    }
+    assert!(Path::new(&format!("{output_dir}/disc2/track.cue")).exists());
+
+    // Clean up
+    let _ = fs::remove_dir_all(input_dir);
+    let _ = fs::remove_dir_all(output_dir);
+}
+
+#[test]
+fn test_json_report() {
+    let test_iso = "test_report.iso";
+    let test_bin = "test_report.bin";
+
+    fs::write(test_iso, vec![0x33u8; 2048 * 8]).expect("Failed to create test ISO");
+
+    let output = Command::new("cargo")
+        .args([
+            "run", "--", test_iso, "-o", test_bin, "-q", "--hash", "--format", "json",
+        ])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(output.status.success(), "iso2raw failed");
+
+    // The report replaces the text output
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let report = stdout.trim();
+    assert_eq!(report.lines().count(), 1, "{stdout}");
+    assert!(
+        report.starts_with(r#"{"command":"convert","input":"test_report.iso","output":"test_report.bin","sector_format":"mode1","detected_layout":"iso","track_mode":"MODE1/2352","total_sectors":8,"#),
+        "{report}"
+    );
+    assert!(
+        report.contains(r#""hashes":{"size":18816,"crc32":""#),
+        "{report}"
+    );
+    assert!(
+        report.contains(r#""cue_sheet":"test_report.cue""#),
+        "{report}"
+    );
+    assert!(
+        report.ends_with(r#""success":true,"error":null}"#),
+        "{report}"
+    );
+
+    // With the image on stdout the report goes to stderr, and warnings are
+    // only in the report
+    let output = Command::new("cargo")
+        .args(["run", "--", test_iso, "-o", "-", "-q", "--format", "json"])
+        .output()
+        .expect("Failed to execute iso2raw");
+    assert!(output.status.success(), "iso2raw failed");
+    assert_eq!(output.stdout.len(), 2352 * 8);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("Warning:"), "{stderr}");
+    assert!(
+        stderr.contains(r#""warnings":[{"sector":null,"message":"No primary volume descriptor"#),
+        "{stderr}"
+    );
+
+    // A failing command still reports, then exits with an error
+    let mut bin_data = fs::read(test_bin).expect("Failed to read output file");
+    bin_data[2352 * 5 + 100] ^= 0xFF;
+    fs::write(test_bin, &bin_data).expect("Failed to write damaged image");
+
+    let output = Command::new("cargo")
+        .args(["run", "--", "verify", test_bin, "-q", "--format", "json"])
+        .output()
+        .expect("Failed to execute iso2raw verify");
+    assert!(!output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(r#""failed_sectors":1,"failures":[{"first_lba":5,"last_lba":5,"failures":["EDC","P parity","Q parity"]}]"#),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(r#""success":false,"error":"1 of 8 sectors failed verification"}"#),
+        "{stdout}"
+    );
+    // ...without printing the error a second time
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("Error:"), "{stderr}");
+
+    // Clean up
+    let _ = fs::remove_file(test_iso);
+    let _ = fs::remove_file(test_bin);
+    let _ = fs::remove_file(test_bin.replace(".bin", ".cue"));
 }
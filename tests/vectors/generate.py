@@ -0,0 +1,139 @@
+#!/usr/bin/env python3
+"""Regenerates sectors.txt, the golden EDC/ECC vectors for tests/golden_vectors.rs.
+
+The sectors are built with an independent port of the EDC/ECC generator from
+Neill Corlett's ECM tools (ecm.c), which derives its tables differently from
+the cdrdao lec.cc code that src/edc_ecc is based on. Run from this directory:
+
+    python3 generate.py > sectors.txt
+"""
+
+# ECM lookup tables
+ECC_F = [((i << 1) ^ (0x11D if i & 0x80 else 0)) & 0xFF for i in range(256)]
+ECC_B = [0] * 256
+for i in range(256):
+    ECC_B[i ^ ECC_F[i]] = i
+
+EDC_LUT = []
+for i in range(256):
+    edc = i
+    for _ in range(8):
+        edc = (edc >> 1) ^ (0xD8018001 if edc & 1 else 0)
+    EDC_LUT.append(edc)
+
+
+def edc(data):
+    crc = 0
+    for byte in data:
+        crc = (crc >> 8) ^ EDC_LUT[(crc ^ byte) & 0xFF]
+    return crc
+
+
+def ecc_block(src, major_count, minor_count, major_mult, minor_inc):
+    size = major_count * minor_count
+    dest = [0] * (2 * major_count)
+    for major in range(major_count):
+        index = (major >> 1) * major_mult + (major & 1)
+        ecc_a = ecc_b = 0
+        for _ in range(minor_count):
+            temp = src[index]
+            index += minor_inc
+            if index >= size:
+                index -= size
+            ecc_a ^= temp
+            ecc_b ^= temp
+            ecc_a = ECC_F[ecc_a]
+        ecc_a = ECC_B[ECC_F[ecc_a] ^ ecc_b]
+        dest[major] = ecc_a
+        dest[major + major_count] = ecc_a ^ ecc_b
+    return dest
+
+
+def ecc(sector, zero_address):
+    sector = list(sector)
+    if zero_address:
+        sector[12:16] = [0, 0, 0, 0]
+    sector[0x81C:0x8C8] = ecc_block(sector[0xC:], 86, 24, 2, 86)
+    sector[0x8C8:0x930] = ecc_block(sector[0xC:], 52, 43, 86, 88)
+    return sector[0x81C:0x8C8], sector[0x8C8:0x930]
+
+
+def bcd(value):
+    return ((value // 10) << 4) | (value % 10)
+
+
+def header(lba, mode):
+    frames = lba + 150
+    return [bcd(frames // 75 // 60), bcd(frames // 75 % 60), bcd(frames % 75), mode]
+
+
+def pattern(name, length):
+    if name == "zero":
+        return [0] * length
+    if name == "ramp":
+        return [i & 0xFF for i in range(length)]
+    if name.startswith("noise"):
+        # xorshift32, as in tests/golden_vectors.rs
+        state = int(name[len("noise"):])
+        out = []
+        for _ in range(length):
+            state ^= (state << 13) & 0xFFFFFFFF
+            state ^= state >> 17
+            state ^= (state << 5) & 0xFFFFFFFF
+            out.append(state & 0xFF)
+        return out
+    raise ValueError(name)
+
+
+SYNC = [0x00] + [0xFF] * 10 + [0x00]
+
+VECTORS = [
+    ("mode1", 0, None, "zero"),
+    ("mode1", 16, None, "ramp"),
+    ("mode1", 359849, None, "noise1"),
+    ("mode2form1", 0, [0, 0, 0x08, 0], "zero"),
+    ("mode2form1", 1234, [1, 0, 0x89, 0], "noise2"),
+    ("mode2form2", 0, [0, 0, 0x20, 0], "ramp"),
+    ("mode2form2", 5000, [1, 1, 0x64, 0x01], "noise3"),
+]
+
+
+def main():
+    print("# Generated by generate.py; see its docstring for provenance.")
+    for mode, lba, subheader, data_name in VECTORS:
+        sector = [0] * 2352
+        sector[0:12] = SYNC
+        if mode == "mode1":
+            sector[12:16] = header(lba, 1)
+            sector[16:2064] = pattern(data_name, 2048)
+            crc = edc(sector[0:2064])
+            sector[2064:2068] = list(crc.to_bytes(4, "little"))
+            p, q = ecc(sector, False)
+        elif mode == "mode2form1":
+            sector[12:16] = header(lba, 2)
+            sector[16:24] = subheader * 2
+            sector[24:2072] = pattern(data_name, 2048)
+            crc = edc(sector[16:2072])
+            sector[2072:2076] = list(crc.to_bytes(4, "little"))
+            p, q = ecc(sector, True)
+        else:
+            sector[12:16] = header(lba, 2)
+            sector[16:24] = subheader * 2
+            sector[24:2348] = pattern(data_name, 2324)
+            crc = edc(sector[16:2348])
+            p = q = None
+
+        subheader_hex = bytes(subheader).hex() if subheader else "-"
+        print()
+        print(f"mode = {mode}")
+        print(f"lba = {lba}")
+        print(f"subheader = {subheader_hex}")
+        print(f"data = {data_name}")
+        print(f"edc = {crc.to_bytes(4, 'little').hex()}")
+        if p is not None:
+            print(f"p = {bytes(p).hex()}")
+            print(f"q = {bytes(q).hex()}")
+
+
+if __name__ == "__main__":
+    main()